@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+
+use crate::history::{History, RunQuery};
+use crate::keystats::{KeyStat, KeyStats};
+
+/// Number of most recent runs whose key results adaptive practice looks at.
+pub const RECENT_RUNS: usize = 50;
+/// Keys and bigrams typed fewer times than this across those runs are not judged.
+pub const MIN_ATTEMPTS: u32 = 5;
+/// Largest `adaptive_strength` accepted; beyond it weights only overflow.
+pub const MAX_STRENGTH: f64 = 10.0;
+
+/// How much each key and bigram should be over-represented, from recorded results.
+///
+/// A weight is how much worse than the user's average a key is, combining its miss
+/// rate and its mean latency, each relative to the average over all keys. Keys at or
+/// better than average weigh nothing, so a text's [`score`](Self::score) only rises
+/// with the characters the user struggles with.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AdaptiveWeights {
+    pub keys: BTreeMap<char, f64>,
+    pub bigrams: BTreeMap<String, f64>,
+}
+
+impl AdaptiveWeights {
+    /// Weights for every key and bigram with at least `min_attempts` attempts.
+    /// Whitespace is left out: every text has plenty of it.
+    pub fn from_stats(stats: &KeyStats, min_attempts: u32) -> Self {
+        Self {
+            keys: relative_weakness(
+                stats.keys.iter().filter(|(c, _)| !c.is_whitespace()).map(|(c, s)| (*c, s)),
+                min_attempts,
+            ),
+            bigrams: relative_weakness(
+                stats
+                    .bigrams
+                    .iter()
+                    .filter(|(b, _)| !b.contains(char::is_whitespace))
+                    .map(|(b, s)| (b.clone(), s)),
+                min_attempts,
+            ),
+        }
+    }
+
+    /// Weights from the key results of the last [`RECENT_RUNS`] runs.
+    pub fn from_history(history: &History) -> Self {
+        Self::from_stats(&history.key_stats(&RunQuery::new().limit(RECENT_RUNS)), MIN_ATTEMPTS)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty() && self.bigrams.is_empty()
+    }
+
+    /// Average weight per character of `text`, counting both its characters and
+    /// its bigrams. Zero for a text with none of the weak keys.
+    pub fn score(&self, text: &str) -> f64 {
+        let chars: Vec<char> = text.chars().collect();
+        if chars.is_empty() {
+            return 0.0;
+        }
+        let mut total: f64 = chars.iter().filter_map(|c| self.keys.get(c)).sum();
+        let mut pair = String::with_capacity(8);
+        for window in chars.windows(2) {
+            pair.clear();
+            pair.extend(window);
+            total += self.bigrams.get(&pair).copied().unwrap_or(0.0);
+        }
+        total / chars.len() as f64
+    }
+
+    /// The `n` heaviest keys and bigrams, heaviest first, for showing what a run targets.
+    pub fn targets(&self, n: usize) -> Vec<(String, f64)> {
+        let mut targets: Vec<(String, f64)> = self
+            .keys
+            .iter()
+            .map(|(c, w)| (c.to_string(), *w))
+            .chain(self.bigrams.iter().map(|(b, w)| (b.clone(), *w)))
+            .filter(|(_, w)| *w > 0.0)
+            .collect();
+        targets.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        targets.truncate(n);
+        targets
+    }
+
+    /// Selection weight for a text: `(1 + score) ^ strength`, with the strength
+    /// held to `0..=MAX_STRENGTH`. A strength of zero picks uniformly; larger values
+    /// lean harder on weak keys.
+    pub fn selection_weight(&self, text: &str, strength: f64) -> f64 {
+        (1.0 + self.score(text)).powf(strength.clamp(0.0, MAX_STRENGTH))
+    }
+}
+
+// Error rate and latency of each item relative to the mean over all items, as the
+// amount above average (0 for items at or below it).
+fn relative_weakness<'a, K: Ord>(
+    items: impl Iterator<Item = (K, &'a KeyStat)>,
+    min_attempts: u32,
+) -> BTreeMap<K, f64> {
+    let items: Vec<(K, &KeyStat)> = items.filter(|(_, s)| s.attempts() >= min_attempts).collect();
+    if items.is_empty() {
+        return BTreeMap::new();
+    }
+    let error_rate = |s: &KeyStat| s.misses as f64 / s.attempts() as f64;
+    let n = items.len() as f64;
+    let mean_error = items.iter().map(|(_, s)| error_rate(s)).sum::<f64>() / n;
+    let latencies: Vec<f64> = items.iter().filter_map(|(_, s)| s.mean_latency_ms()).collect();
+    let mean_latency = if latencies.is_empty() {
+        0.0
+    } else {
+        latencies.iter().sum::<f64>() / latencies.len() as f64
+    };
+
+    items
+        .into_iter()
+        .map(|(key, stat)| {
+            // A floor on the mean keeps one miss in an otherwise clean history from
+            // looking like an enormous outlier
+            let errors = error_rate(stat) / mean_error.max(0.02) - 1.0;
+            let latency = match stat.mean_latency_ms() {
+                Some(ms) if mean_latency > 0.0 => ms / mean_latency - 1.0,
+                _ => 0.0,
+            };
+            (key, errors.max(0.0) + latency.max(0.0))
+        })
+        .collect()
+}
+
+/// Index picked at random in proportion to `weights`, or `None` if there are no
+/// positive weights.
+pub fn pick_weighted<R: Rng + ?Sized>(weights: &[f64], rng: &mut R) -> Option<usize> {
+    WeightedIndex::new(finite_weights(weights.iter().copied())).ok().map(|dist| dist.sample(rng))
+}
+
+/// `weights` made safe for `WeightedIndex`, which panics on an infinite total:
+/// negative, NaN and infinite weights count as zero, and the rest are scaled so
+/// the largest is 1.
+pub fn finite_weights(weights: impl IntoIterator<Item = f64>) -> Vec<f64> {
+    let mut weights: Vec<f64> = weights
+        .into_iter()
+        .map(|w| if w.is_finite() { w.max(0.0) } else { 0.0 })
+        .collect();
+    let max = weights.iter().copied().fold(0.0, f64::max);
+    if max > 0.0 {
+        weights.iter_mut().for_each(|w| *w /= max);
+    }
+    weights
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn stat(hits: u32, misses: u32, latency_ms: u64) -> KeyStat {
+        let attempts = hits + misses;
+        KeyStat { hits, misses, latency_ms: latency_ms * attempts as u64, timed: attempts }
+    }
+
+    #[test]
+    fn test_weak_keys_weigh_more() {
+        let mut stats = KeyStats::new();
+        stats.keys.insert('a', stat(20, 0, 150));
+        stats.keys.insert('e', stat(20, 0, 150));
+        stats.keys.insert('q', stat(10, 10, 150));
+        stats.keys.insert('z', stat(20, 0, 600));
+        stats.keys.insert('x', stat(1, 1, 900));
+        let weights = AdaptiveWeights::from_stats(&stats, 5);
+
+        assert_eq!(weights.keys[&'a'], 0.0);
+        assert!(weights.keys[&'q'] > 0.0);
+        assert!(weights.keys[&'z'] > 0.0);
+        // Too few attempts to judge
+        assert!(!weights.keys.contains_key(&'x'));
+        assert!(weights.score("quiz") > weights.score("area"));
+        assert_eq!(weights.targets(1)[0].0, "q");
+    }
+
+    #[test]
+    fn test_strength_zero_is_uniform() {
+        let mut weights = AdaptiveWeights::default();
+        weights.keys.insert('q', 3.0);
+        assert_eq!(weights.selection_weight("qqq", 0.0), 1.0);
+        assert!(weights.selection_weight("qqq", 2.0) > weights.selection_weight("aaa", 2.0));
+
+        let mut rng = StdRng::seed_from_u64(7);
+        assert_eq!(pick_weighted(&[0.0, 1.0, 0.0], &mut rng), Some(1));
+        assert_eq!(pick_weighted(&[0.0, 0.0], &mut rng), None);
+    }
+
+    #[test]
+    fn test_huge_strength_stays_finite() {
+        let mut weights = AdaptiveWeights::default();
+        weights.keys.insert('q', 50.0);
+        let weight = weights.selection_weight(&"q".repeat(200), 1e6);
+        assert!(weight.is_finite());
+
+        let mut rng = StdRng::seed_from_u64(7);
+        assert!(pick_weighted(&[weight, f64::MAX, f64::MAX, 1.0], &mut rng).is_some());
+        assert_eq!(pick_weighted(&[f64::INFINITY, f64::NAN, 1.0], &mut rng), Some(2));
+        assert_eq!(pick_weighted(&[f64::INFINITY, f64::NAN], &mut rng), None);
+    }
+}
@@ -0,0 +1,335 @@
+//! Checks a race run from its keystroke log, so results come from what was typed
+//! rather than from what a client claims.
+//!
+//! A run is replayed against the race text and its timing examined. Runs no
+//! person could have typed are rejected; runs that are possible but unusual are
+//! accepted with flags for the other racers to see.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::graphemes;
+use crate::metrics;
+use crate::normalize::Normalization;
+use crate::replay::{KeyAction, Keystroke, KeystrokeLog};
+
+/// Where the line is drawn between human and not.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// Runs faster than this are rejected. The fastest recorded typists sustain
+    /// a little over 200 WPM on short texts.
+    pub max_wpm: f64,
+    /// Runs faster than this are flagged.
+    pub flag_wpm: f64,
+    /// Characters typed this many in a row, each within `paste_gap_ms` of the
+    /// last, count as pasted.
+    pub paste_run: usize,
+    pub paste_gap_ms: u64,
+    /// Gaps between keystrokes shorter than this are too fast to be deliberate.
+    /// A few happen when keys roll over; more than `max_fast_share` of them do not.
+    pub min_interval_ms: u64,
+    pub max_fast_share: f64,
+    /// Runs whose gaps vary less than this, relative to their mean, are flagged
+    /// as machine-regular. People rarely drop below 0.3.
+    pub min_rhythm_variation: f64,
+    /// How much shorter than the server saw it a run's log may say it was, to
+    /// allow for the go and the finish spending time on the network.
+    pub clock_slack_ms: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_wpm: 250.0,
+            flag_wpm: 170.0,
+            paste_run: 5,
+            paste_gap_ms: 5,
+            min_interval_ms: 15,
+            max_fast_share: 0.25,
+            min_rhythm_variation: 0.12,
+            clock_slack_ms: 3000,
+        }
+    }
+}
+
+// Fewer gaps than this say too little about a rhythm to judge it
+const MIN_GAPS_TO_JUDGE: usize = 10;
+
+/// Something unusual about an accepted run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Flag {
+    /// Faster than [`Limits::flag_wpm`].
+    NearSpeedLimit,
+    /// Keystrokes evenly spaced like a script's.
+    UniformRhythm,
+    /// A long text at speed without a single slip or correction.
+    FlawlessAtSpeed,
+}
+
+impl Flag {
+    /// Short description for showing next to a result.
+    pub fn label(self) -> &'static str {
+        match self {
+            Flag::NearSpeedLimit => "near the speed limit",
+            Flag::UniformRhythm => "uniform rhythm",
+            Flag::FlawlessAtSpeed => "flawless at speed",
+        }
+    }
+}
+
+/// Why a run was not accepted.
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum Rejection {
+    #[error("the keystrokes do not type the race text")]
+    WrongText,
+    #[error("keystroke times go backwards")]
+    OutOfOrder,
+    #[error("text was pasted at {at_ms} ms")]
+    Pasted { at_ms: u64 },
+    #[error("{wpm:.0} wpm is beyond human speed")]
+    TooFast { wpm: f64 },
+    #[error("{share:.0}% of keystrokes came too close together to be typed")]
+    InhumanIntervals { share: f64 },
+    #[error("the keystrokes take {logged_ms} ms but the race had run {elapsed_ms} ms")]
+    ClockMismatch { logged_ms: u64, elapsed_ms: u64 },
+}
+
+/// Results of an accepted run, worked out from its keystrokes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verdict {
+    pub wpm: f64,
+    /// Seconds from the start of the race to the last keystroke.
+    pub time: f64,
+    /// Share of typed characters that were right, as a percentage.
+    pub accuracy: f64,
+    pub flags: Vec<Flag>,
+}
+
+/// Replays `events` against `target` and checks them against `limits`.
+pub fn check_run(target: &str, events: &[Keystroke], limits: &Limits) -> Result<Verdict, Rejection> {
+    if events.windows(2).any(|pair| pair[1].at_ms < pair[0].at_ms) {
+        return Err(Rejection::OutOfOrder);
+    }
+    // Clients may type either encoding of an accented letter
+    let unicode = Normalization { unicode: true, ..Normalization::EXACT };
+    let target = &unicode.canonical(target);
+    let log = KeystrokeLog { events: events.to_vec(), ..KeystrokeLog::default() };
+    if unicode.canonical(&log.input_after(events.len())).trim() != target.trim() || target.trim().is_empty() {
+        return Err(Rejection::WrongText);
+    }
+    if let Some(at_ms) = pasted_at(events, limits) {
+        return Err(Rejection::Pasted { at_ms });
+    }
+
+    let time = log.duration().as_secs_f64();
+    // A whole run inside one millisecond would otherwise count as standing still
+    let wpm = metrics::net_wpm(graphemes::count(target.trim()), log.duration().max(Duration::from_millis(1)));
+    if wpm > limits.max_wpm {
+        return Err(Rejection::TooFast { wpm });
+    }
+
+    let gaps = insert_gaps(events);
+    if gaps.len() >= MIN_GAPS_TO_JUDGE {
+        let fast = gaps.iter().filter(|&&gap| gap < limits.min_interval_ms).count();
+        let share = fast as f64 / gaps.len() as f64;
+        if share > limits.max_fast_share {
+            return Err(Rejection::InhumanIntervals { share: share * 100.0 });
+        }
+    }
+
+    let (typed, wrong) = typing_errors(target, events);
+    let accuracy = if typed == 0 { 100.0 } else { (typed - wrong) as f64 / typed as f64 * 100.0 };
+    let corrected = events
+        .iter()
+        .any(|e| matches!(e.action, KeyAction::Backspace { .. } | KeyAction::DeleteWord { .. }));
+
+    let mut flags = Vec::new();
+    if wpm > limits.flag_wpm {
+        flags.push(Flag::NearSpeedLimit);
+    }
+    if gaps.len() >= MIN_GAPS_TO_JUDGE && variation(&gaps) < limits.min_rhythm_variation {
+        flags.push(Flag::UniformRhythm);
+    }
+    if wrong == 0 && !corrected && typed >= 100 && wpm > 120.0 {
+        flags.push(Flag::FlawlessAtSpeed);
+    }
+    Ok(Verdict { wpm, time, accuracy, flags })
+}
+
+/// Checks a log against the time the server saw pass between the go and the
+/// finish. The log's own timestamps come from the client, so a run squeezed into
+/// less time than it really took would otherwise look faster than it was.
+pub fn check_clock(events: &[Keystroke], elapsed: Duration, limits: &Limits) -> Result<(), Rejection> {
+    let logged_ms = events.last().map(|e| e.at_ms).unwrap_or(0);
+    let elapsed_ms = elapsed.as_millis() as u64;
+    if logged_ms.saturating_add(limits.clock_slack_ms) < elapsed_ms {
+        return Err(Rejection::ClockMismatch { logged_ms, elapsed_ms });
+    }
+    Ok(())
+}
+
+// Start of the first run of inserts that came in faster than anyone types
+fn pasted_at(events: &[Keystroke], limits: &Limits) -> Option<u64> {
+    let mut run_start = 0;
+    let mut run = 0;
+    let mut last: Option<u64> = None;
+    for event in events {
+        // Auto-indent fills in a line's indentation at once; it was never typed
+        if matches!(event.action, KeyAction::Indent { .. }) {
+            continue;
+        }
+        if !matches!(event.action, KeyAction::Insert { .. }) {
+            run = 0;
+            last = None;
+            continue;
+        }
+        match last {
+            Some(at) if event.at_ms - at <= limits.paste_gap_ms => run += 1,
+            _ => {
+                run = 1;
+                run_start = event.at_ms;
+            }
+        }
+        if run >= limits.paste_run {
+            return Some(run_start);
+        }
+        last = Some(event.at_ms);
+    }
+    None
+}
+
+// Milliseconds between consecutive inserts
+fn insert_gaps(events: &[Keystroke]) -> Vec<u64> {
+    let inserts: Vec<u64> = events
+        .iter()
+        .filter(|e| matches!(e.action, KeyAction::Insert { .. }))
+        .map(|e| e.at_ms)
+        .collect();
+    inserts.windows(2).map(|pair| pair[1] - pair[0]).collect()
+}
+
+// Characters typed, and how many of them were wrong for their position
+fn typing_errors(target: &str, events: &[Keystroke]) -> (usize, usize) {
+    let target: Vec<char> = target.chars().collect();
+    let (mut position, mut typed, mut wrong) = (0usize, 0, 0);
+    for event in events {
+        match event.action {
+            KeyAction::Insert { ch } => {
+                typed += 1;
+                if target.get(position) != Some(&ch) {
+                    wrong += 1;
+                }
+                position += 1;
+            }
+            KeyAction::Indent { .. } => position += 1,
+            KeyAction::Backspace { removed } | KeyAction::DeleteWord { removed } => {
+                position = position.saturating_sub(removed);
+            }
+        }
+    }
+    (typed, wrong)
+}
+
+// Coefficient of variation: standard deviation over mean
+fn variation(gaps: &[u64]) -> f64 {
+    let n = gaps.len() as f64;
+    let mean = gaps.iter().sum::<u64>() as f64 / n;
+    if mean == 0.0 {
+        return 0.0;
+    }
+    let var = gaps.iter().map(|&g| (g as f64 - mean).powi(2)).sum::<f64>() / n;
+    var.sqrt() / mean
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "the quick brown fox jumps over the lazy dog";
+
+    // Types `text` with gaps cycling through `gaps` milliseconds
+    fn typed(text: &str, gaps: &[u64]) -> Vec<Keystroke> {
+        let mut at = 300;
+        text.chars()
+            .enumerate()
+            .map(|(i, ch)| {
+                at += gaps[i % gaps.len()];
+                Keystroke { at_ms: at, action: KeyAction::Insert { ch } }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_human_run_is_accepted_with_recomputed_results() {
+        let mut events = typed("the quick brown fix", &[150, 90, 210, 120]);
+        // A slip, put right
+        let at = events.last().unwrap().at_ms;
+        events.push(Keystroke { at_ms: at + 300, action: KeyAction::Backspace { removed: 1 } });
+        events.push(Keystroke { at_ms: at + 300, action: KeyAction::Backspace { removed: 1 } });
+        let at = at + 500;
+        events.extend(typed("ox jumps over the lazy dog", &[150, 90, 210, 120]).into_iter().map(|mut e| {
+            e.at_ms += at;
+            e
+        }));
+
+        let verdict = check_run(TEXT, &events, &Limits::default()).unwrap();
+        assert!(verdict.flags.is_empty(), "{:?}", verdict.flags);
+        let time = events.last().unwrap().at_ms as f64 / 1000.0;
+        assert!((verdict.time - time).abs() < 1e-9);
+        assert!((verdict.wpm - TEXT.len() as f64 / 5.0 / (time / 60.0)).abs() < 1e-9);
+        assert!(verdict.accuracy < 100.0);
+    }
+
+    #[test]
+    fn test_impossible_runs_are_rejected() {
+        let limits = Limits::default();
+        let human = typed(TEXT, &[150, 90, 210, 120]);
+        assert_eq!(check_run("another text", &human, &limits), Err(Rejection::WrongText));
+
+        let mut backwards = human.clone();
+        backwards.swap(3, 4);
+        assert_eq!(check_run(TEXT, &backwards, &limits), Err(Rejection::OutOfOrder));
+
+        // The second half arrives all at once
+        let mut pasted = human.clone();
+        let at = pasted[20].at_ms;
+        for event in &mut pasted[20..] {
+            event.at_ms = at;
+        }
+        assert_eq!(check_run(TEXT, &pasted, &limits), Err(Rejection::Pasted { at_ms: at }));
+
+        assert!(matches!(check_run(TEXT, &typed(TEXT, &[20, 30]), &limits), Err(Rejection::TooFast { .. })));
+
+        // Plausible overall, but half the keys are impossibly close together
+        let bursts = typed(TEXT, &[4, 400]);
+        assert!(matches!(check_run(TEXT, &bursts, &limits), Err(Rejection::InhumanIntervals { .. })));
+    }
+
+    #[test]
+    fn test_log_shorter_than_the_race_is_rejected() {
+        let limits = Limits::default();
+        let human = typed(TEXT, &[150, 90, 210, 120]);
+        let logged_ms = human.last().unwrap().at_ms;
+        // Finishing a little after the last key is network delay
+        assert_eq!(check_clock(&human, Duration::from_millis(logged_ms + 1500), &limits), Ok(()));
+        assert_eq!(
+            check_clock(&human, Duration::from_millis(logged_ms * 3), &limits),
+            Err(Rejection::ClockMismatch { logged_ms, elapsed_ms: logged_ms * 3 })
+        );
+    }
+
+    #[test]
+    fn test_suspicious_runs_are_flagged() {
+        let limits = Limits::default();
+        let metronome = typed(TEXT, &[100]);
+        assert_eq!(check_run(TEXT, &metronome, &limits).unwrap().flags, vec![Flag::UniformRhythm]);
+
+        let long = [TEXT, TEXT, TEXT].join(" ");
+        let fast = typed(&long, &[40, 70, 55, 90]);
+        let flags = check_run(&long, &fast, &limits).unwrap().flags;
+        assert_eq!(flags, vec![Flag::NearSpeedLimit, Flag::FlawlessAtSpeed]);
+    }
+}
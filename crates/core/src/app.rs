@@ -1,172 +1,796 @@
-use rand::Rng;
+use std::time::{Duration, Instant};
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use crossterm::event::KeyEvent;
+#[cfg(feature = "multiplayer")]
+use crate::multiplayer::RaceConnection;
 use crate::{
+    adaptive::{self, AdaptiveWeights},
     config::Config,
-    stats::Stats,
+    engine::Engine,
+    game::GameMode,
+    graphemes,
+    history::{History, HistoryError, PbKey, RunRecord},
+    packs::{PackWarning, TextLibrary},
+    protocol::MultiplayerMessage,
+    replay::{KeystrokeLog, ReplayPlayer},
+    room::{RoomEvent, RoomView},
     text::Text,
+    theme::Theme,
+    words::{WordList, WordResult},
 };
-use serde::Deserialize;
+
+/// Durations offered on the main menu for timed runs, in seconds.
+pub const TIME_PRESETS: [u64; 4] = [15, 30, 60, 120];
+
+/// Test lengths offered on the main menu for word-count runs.
+pub const WORD_PRESETS: [usize; 4] = [10, 25, 50, 100];
+
+// In timed mode another passage is appended once fewer than this many
+// characters are left to type, so the text never runs out before the deadline.
+const TIMED_LOOKAHEAD_CHARS: usize = 80;
+
+// How often progress in an online race is sent to the room
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum State {
     MainMenu,
     TypingGame,
     EndScreen,
+    StatsScreen,
+    Replay,
+    /// Host/join form for online races.
+    JoinScreen,
+    /// In a race room, readying up for the next race.
+    Lobby,
+    /// The race text is out and the race is about to begin.
+    Countdown,
+    /// Places of an online race as racers finish, then the final standings.
+    RaceResults,
+}
+
+/// Field being edited on the host/join form.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinField {
+    Name,
+    Address,
+    Room,
+}
+
+/// What the host/join form holds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JoinForm {
+    /// Host a server on `address`, rather than join a room on one.
+    pub host: bool,
+    pub name: String,
+    pub address: String,
+    pub room: String,
+    pub field: JoinField,
+    /// Why the last attempt to connect failed, or the connection was lost.
+    pub error: Option<String>,
+}
+
+/// Address a hosted server listens on unless told otherwise.
+pub const DEFAULT_HOST_ADDRESS: &str = "0.0.0.0:7878";
+
+/// Server joined unless told otherwise.
+pub const DEFAULT_JOIN_ADDRESS: &str = "127.0.0.1:7878";
+
+impl Default for JoinForm {
+    fn default() -> Self {
+        Self {
+            host: true,
+            name: std::env::var("USER").unwrap_or_else(|_| "player".to_string()),
+            address: DEFAULT_HOST_ADDRESS.to_string(),
+            room: String::new(),
+            field: JoinField::Name,
+            error: None,
+        }
+    }
+}
+
+impl JoinForm {
+    /// Fields shown for the current choice; a host has no room code to enter.
+    pub fn fields(&self) -> &'static [JoinField] {
+        if self.host {
+            &[JoinField::Name, JoinField::Address]
+        } else {
+            &[JoinField::Name, JoinField::Address, JoinField::Room]
+        }
+    }
+
+    fn toggle_host(&mut self) {
+        self.host = !self.host;
+        // Swap in the other default unless the address was typed in
+        let (from, to) = if self.host {
+            (DEFAULT_JOIN_ADDRESS, DEFAULT_HOST_ADDRESS)
+        } else {
+            (DEFAULT_HOST_ADDRESS, DEFAULT_JOIN_ADDRESS)
+        };
+        if self.address == from {
+            self.address = to.to_string();
+        }
+        if !self.fields().contains(&self.field) {
+            self.field = JoinField::Name;
+        }
+    }
+
+    fn next_field(&mut self, forward: bool) {
+        let fields = self.fields();
+        let pos = fields.iter().position(|f| *f == self.field).unwrap_or(0);
+        let next = if forward { pos + 1 } else { pos + fields.len() - 1 };
+        self.field = fields[next % fields.len()];
+    }
+
+    fn value_mut(&mut self) -> &mut String {
+        match self.field {
+            JoinField::Name => &mut self.name,
+            JoinField::Address => &mut self.address,
+            JoinField::Room => &mut self.room,
+        }
+    }
+}
+
+/// Page shown on the stats screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsView {
+    Trends,
+    Keys,
 }
 
 pub struct App {
     pub config: Config,
+    pub theme: Theme,
     pub texts: Vec<Text>,
+    pub text_warnings: Vec<PackWarning>,
     pub categories: Vec<String>,
+    /// Languages of the code snippets, which take the place of categories in code mode.
+    pub languages: Vec<String>,
     pub selected_category: Option<String>, // None = Random
-    pub stats: Stats,
-    pub input: String,
+    pub mode: GameMode,
+    /// The run being typed: its text, input, clock and stats, and its keystrokes,
+    /// which are kept until the next run starts.
+    pub engine: Engine,
     pub current_text_index: usize,
+    pub word_list: WordList,
     pub should_exit: bool,
     pub state: State,
     pub wpm_history: Vec<u64>,
+    /// Where completed runs are saved; `None` keeps them out of any history.
+    pub history: Option<History>,
+    /// Runs that could not be saved, for the front-end to report.
+    pub history_errors: Vec<HistoryError>,
+    /// Best earlier run with the same mode and category, looked up when a run starts.
+    pub personal_best: Option<RunRecord>,
+    /// The run shown on the end screen.
+    pub last_run: Option<RunRecord>,
+    /// Weak keys and bigrams that adaptive practice favours, refreshed per run.
+    pub adaptive_weights: AdaptiveWeights,
+    pub stats_view: StatsView,
+    // Screen the stats screen goes back to
+    stats_return: State,
+    /// A saved run being raced: its text is typed again and its ghost follows
+    /// the recorded keystrokes. Cleared on returning to the menu.
+    pub race: Option<KeystrokeLog>,
+    // Keystrokes the ghost follows this run: the raced run, or the personal
+    // best's replay when it was on the same text
+    ghost_log: Option<KeystrokeLog>,
+    /// The run being played back on the replay screen.
+    pub replay: Option<ReplayPlayer>,
+    // Screen the replay goes back to, and when the replay clock last moved
+    replay_return: State,
+    replay_tick: Instant,
+    pub join_form: JoinForm,
+    /// The online race room, from joining it until leaving.
+    pub room: Option<RoomView>,
+    /// When the race countdown runs out.
+    pub race_start: Option<Instant>,
+    #[cfg(feature = "multiplayer")]
+    connection: Option<RaceConnection>,
+    // Mode to go back to on leaving the room, since races are typed as passages
+    offline_mode: GameMode,
+    progress_sent: Instant,
+    rng: StdRng,
 }
 
 impl App {
     pub fn new() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let config = Config::new();
-        #[derive(Deserialize)]
-        struct RawText { category: String, content: String, attribution: String }
-        // texts.json is stored at repository root; this file is at crates/core/src/app.rs
-        const RAW_TEXTS: &str = include_str!("../../../texts.json");
-        let parsed: Vec<RawText> = serde_json::from_str(RAW_TEXTS)?;
-        let texts: Vec<Text> = parsed
-            .into_iter()
-            .map(|t| Text {
-                content: t.content,
-                source: t.attribution,
-                language: "en".to_string(),
-                category: t.category,
-            })
-            .collect();
-        let stats = Stats::new();
-        let input = String::new();
+        Self::new_with_config(Config::new())
+    }
+
+    /// Builds an app from the bundled packs plus any packs in the user data directory.
+    pub fn new_with_config(config: Config) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Self::with_library(config, TextLibrary::load(None))
+    }
+
+    /// Builds an app from an already loaded library. Pack warnings are kept in
+    /// `text_warnings` for the front-end to report; only an empty library is an error,
+    /// unless the config has a `default_text` to fall back on.
+    pub fn with_library(config: Config, library: TextLibrary) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let TextLibrary { mut texts, warnings, .. } = library;
+        if texts.is_empty() {
+            match &config.default_text {
+                Some(text) => texts.push(Text::from_all(text, "", "en", "general")),
+                None => return Err("no texts could be loaded from any pack".into()),
+            }
+        }
         let categories = {
             let mut set = std::collections::BTreeSet::new();
-            for t in &texts { if !t.category.is_empty() { set.insert(t.category.clone()); } }
+            for t in &texts { if !t.category.is_empty() && !t.is_code() { set.insert(t.category.clone()); } }
             set.into_iter().collect::<Vec<_>>()
         };
-        let current_text_index = if texts.is_empty() { 0 } else { rand::thread_rng().gen_range(0..texts.len()) };
-        let should_exit = false;
-        let state = State::MainMenu;
-
-        Ok(App {
-            config,
+        let languages = {
+            let set: std::collections::BTreeSet<String> = texts.iter().filter(|t| t.is_code()).map(|t| t.language.clone()).collect();
+            set.into_iter().collect::<Vec<_>>()
+        };
+        let mut rng = StdRng::from_entropy();
+        let current_text_index = rng.gen_range(0..texts.len());
+        let mut engine = Engine::with_normalization(config.game_mode(), &texts[current_text_index].content, config.normalize);
+        engine.set_auto_indent(config.auto_indent);
+        Ok(Self {
+            state: State::MainMenu,
+            should_exit: false,
+            engine,
             texts,
+            text_warnings: warnings,
             categories,
+            languages,
             selected_category: None,
-            stats,
-            input,
+            mode: config.game_mode(),
+            offline_mode: config.game_mode(),
             current_text_index,
-            should_exit,
-            state,
+            word_list: WordList::english(),
+            theme: Theme::from_config(&config),
+            config,
             wpm_history: Vec::new(),
+            history: None,
+            history_errors: Vec::new(),
+            personal_best: None,
+            last_run: None,
+            adaptive_weights: AdaptiveWeights::default(),
+            stats_view: StatsView::Trends,
+            stats_return: State::MainMenu,
+            race: None,
+            ghost_log: None,
+            replay: None,
+            replay_return: State::MainMenu,
+            replay_tick: Instant::now(),
+            join_form: JoinForm::default(),
+            room: None,
+            race_start: None,
+            #[cfg(feature = "multiplayer")]
+            connection: None,
+            progress_sent: Instant::now(),
+            rng,
         })
     }
 
-    fn handle_backspace_with_rules(&mut self, ctrl: bool) {
-        if self.input.is_empty() { return; }
-        let current_text = &self.texts[self.current_text_index].content;
-        if ctrl {
-            // Delete to start of current word
-            let word_start = self.get_current_word_start();
-            if word_start < self.input.len() {
-                self.input.truncate(word_start);
-                self.update_stats();
+    /// Makes passage and word selection repeatable, e.g. for `--seed`.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Restricts passages to `category`, which must be one of `category_choices`.
+    pub fn select_category(&mut self, category: &str) -> Result<(), String> {
+        if !self.category_choices().iter().any(|c| c == category) {
+            return Err(format!(
+                "unknown category {:?} (available: {})",
+                category,
+                self.category_choices().join(", ")
+            ));
+        }
+        self.selected_category = Some(category.to_string());
+        Ok(())
+    }
+
+    /// Leaves the menu or end screen and begins a fresh run.
+    pub fn start_run(&mut self) {
+        self.state = State::TypingGame;
+        self.reset();
+        self.extend_target_if_needed();
+    }
+
+    pub fn reset(&mut self) {
+        self.refresh_adaptive_weights();
+        self.current_text_index = self.pick_random_index();
+        let target = match self.mode {
+            _ if self.race.is_some() => self.race.as_ref().map(|log| log.target.clone()).unwrap_or_default(),
+            _ if self.room.is_some() => self.room.as_ref().and_then(|r| r.text.clone()).unwrap_or_default(),
+            GameMode::Words(count) if self.is_adaptive() => {
+                let strength = self.config.adaptive_strength;
+                let weights = &self.adaptive_weights;
+                self.word_list.generate_weighted(count, |w| weights.selection_weight(w, strength), &mut self.rng)
             }
-            return;
+            GameMode::Words(count) => self.word_list.generate(count, &mut self.rng),
+            GameMode::Zen => String::new(),
+            _ => self.texts.get(self.current_text_index).map(|t| t.content.clone()).unwrap_or_default(),
+        };
+        self.wpm_history.clear();
+        self.last_run = None;
+        let mut normalization = self.config.normalize;
+        // Everyone in a race, ghosts included, types the same letters
+        if self.race.is_some() || self.room.is_some() {
+            normalization.strip_diacritics = false;
         }
+        self.engine = Engine::with_normalization(self.mode, &target, normalization);
+        self.engine.set_auto_indent(self.config.auto_indent);
+        let key = self.pb_key();
+        self.personal_best = self.history.as_ref().and_then(|h| h.personal_best(&key)).cloned();
+        self.ghost_log = self.race.clone().or_else(|| self.pb_replay());
+    }
 
-        // Deleting one character. Only allow crossing into previous word if there are errors before.
-        let target_pos = self.input.len().saturating_sub(1);
-        let current_word_start = self.get_current_word_start();
-        if target_pos < current_word_start {
-            if !self.has_errors_before_position(current_text, current_word_start) {
-                // No errors before; do not allow moving back into previous words
-                return;
+    /// Starts a run on the text of `log` with a ghost that types it exactly as
+    /// that run did, e.g. an earlier run of your own or one a teammate exported.
+    pub fn race(&mut self, log: KeystrokeLog) {
+        self.mode = log.mode;
+        self.race = Some(log);
+        self.start_run();
+    }
+
+    fn back_to_menu(&mut self) {
+        self.state = State::MainMenu;
+        self.race = None;
+        self.reset();
+    }
+
+    // The personal best's keystrokes, when the ghost is on and the best was typed
+    // on this very text; otherwise the ghost falls back to its per-second pace
+    fn pb_replay(&self) -> Option<KeystrokeLog> {
+        if !self.config.ghost {
+            return None;
+        }
+        let pb = self.personal_best.as_ref()?;
+        let log = self.history.as_ref()?.load_replay(pb).ok()?;
+        (log.target == self.engine.text()).then_some(log)
+    }
+
+    /// Whether adaptive practice is on and there is enough history to act on.
+    pub fn is_adaptive(&self) -> bool {
+        self.config.adaptive && !self.adaptive_weights.is_empty()
+    }
+
+    pub fn toggle_adaptive(&mut self) {
+        self.config.adaptive = !self.config.adaptive;
+        self.refresh_adaptive_weights();
+    }
+
+    fn refresh_adaptive_weights(&mut self) {
+        self.adaptive_weights = match (&self.history, self.config.adaptive) {
+            (Some(history), true) => AdaptiveWeights::from_history(history),
+            _ => AdaptiveWeights::default(),
+        };
+    }
+
+    /// Whether the finished run beat the previous personal best. The first run
+    /// of its kind counts as one.
+    pub fn is_new_pb(&self) -> bool {
+        match (&self.last_run, &self.personal_best) {
+            (Some(run), Some(pb)) => run.wpm > pb.wpm,
+            (Some(run), None) => run.wpm > 0.0,
+            _ => false,
+        }
+    }
+
+    /// WPM of the finished run minus the previous personal best.
+    pub fn pb_delta(&self) -> Option<f64> {
+        Some(self.last_run.as_ref()?.wpm - self.personal_best.as_ref()?.wpm)
+    }
+
+    /// Grapheme cluster the ghost has reached at this point of the run, counted
+    /// like the cursor: the raced run's cursor, or the personal best's when the
+    /// ghost is switched on.
+    pub fn ghost_position(&self) -> Option<usize> {
+        // Online the other racers are the competition
+        if !self.engine.stats().is_running() || self.room.is_some() {
+            return None;
+        }
+        let elapsed = self.engine.elapsed();
+        if let Some(log) = &self.ghost_log {
+            return Some(log.position_at(elapsed));
+        }
+        if !self.config.ghost {
+            return None;
+        }
+        let pb = self.personal_best.as_ref()?;
+        Some(pb.chars_at(elapsed).floor() as usize)
+    }
+
+    /// Grapheme clusters the cursor is ahead of the ghost (negative when behind).
+    pub fn ghost_gap(&self) -> Option<i64> {
+        let ghost = self.ghost_position()?;
+        Some(graphemes::count(self.engine.input()) as i64 - ghost as i64)
+    }
+
+    /// Source to credit under the text; generated and mixed runs have none.
+    pub fn attribution(&self) -> Option<&str> {
+        match self.mode {
+            GameMode::Time(_) | GameMode::Words(_) | GameMode::Zen => None,
+            _ => Some(self.current_passage()?.source.as_str()).filter(|s| !s.is_empty()),
+        }
+    }
+
+    // The library passage being typed. A raced text is looked up by content since
+    // it may come from someone else's library.
+    fn current_passage(&self) -> Option<&Text> {
+        if self.race.is_some() || self.room.is_some() {
+            return self.texts.iter().find(|t| t.content.trim() == self.engine.text().trim());
+        }
+        self.texts.get(self.current_text_index)
+    }
+
+    /// Per-word outcome of the run so far, for word-count runs.
+    pub fn word_results(&self) -> Vec<WordResult> {
+        self.engine.word_results()
+    }
+
+    /// Deadline of the current run, only set in timed mode.
+    pub fn time_limit(&self) -> Option<Duration> {
+        self.engine.time_limit()
+    }
+
+    /// Time left before the deadline in timed mode. Before the first keypress
+    /// this is the full duration.
+    pub fn time_remaining(&self) -> Option<Duration> {
+        self.engine.time_remaining()
+    }
+
+    fn is_time_up(&self) -> bool {
+        self.engine.stats().is_running() && self.engine.is_time_up()
+    }
+
+    /// What the category selector picks from: the languages of the code
+    /// snippets in code mode, otherwise the passage categories.
+    pub fn category_choices(&self) -> &[String] {
+        if self.mode == GameMode::Programming {
+            &self.languages
+        } else {
+            &self.categories
+        }
+    }
+
+    /// Language of the code snippet being typed in code mode.
+    pub fn language(&self) -> Option<&str> {
+        match self.mode {
+            GameMode::Programming => Some(self.current_passage()?.language.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Cycle the main menu mode: passage, code, then each timed preset, then
+    /// each word-count preset, then zen.
+    pub fn cycle_mode(&mut self, forward: bool) {
+        let mut modes = vec![GameMode::Normal, GameMode::Programming];
+        modes.extend(TIME_PRESETS.iter().map(|&secs| GameMode::Time(secs)));
+        modes.extend(WORD_PRESETS.iter().map(|&count| GameMode::Words(count)));
+        modes.push(GameMode::Zen);
+        let pos = modes.iter().position(|m| *m == self.mode).unwrap_or(0);
+        let next = if forward {
+            (pos + 1) % modes.len()
+        } else {
+            (pos + modes.len() - 1) % modes.len()
+        };
+        self.mode = modes[next];
+        self.config.set_game_mode(self.mode);
+        // Languages and categories don't carry over between code and prose
+        if let Some(category) = &self.selected_category {
+            if !self.category_choices().contains(category) {
+                self.selected_category = None;
             }
         }
-        self.input.pop();
-        self.update_stats();
     }
 
-    fn get_current_word_start(&self) -> usize {
-        let mut word_start = 0;
-        let mut in_word = false;
-        for (i, c) in self.input.chars().enumerate() {
-            if c.is_whitespace() {
-                if in_word { word_start = i + 1; }
-                in_word = false;
+    // Keep a timed run fed with text: append another passage before the user
+    // reaches the end of what is on screen.
+    fn extend_target_if_needed(&mut self) {
+        if self.time_limit().is_none() || self.texts.is_empty() {
+            return;
+        }
+        let remaining = self.engine.text().chars().count().saturating_sub(self.engine.input().chars().count());
+        if remaining >= TIMED_LOOKAHEAD_CHARS {
+            return;
+        }
+        let next = self.pick_random_index();
+        let content = self.texts[next].content.trim().to_string();
+        self.engine.append_text(&content);
+    }
+
+    // End a timed run on its deadline: the engine's clock stops at exactly the
+    // limit so the final stats don't depend on how late the last frame was.
+    fn finish_on_deadline(&mut self) {
+        self.engine.stop();
+        self.finish_run();
+    }
+
+    // Move to the end screen and save the run to the history.
+    fn finish_run(&mut self) {
+        self.sample_wpm();
+        self.state = State::EndScreen;
+        let mut record = self.run_record();
+        if let Some(history) = &mut self.history {
+            let saved = if self.engine.keystroke_log().is_empty() {
+                history.record(record.clone())
             } else {
-                in_word = true;
+                history.record_with_replay(record.clone(), self.engine.keystroke_log())
+            };
+            match saved {
+                Ok(()) => record = history.runs().last().cloned().unwrap_or(record),
+                Err(e) => self.history_errors.push(e),
             }
         }
-        word_start
+        if self.room.is_some() {
+            // The server works the result out from the keystrokes themselves
+            self.send_to_room(MultiplayerMessage::Finish { events: self.engine.keystroke_log().events.clone() });
+            self.state = State::RaceResults;
+        }
+        self.last_run = Some(record);
     }
 
-    fn has_errors_before_position(&self, text: &str, position: usize) -> bool {
-        let compare_len = self.input.len().min(text.len());
-        for (i, (ic, tc)) in self.input.chars().zip(text.chars()).take(compare_len).enumerate() {
-            if i >= position { break; }
-            if ic != tc { return true; }
+    /// Plays `log` back on the replay screen at `speed` times real time; leaving
+    /// it returns to the current screen.
+    pub fn open_replay(&mut self, log: KeystrokeLog, speed: f64) {
+        if self.state != State::Replay {
+            self.replay_return = self.state.clone();
         }
-        false
+        self.replay = Some(ReplayPlayer::new(log, speed));
+        self.replay_tick = Instant::now();
+        self.state = State::Replay;
     }
 
-    pub fn new_with_config(config: Config) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        #[derive(Deserialize)]
-        struct RawText { category: String, content: String, attribution: String }
-        const RAW_TEXTS: &str = include_str!("../../../texts.json");
-        let parsed: Vec<RawText> = serde_json::from_str(RAW_TEXTS)?;
-        let texts: Vec<Text> = parsed
-            .into_iter()
-            .map(|t| Text {
-                content: t.content,
-                source: t.attribution,
-                language: "en".to_string(),
-                category: t.category,
-            })
-            .collect();
-        let categories = {
-            let mut set = std::collections::BTreeSet::new();
-            for t in &texts { if !t.category.is_empty() { set.insert(t.category.clone()); } }
-            set.into_iter().collect::<Vec<_>>()
+    fn close_replay(&mut self) {
+        self.replay = None;
+        self.state = self.replay_return.clone();
+    }
+
+    /// Shows the host/join form for online races.
+    pub fn open_join_screen(&mut self) {
+        self.join_form.error = None;
+        self.state = State::JoinScreen;
+    }
+
+    // Hosts or joins as the form says, blocking until the server answers
+    #[cfg(feature = "multiplayer")]
+    fn connect(&mut self) {
+        let form = &self.join_form;
+        let name = form.name.trim();
+        if name.is_empty() {
+            self.join_form.error = Some("enter a name to race under".to_string());
+            return;
+        }
+        let connected = if form.host {
+            RaceConnection::host(form.address.trim(), name).map(|(connection, _)| connection)
+        } else if form.room.trim().is_empty() {
+            self.join_form.error = Some("enter the room code you were given".to_string());
+            return;
+        } else {
+            RaceConnection::join(form.address.trim(), form.room.trim(), name)
         };
-        let current_text_index = if texts.is_empty() { 0 } else { rand::thread_rng().gen_range(0..texts.len()) };
-        Ok(Self {
-            state: State::MainMenu,
-            should_exit: false,
-            input: String::new(),
-            texts,
-            categories,
-            selected_category: None,
-            current_text_index,
-            stats: Stats::new(),
-            config,
-            wpm_history: Vec::new(),
-        })
+        match connected {
+            Ok(connection) => {
+                self.connection = Some(connection);
+                self.offline_mode = self.mode;
+                self.join_form.error = None;
+            }
+            Err(e) => self.join_form.error = Some(e.to_string()),
+        }
     }
 
-    pub fn reset(&mut self) {
-        self.input.clear();
-        self.stats.reset();
-        self.current_text_index = self.pick_random_index();
-        self.wpm_history.clear();
+    #[cfg(not(feature = "multiplayer"))]
+    fn connect(&mut self) {
+        self.join_form.error = Some("this build has no multiplayer support".to_string());
+    }
+
+    #[cfg(feature = "multiplayer")]
+    fn send_to_room(&mut self, message: MultiplayerMessage) {
+        if let Some(connection) = &self.connection {
+            // A lost connection is noticed on the next poll
+            let _ = connection.send(message);
+        }
+    }
+
+    #[cfg(not(feature = "multiplayer"))]
+    fn send_to_room(&mut self, _message: MultiplayerMessage) {}
+
+    // Applies what the server sent since the last frame
+    #[cfg(feature = "multiplayer")]
+    fn poll_room(&mut self) {
+        let Some(connection) = &mut self.connection else {
+            return;
+        };
+        let messages = connection.poll();
+        let connected = connection.is_connected();
+        for message in messages {
+            self.apply_room_message(message);
+        }
+        if !connected {
+            self.leave_room();
+            self.join_form.error = Some("lost the connection to the race server".to_string());
+        }
+    }
+
+    /// Applies a message from the race server: joining the room, the countdown,
+    /// the go and the results move between the race screens.
+    pub fn apply_room_message(&mut self, message: MultiplayerMessage) {
+        let Some(room) = &mut self.room else {
+            match RoomView::joined(&message) {
+                Some(room) => {
+                    self.room = Some(room);
+                    self.state = State::Lobby;
+                }
+                // Refused before getting into a room, e.g. a wrong code
+                None => {
+                    if let MultiplayerMessage::Error { message, .. } = message {
+                        self.leave_room();
+                        self.join_form.error = Some(message);
+                    }
+                }
+            }
+            return;
+        };
+        match room.apply(&message) {
+            Some(RoomEvent::Countdown(delay)) => {
+                self.race_start = Some(Instant::now() + delay);
+                self.state = State::Countdown;
+            }
+            Some(RoomEvent::Go) => {
+                self.race_start = None;
+                self.mode = GameMode::Normal;
+                self.start_run();
+                // The clock runs from the go, not the first keypress
+                self.engine.start();
+                self.progress_sent = Instant::now();
+            }
+            Some(RoomEvent::Results) if self.state == State::TypingGame => {
+                self.engine.stop();
+                self.state = State::RaceResults;
+            }
+            _ => {}
+        }
+    }
+
+    /// Leaves the race room and disconnects, back to the host/join form.
+    pub fn leave_room(&mut self) {
+        if self.room.is_some() {
+            self.send_to_room(MultiplayerMessage::LeaveRoom);
+        }
+        #[cfg(feature = "multiplayer")]
+        {
+            self.connection = None;
+        }
+        self.room = None;
+        self.race_start = None;
+        self.mode = self.offline_mode;
+        self.state = State::JoinScreen;
+        self.reset();
+    }
+
+    fn toggle_ready(&mut self) {
+        let Some(me) = self.room.as_ref().and_then(|r| r.me()) else {
+            return;
+        };
+        let ready = !me.info.ready;
+        self.send_to_room(MultiplayerMessage::Ready { ready });
+    }
+
+    fn start_room_race(&mut self) {
+        if !self.room.as_ref().is_some_and(|r| r.is_host()) {
+            return;
+        }
+        let text = self.race_text();
+        self.send_to_room(MultiplayerMessage::StartRace { text });
+    }
+
+    // A text for the room, picked the way the menu's mode would pick one. Timed
+    // runs have no end to race to, so they race a passage. Races are typed without
+    // Enter or Tab, so a host in code mode races a passage rather than a snippet.
+    fn race_text(&mut self) -> String {
+        match self.offline_mode {
+            GameMode::Words(count) => self.word_list.generate(count, &mut self.rng),
+            _ => {
+                let index = self.pick_index(false);
+                self.texts.get(index).map(|t| t.content.clone()).unwrap_or_default()
+            }
+        }
+    }
+
+    // Reports progress to the room a few times a second
+    fn report_progress(&mut self) {
+        if self.progress_sent.elapsed() < PROGRESS_INTERVAL {
+            return;
+        }
+        self.progress_sent = Instant::now();
+        let (progress, wpm) = (self.get_progress().min(100.0) as f32, self.engine.wpm() as f32);
+        if let Some(room) = &mut self.room {
+            room.update_me(progress, wpm);
+        }
+        self.send_to_room(MultiplayerMessage::Progress { progress, wpm });
+    }
+
+    // Category a run is filed under: the passage's own for single passages, the
+    // menu choice for timed runs that mix passages, and none for generated words.
+    fn run_category(&self) -> Option<String> {
+        match self.mode {
+            GameMode::Words(_) | GameMode::Zen => None,
+            GameMode::Programming => self.language().map(str::to_string),
+            GameMode::Time(_) if self.race.is_none() => self.selected_category.clone(),
+            GameMode::Time(_) => None,
+            _ => self.current_passage().map(|t| t.category.clone()).filter(|c| !c.is_empty()),
+        }
+    }
+
+    fn pb_key(&self) -> PbKey {
+        PbKey::new(self.mode, self.run_category().as_deref())
+    }
+
+    /// The current run as a history record, timestamped now.
+    pub fn run_record(&self) -> RunRecord {
+        let text_id = match self.mode {
+            GameMode::Words(_) | GameMode::Time(_) | GameMode::Zen => None,
+            _ => Some(Text::from_str(self.engine.text()).id()),
+        };
+        RunRecord {
+            timestamp: chrono::Utc::now(),
+            mode: self.mode,
+            text_id,
+            category: self.run_category(),
+            wpm: self.engine.wpm(),
+            raw_wpm: self.engine.raw_wpm(),
+            accuracy: self.engine.accuracy(),
+            errors: self.engine.errors(),
+            duration_secs: self.engine.elapsed().as_secs_f64(),
+            wpm_samples: self.wpm_history.clone(),
+            timeline: self.engine.timeline(),
+            consistency: self.engine.consistency(),
+            burst: self.engine.burst(),
+            keys: self.engine.key_stats().clone(),
+            replay: None,
+        }
+    }
+
+    fn sample_wpm(&mut self) {
+        // Sample WPM once per elapsed second to build a compact sparkline
+        let secs = self.engine.elapsed().as_secs() as usize;
+        while self.wpm_history.len() < secs {
+            self.wpm_history.push(self.engine.wpm().round() as u64);
+        }
     }
 
-    fn pick_random_index(&self) -> usize {
+    fn pick_random_index(&mut self) -> usize {
+        // Code snippets are only typed in code mode, where the selector picks a language
+        self.pick_index(self.mode == GameMode::Programming)
+    }
+
+    // Picks a code snippet or a passage. The selector only applies to the kind of
+    // text the current mode types; a language means nothing when picking prose.
+    fn pick_index(&mut self, code: bool) -> usize {
         if self.texts.is_empty() { return 0; }
-        let pool: Vec<usize> = match &self.selected_category {
-            Some(cat) => self.texts.iter().enumerate().filter(|(_, t)| &t.category == cat).map(|(i, _)| i).collect(),
-            None => (0..self.texts.len()).collect(),
+        let selected = match &self.selected_category {
+            Some(choice) if code == (self.mode == GameMode::Programming) => Some(choice),
+            _ => None,
         };
+        let pool: Vec<usize> = self
+            .texts
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| t.is_code() == code)
+            .filter(|(_, t)| match selected {
+                Some(choice) if code => &t.language == choice,
+                Some(choice) => &t.category == choice,
+                None => true,
+            })
+            .map(|(i, _)| i)
+            .collect();
         if pool.is_empty() { return 0; }
-        let idx = rand::thread_rng().gen_range(0..pool.len());
+        if self.is_adaptive() {
+            let strength = self.config.adaptive_strength;
+            let weights: Vec<f64> = pool
+                .iter()
+                .map(|&i| self.adaptive_weights.selection_weight(&self.texts[i].content, strength))
+                .collect();
+            if let Some(idx) = adaptive::pick_weighted(&weights, &mut self.rng) {
+                return pool[idx];
+            }
+        }
+        let idx = self.rng.gen_range(0..pool.len());
         pool[idx]
     }
 
@@ -175,33 +799,41 @@ impl App {
             State::MainMenu => {
                 match key.code {
                     crossterm::event::KeyCode::Enter => {
-                        self.state = State::TypingGame;
-                        self.reset();
+                        self.start_run();
+                    }
+                    crossterm::event::KeyCode::Up => self.cycle_mode(false),
+                    crossterm::event::KeyCode::Down => self.cycle_mode(true),
+                    crossterm::event::KeyCode::Char('s' | 'S') => self.open_stats(),
+                    crossterm::event::KeyCode::Char('a' | 'A') => self.toggle_adaptive(),
+                    crossterm::event::KeyCode::Char('o' | 'O') if cfg!(feature = "multiplayer") => {
+                        self.open_join_screen();
                     }
                     crossterm::event::KeyCode::Left => {
                         // cycle category backwards (None -> last)
-                        if self.categories.is_empty() {
+                        let categories = self.category_choices().to_vec();
+                        if categories.is_empty() {
                             self.selected_category = None;
                         } else {
                             match &self.selected_category {
-                                None => self.selected_category = Some(self.categories.last().unwrap().clone()),
+                                None => self.selected_category = Some(categories.last().unwrap().clone()),
                                 Some(cur) => {
-                                    let pos = self.categories.iter().position(|c| c == cur).unwrap_or(0);
-                                    if pos == 0 { self.selected_category = None; } else { self.selected_category = Some(self.categories[pos-1].clone()); }
+                                    let pos = categories.iter().position(|c| c == cur).unwrap_or(0);
+                                    if pos == 0 { self.selected_category = None; } else { self.selected_category = Some(categories[pos-1].clone()); }
                                 }
                             }
                         }
                     }
                     crossterm::event::KeyCode::Right => {
                         // cycle category forwards (None -> first)
-                        if self.categories.is_empty() {
+                        let categories = self.category_choices().to_vec();
+                        if categories.is_empty() {
                             self.selected_category = None;
                         } else {
                             match &self.selected_category {
-                                None => self.selected_category = Some(self.categories[0].clone()),
+                                None => self.selected_category = Some(categories[0].clone()),
                                 Some(cur) => {
-                                    let pos = self.categories.iter().position(|c| c == cur).unwrap_or(0);
-                                    if pos + 1 >= self.categories.len() { self.selected_category = None; } else { self.selected_category = Some(self.categories[pos+1].clone()); }
+                                    let pos = categories.iter().position(|c| c == cur).unwrap_or(0);
+                                    if pos + 1 >= categories.len() { self.selected_category = None; } else { self.selected_category = Some(categories[pos+1].clone()); }
                                 }
                             }
                         }
@@ -213,86 +845,150 @@ impl App {
                 }
             }
             State::TypingGame => {
+                if self.is_time_up() {
+                    // The deadline passed between frames; this key is too late to count
+                    self.finish_on_deadline();
+                    return;
+                }
                 match key.code {
                     crossterm::event::KeyCode::Char(c) => {
-                        // Handle control-word delete (Ctrl+W)
+                        // Handle control-word delete (Ctrl+W). Some terminals send Ctrl+H
+                        // instead of Ctrl+Backspace, so treat it the same way.
                         if key.modifiers.contains(crossterm::event::KeyModifiers::CONTROL)
-                            && (c == 'w' || c == 'W')
+                            && matches!(c, 'w' | 'W' | 'h' | 'H')
                         {
-                            self.handle_backspace_with_rules(true);
+                            self.engine.handle_backspace(true);
                             return;
                         }
                         // Don't insert invisible control chars; only insert when no CTRL/ALT (SHIFT ok)
                         if key.modifiers.intersects(crossterm::event::KeyModifiers::CONTROL | crossterm::event::KeyModifiers::ALT) {
                             return;
                         }
-                        if !self.stats.is_running() { self.stats.start(); }
-                        // Record keystroke correctness before mutating input
-                        let was_correct = {
-                            let pos = self.input.len();
-                            let current_text = &self.texts[self.current_text_index].content;
-                            if pos < current_text.len() {
-                                // Compare with target at this position
-                                current_text.chars().nth(pos).map(|tc| tc == c).unwrap_or(false)
-                            } else {
-                                false // extra chars are considered incorrect
-                            }
-                        };
-                        self.stats.note_keypress(was_correct);
-                        self.input.push(c);
-                        self.update_stats();
+                        self.engine.type_char(c);
+                        self.extend_target_if_needed();
+                    }
+                    // Code is typed with its line breaks and indentation
+                    crossterm::event::KeyCode::Enter if self.mode == GameMode::Programming => {
+                        self.engine.type_char('\n');
+                    }
+                    crossterm::event::KeyCode::Tab if self.mode == GameMode::Programming => {
+                        self.engine.type_indent();
                     }
                     crossterm::event::KeyCode::Backspace => {
                         // Treat Ctrl or Alt modified Backspace as word delete for tmux/screen/terms
                         let ctrl_or_alt = key.modifiers.intersects(
                             crossterm::event::KeyModifiers::CONTROL | crossterm::event::KeyModifiers::ALT,
                         );
-                        self.handle_backspace_with_rules(ctrl_or_alt);
-                    }
-                    // Some terminals send Ctrl+H instead of Ctrl+Backspace
-                    crossterm::event::KeyCode::Char('h') | crossterm::event::KeyCode::Char('H')
-                        if key.modifiers.contains(crossterm::event::KeyModifiers::CONTROL) =>
-                    {
-                        self.handle_backspace_with_rules(true);
+                        self.engine.handle_backspace(ctrl_or_alt);
                     }
-                    crossterm::event::KeyCode::Esc => {
-                        self.state = State::MainMenu;
-                        self.reset();
+                    crossterm::event::KeyCode::Esc if self.room.is_some() => self.leave_room(),
+                    // A zen run has no end of its own, so Esc finishes it once started
+                    crossterm::event::KeyCode::Esc if self.mode == GameMode::Zen && self.engine.stats().is_started() => {
+                        self.engine.stop();
                     }
+                    crossterm::event::KeyCode::Esc => self.back_to_menu(),
                     _ => {}
                 }
             }
             State::EndScreen => {
                 match key.code {
                     crossterm::event::KeyCode::Enter => {
-                        self.state = State::TypingGame;
-                        self.reset();
+                        self.start_run();
                     }
-                    crossterm::event::KeyCode::Esc => {
-                        self.state = State::MainMenu;
-                        self.reset();
+                    crossterm::event::KeyCode::Char('s' | 'S') => self.open_stats(),
+                    crossterm::event::KeyCode::Char('r' | 'R') if !self.engine.keystroke_log().is_empty() => {
+                        self.open_replay(self.engine.keystroke_log().clone(), 1.0);
+                    }
+                    crossterm::event::KeyCode::Char('g' | 'G')
+                        if !self.engine.keystroke_log().is_empty() && self.mode != GameMode::Zen =>
+                    {
+                        self.race(self.engine.keystroke_log().clone());
                     }
+                    crossterm::event::KeyCode::Esc => self.back_to_menu(),
                     _ => {}
                 }
             }
+            State::StatsScreen => {
+                if matches!(
+                    key.code,
+                    crossterm::event::KeyCode::Tab | crossterm::event::KeyCode::Left | crossterm::event::KeyCode::Right
+                ) {
+                    self.stats_view = match self.stats_view {
+                        StatsView::Trends => StatsView::Keys,
+                        StatsView::Keys => StatsView::Trends,
+                    };
+                } else if matches!(
+                    key.code,
+                    crossterm::event::KeyCode::Esc | crossterm::event::KeyCode::Enter | crossterm::event::KeyCode::Char('s' | 'S')
+                ) {
+                    self.state = self.stats_return.clone();
+                }
+            }
+            State::Replay => {
+                let Some(player) = &mut self.replay else {
+                    self.close_replay();
+                    return;
+                };
+                match key.code {
+                    crossterm::event::KeyCode::Char(' ') if player.is_done() => player.restart(),
+                    crossterm::event::KeyCode::Char(' ') => player.paused = !player.paused,
+                    crossterm::event::KeyCode::Char('+' | '=') | crossterm::event::KeyCode::Right => player.faster(),
+                    crossterm::event::KeyCode::Char('-') | crossterm::event::KeyCode::Left => player.slower(),
+                    crossterm::event::KeyCode::Char('r' | 'R') => player.restart(),
+                    crossterm::event::KeyCode::Esc | crossterm::event::KeyCode::Enter => self.close_replay(),
+                    _ => {}
+                }
+            }
+            State::JoinScreen => {
+                let form = &mut self.join_form;
+                match key.code {
+                    crossterm::event::KeyCode::Left | crossterm::event::KeyCode::Right => form.toggle_host(),
+                    crossterm::event::KeyCode::Tab | crossterm::event::KeyCode::Down => form.next_field(true),
+                    crossterm::event::KeyCode::BackTab | crossterm::event::KeyCode::Up => form.next_field(false),
+                    crossterm::event::KeyCode::Backspace => {
+                        form.value_mut().pop();
+                    }
+                    crossterm::event::KeyCode::Char(c)
+                        if !key.modifiers.intersects(
+                            crossterm::event::KeyModifiers::CONTROL | crossterm::event::KeyModifiers::ALT,
+                        ) =>
+                    {
+                        let c = if form.field == JoinField::Room { c.to_ascii_uppercase() } else { c };
+                        form.value_mut().push(c);
+                    }
+                    crossterm::event::KeyCode::Enter => self.connect(),
+                    crossterm::event::KeyCode::Esc => self.back_to_menu(),
+                    _ => {}
+                }
+            }
+            State::Lobby => match key.code {
+                crossterm::event::KeyCode::Char(' ' | 'r' | 'R') => self.toggle_ready(),
+                crossterm::event::KeyCode::Enter => self.start_room_race(),
+                crossterm::event::KeyCode::Esc => self.leave_room(),
+                _ => {}
+            },
+            State::Countdown => {
+                if key.code == crossterm::event::KeyCode::Esc {
+                    self.leave_room();
+                }
+            }
+            State::RaceResults => match key.code {
+                crossterm::event::KeyCode::Enter => self.state = State::Lobby,
+                crossterm::event::KeyCode::Esc => self.leave_room(),
+                _ => {}
+            },
         }
 
         // Check if the current text is finished
         if self.state == State::TypingGame && self.is_finished() {
-            self.state = State::EndScreen;
-            self.stats.stop();
-        }
-    }
-
-    pub fn update_stats(&mut self) {
-        if self.state == State::TypingGame {
-            let current_text = &self.texts[self.current_text_index].content;
-            self.stats.update(&self.input, current_text);
+            self.engine.stop();
+            self.finish_run();
         }
     }
 
+    /// Whether the text is done; timed runs also end on their deadline.
     pub fn is_finished(&self) -> bool {
-        self.input.trim() == self.texts[self.current_text_index].content.trim()
+        self.engine.is_complete()
     }
 
     pub fn current_text(&self) -> &Text {
@@ -300,64 +996,96 @@ impl App {
     }
 
     pub fn get_input(&self) -> &str {
-        self.input.as_str()
+        self.engine.input()
     }
 
     pub fn handle_backspace(&mut self) {
-        if self.state == State::TypingGame && !self.input.is_empty() {
-            self.input.pop();
-            self.update_stats();
+        if self.state == State::TypingGame {
+            self.engine.handle_backspace(false);
         }
     }
 
     pub fn handle_enter(&mut self) {
         match self.state {
-            State::MainMenu => {
-                self.state = State::TypingGame;
-                self.reset();
-            }
-            State::EndScreen => {
-                self.state = State::TypingGame;
-                self.reset();
-            }
+            State::MainMenu | State::EndScreen => self.start_run(),
             _ => {}
         }
     }
 
     pub fn handle_escape(&mut self) {
         match self.state {
-            State::TypingGame => {
-                self.state = State::MainMenu;
-                self.reset();
-            }
-            State::EndScreen => {
-                self.state = State::MainMenu;
-                self.reset();
+            State::TypingGame if self.room.is_some() => self.leave_room(),
+            State::TypingGame if self.mode == GameMode::Zen && self.engine.stats().is_started() => {
+                self.engine.stop();
+                self.finish_run();
             }
+            State::TypingGame | State::EndScreen => self.back_to_menu(),
             State::MainMenu => {
                 self.should_exit = true;
             }
+            State::StatsScreen => {
+                self.state = self.stats_return.clone();
+            }
+            State::Replay => self.close_replay(),
+            State::JoinScreen => self.back_to_menu(),
+            State::Lobby | State::Countdown | State::RaceResults => self.leave_room(),
         }
     }
 
-    pub fn get_progress(&self) -> f64 {
-        if self.input.is_empty() {
-            0.0
-        } else {
-            let total_chars = self.current_text().content.chars().count();
-            let current_chars = self.input.chars().count();
-            (current_chars as f64 / total_chars as f64) * 100.0
+    /// Shows the stats screen; leaving it returns to the current screen.
+    pub fn open_stats(&mut self) {
+        if self.state != State::StatsScreen {
+            self.stats_return = self.state.clone();
+            self.state = State::StatsScreen;
         }
     }
 
+    pub fn get_progress(&self) -> f64 {
+        self.engine.progress()
+    }
+
     pub fn update(&mut self) {
         if self.state == State::TypingGame {
-            self.update_stats();
-            // Sample WPM once per elapsed second to build a compact sparkline
-            let secs = self.stats.elapsed_time().as_secs() as usize;
-            while self.wpm_history.len() < secs {
-                self.wpm_history.push(self.stats.wpm().round() as u64);
+            if self.is_time_up() {
+                self.finish_on_deadline();
+                return;
+            }
+            self.sample_wpm();
+            if self.room.is_some() {
+                self.report_progress();
             }
         }
+        #[cfg(feature = "multiplayer")]
+        self.poll_room();
+        let now = Instant::now();
+        if let Some(player) = &mut self.replay {
+            player.advance(now - self.replay_tick);
+        }
+        self.replay_tick = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::text::CODE_CATEGORY;
+
+    #[test]
+    fn test_race_from_code_mode_uses_prose() {
+        let mut library = TextLibrary::new();
+        library.add_text(Text::from_all("The quick brown fox.", "", "en", "general"));
+        library.add_text(Text::from_all("fn main() {\n    run();\n}", "", "rust", CODE_CATEGORY));
+        let mut app = App::with_library(Config::default(), library).unwrap();
+        app.seed(7);
+        app.mode = GameMode::Programming;
+        app.offline_mode = GameMode::Programming;
+        app.select_category("rust").unwrap();
+
+        // Code mode itself still types the snippet
+        let index = app.pick_random_index();
+        assert!(app.texts[index].is_code());
+        for _ in 0..20 {
+            assert_eq!(app.race_text(), "The quick brown fox.");
+        }
     }
-} 
\ No newline at end of file
+}
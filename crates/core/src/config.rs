@@ -1,20 +1,128 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use thiserror::Error;
+
+use crate::adaptive::MAX_STRENGTH;
+use crate::game::GameMode;
+use crate::normalize::Normalization;
+
+/// Environment variables read by [`Config::apply_env`], and the key each one sets.
+pub const ENV_VARS: [(&str, &str); 7] = [
+    ("TYPERPUNK_THEME", "theme"),
+    ("TYPERPUNK_MODE", "mode"),
+    ("TYPERPUNK_TIME", "time"),
+    ("TYPERPUNK_WORDS", "words"),
+    ("TYPERPUNK_TEXTS", "texts_path"),
+    ("TYPERPUNK_GHOST", "ghost"),
+    ("TYPERPUNK_ADAPTIVE", "adaptive"),
+];
+
+/// Top-level keys of the config file.
+pub const KEYS: [&str; 12] = [
+    "theme", "mode", "time", "words", "ghost", "adaptive", "adaptive_strength", "texts_path",
+    "default_text", "colors", "normalize", "auto_indent",
+];
+
+const MODES: [&str; 5] = ["passage", "code", "time", "words", "zen"];
+const THEMES: [&str; 2] = ["dark", "light"];
+const COLOR_NAMES: [&str; 17] = [
+    "reset", "black", "red", "green", "yellow", "blue", "magenta", "cyan", "gray",
+    "darkgray", "lightred", "lightgreen", "lightyellow", "lightblue", "lightmagenta",
+    "lightcyan", "white",
+];
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Config {
     pub theme: String,
     pub mode: String,
     pub time: u64,
     pub words: usize,
+    /// Mark where the personal best for the run would be while typing.
+    pub ghost: bool,
+    /// Favour passages and words that exercise the user's weakest keys.
+    pub adaptive: bool,
+    /// How hard adaptive practice leans on weak keys; 0 picks uniformly.
+    pub adaptive_strength: f64,
+    /// Extra text pack (file or directory), relative to the config file.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub texts_path: Option<PathBuf>,
+    /// Passage to fall back on when no pack could be loaded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_text: Option<String>,
+    #[serde(skip_serializing_if = "ThemeColors::is_empty")]
+    pub colors: ThemeColors,
+    /// Which characters count as typed right, as `normalize.unicode`,
+    /// `normalize.ascii_punctuation` and `normalize.strip_diacritics`.
+    pub normalize: Normalization,
+    /// In code mode, type a line's indentation automatically after Enter.
+    pub auto_indent: bool,
+}
+
+/// Per-colour overrides on top of the named theme. Values are colour names
+/// (`"Red"`, `"light blue"`), 256-colour indices or `#rrggbb`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThemeColors {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub foreground: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accent: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub success: Option<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("{path}: {message}")]
+    Io { path: String, message: String },
+    #[error("{path}:{line}:{column}: invalid JSON: {message}")]
+    Syntax { path: String, line: usize, column: usize, message: String },
+    #[error("{origin}: invalid value for `{key}`: {message}")]
+    Invalid { origin: String, key: String, message: String },
+    #[error("{origin}: unknown key `{key}`")]
+    UnknownKey { origin: String, key: String },
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             theme: "dark".to_string(),
-            mode: "time".to_string(),
+            mode: "passage".to_string(),
             time: 60,
             words: 50,
+            ghost: false,
+            adaptive: false,
+            adaptive_strength: 2.0,
+            texts_path: None,
+            default_text: None,
+            colors: ThemeColors::default(),
+            normalize: Normalization::default(),
+            auto_indent: true,
+        }
+    }
+}
+
+impl ThemeColors {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    fn slot(&mut self, name: &str) -> Option<&mut Option<String>> {
+        match name {
+            "background" => Some(&mut self.background),
+            "foreground" => Some(&mut self.foreground),
+            "accent" => Some(&mut self.accent),
+            "error" => Some(&mut self.error),
+            "success" => Some(&mut self.success),
+            _ => None,
         }
     }
 }
@@ -39,4 +147,406 @@ impl Config {
     pub fn get_words(&self) -> usize {
         self.words
     }
-} 
+
+    /// Where the TUI keeps its settings, e.g. `~/.config/typerpunk/config.json` on Linux.
+    #[cfg(feature = "dirs")]
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("typerpunk").join("config.json"))
+    }
+
+    #[cfg(not(feature = "dirs"))]
+    pub fn default_path() -> Option<PathBuf> {
+        None
+    }
+
+    /// The mode a new run starts in.
+    pub fn game_mode(&self) -> GameMode {
+        match self.mode.as_str() {
+            "time" => GameMode::Time(self.time),
+            "words" => GameMode::Words(self.words),
+            "zen" => GameMode::Zen,
+            "code" => GameMode::Programming,
+            _ => GameMode::Normal,
+        }
+    }
+
+    /// Stores `mode` so it is picked again next time; other modes keep the passage setting.
+    pub fn set_game_mode(&mut self, mode: GameMode) {
+        match mode {
+            GameMode::Time(secs) => {
+                self.mode = "time".to_string();
+                self.time = secs;
+            }
+            GameMode::Words(count) => {
+                self.mode = "words".to_string();
+                self.words = count;
+            }
+            GameMode::Zen => self.mode = "zen".to_string(),
+            GameMode::Programming => self.mode = "code".to_string(),
+            _ => self.mode = "passage".to_string(),
+        }
+    }
+
+    /// Defaults overlaid with the file at `path`. A relative `texts_path` in the
+    /// file is resolved against the file's directory.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let origin = path.display().to_string();
+        let json = fs::read_to_string(path).map_err(|e| ConfigError::Io {
+            path: origin.clone(),
+            message: e.to_string(),
+        })?;
+        let mut config = Self::default();
+        config.apply_json(&origin, &json)?;
+        if let (Some(texts), Some(dir)) = (&config.texts_path, path.parent()) {
+            if texts.is_relative() {
+                config.texts_path = Some(dir.join(texts));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Like [`Config::load`], but a missing file just means defaults.
+    pub fn load_or_default(path: &Path) -> Result<Self, ConfigError> {
+        if path.exists() {
+            Self::load(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        let io_err = |e: std::io::Error| ConfigError::Io {
+            path: path.display().to_string(),
+            message: e.to_string(),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(io_err)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(|e| ConfigError::Io {
+            path: path.display().to_string(),
+            message: e.to_string(),
+        })?;
+        fs::write(path, json + "\n").map_err(io_err)
+    }
+
+    /// Applies a JSON object of settings, validating every key. Besides the flat
+    /// layout written by [`Config::save`], this accepts the older layout where
+    /// `theme` is an object holding `name` and the colours (as in the repo's `config.json`).
+    pub fn apply_json(&mut self, origin: &str, json: &str) -> Result<(), ConfigError> {
+        let value: Value = serde_json::from_str(json).map_err(|e| ConfigError::Syntax {
+            path: origin.to_string(),
+            line: e.line(),
+            column: e.column(),
+            message: e.to_string(),
+        })?;
+        let Value::Object(map) = value else {
+            return Err(ConfigError::Invalid {
+                origin: origin.to_string(),
+                key: "(root)".to_string(),
+                message: "expected an object".to_string(),
+            });
+        };
+
+        for (key, value) in &map {
+            if !KEYS.contains(&key.as_str()) {
+                return Err(ConfigError::UnknownKey { origin: origin.to_string(), key: key.clone() });
+            }
+            match (key.as_str(), value) {
+                ("theme", Value::Object(theme)) => {
+                    for (name, value) in theme {
+                        let key = format!("theme.{}", name);
+                        let value = expect_str(origin, &key, value)?;
+                        match name.as_str() {
+                            "name" => self.set(origin, "theme", value)?,
+                            _ => self.set(origin, &format!("colors.{}", name), value).map_err(|_| {
+                                invalid_or_unknown(origin, &key, name, value)
+                            })?,
+                        }
+                    }
+                }
+                ("colors", Value::Object(colors)) => {
+                    self.apply_colors(origin, colors)?;
+                }
+                ("normalize", Value::Object(options)) => {
+                    for (name, value) in options {
+                        let key = format!("normalize.{}", name);
+                        let Value::Bool(b) = value else {
+                            return Err(ConfigError::Invalid {
+                                origin: origin.to_string(),
+                                key,
+                                message: format!("expected true or false, got {}", value),
+                            });
+                        };
+                        self.set(origin, &key, &b.to_string())?;
+                    }
+                }
+                ("time" | "words" | "adaptive_strength", Value::Number(n)) => {
+                    self.set(origin, key, &n.to_string())?;
+                }
+                ("ghost" | "adaptive" | "auto_indent", Value::Bool(b)) => {
+                    self.set(origin, key, &b.to_string())?;
+                }
+                ("texts_path" | "default_text", Value::Null) => {}
+                _ => {
+                    let value = expect_str(origin, key, value)?;
+                    self.set(origin, key, value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_colors(&mut self, origin: &str, colors: &Map<String, Value>) -> Result<(), ConfigError> {
+        for (name, value) in colors {
+            let key = format!("colors.{}", name);
+            let value = expect_str(origin, &key, value)?;
+            self.set(origin, &key, value)?;
+        }
+        Ok(())
+    }
+
+    /// Sets one key from its string form, as used for environment variables and
+    /// command-line flags. `origin` names where the value came from in errors.
+    pub fn set(&mut self, origin: &str, key: &str, value: &str) -> Result<(), ConfigError> {
+        let invalid = |message: String| ConfigError::Invalid {
+            origin: origin.to_string(),
+            key: key.to_string(),
+            message,
+        };
+        match key {
+            "theme" => {
+                let theme = value.trim().to_lowercase();
+                // The repo's sample config calls the dark theme "default"
+                let theme = if theme == "default" { "dark".to_string() } else { theme };
+                if !THEMES.contains(&theme.as_str()) {
+                    return Err(invalid(format!("expected one of {}, got {:?}", THEMES.join(", "), value)));
+                }
+                self.theme = theme;
+            }
+            "mode" => {
+                let mode = value.trim().to_lowercase();
+                let mode = match mode.as_str() {
+                    "normal" | "quote" => "passage".to_string(),
+                    "programming" => "code".to_string(),
+                    _ => mode,
+                };
+                if !MODES.contains(&mode.as_str()) {
+                    return Err(invalid(format!("expected one of {}, got {:?}", MODES.join(", "), value)));
+                }
+                self.mode = mode;
+            }
+            "time" => {
+                self.time = parse_positive(value).map_err(invalid)?;
+            }
+            "words" => {
+                self.words = parse_positive(value).map_err(invalid)?;
+            }
+            "ghost" => {
+                self.ghost = parse_bool(value).map_err(invalid)?;
+            }
+            "adaptive" => {
+                self.adaptive = parse_bool(value).map_err(invalid)?;
+            }
+            "auto_indent" => {
+                self.auto_indent = parse_bool(value).map_err(invalid)?;
+            }
+            "adaptive_strength" => {
+                self.adaptive_strength = match value.trim().parse::<f64>() {
+                    Ok(n) if (0.0..=MAX_STRENGTH).contains(&n) => n,
+                    _ => {
+                        return Err(invalid(format!(
+                            "expected a number from 0 to {}, got {:?}",
+                            MAX_STRENGTH, value
+                        )))
+                    }
+                };
+            }
+            "texts_path" => {
+                self.texts_path = Some(PathBuf::from(value));
+            }
+            "normalize.unicode" => {
+                self.normalize.unicode = parse_bool(value).map_err(invalid)?;
+            }
+            "normalize.ascii_punctuation" => {
+                self.normalize.ascii_punctuation = parse_bool(value).map_err(invalid)?;
+            }
+            "normalize.strip_diacritics" => {
+                self.normalize.strip_diacritics = parse_bool(value).map_err(invalid)?;
+            }
+            "default_text" => {
+                if value.trim().is_empty() {
+                    return Err(invalid("must not be empty".to_string()));
+                }
+                self.default_text = Some(value.to_string());
+            }
+            _ => {
+                let slot = key
+                    .strip_prefix("colors.")
+                    .and_then(|name| self.colors.slot(name))
+                    .ok_or_else(|| ConfigError::UnknownKey {
+                        origin: origin.to_string(),
+                        key: key.to_string(),
+                    })?;
+                if !is_color(value) {
+                    return Err(invalid(format!("unknown colour {:?}", value)));
+                }
+                *slot = Some(value.to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Overlays any `TYPERPUNK_*` variables from `vars` (see [`ENV_VARS`]).
+    pub fn apply_env<I>(&mut self, vars: I) -> Result<(), ConfigError>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        for (var, value) in vars {
+            if let Some((_, key)) = ENV_VARS.iter().find(|(name, _)| *name == var) {
+                self.set(&var, key, &value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn expect_str<'a>(origin: &str, key: &str, value: &'a Value) -> Result<&'a str, ConfigError> {
+    value.as_str().ok_or_else(|| ConfigError::Invalid {
+        origin: origin.to_string(),
+        key: key.to_string(),
+        message: format!("expected a string, got {}", value),
+    })
+}
+
+fn invalid_or_unknown(origin: &str, key: &str, name: &str, value: &str) -> ConfigError {
+    if ThemeColors::default().slot(name).is_some() {
+        ConfigError::Invalid {
+            origin: origin.to_string(),
+            key: key.to_string(),
+            message: format!("unknown colour {:?}", value),
+        }
+    } else {
+        ConfigError::UnknownKey { origin: origin.to_string(), key: key.to_string() }
+    }
+}
+
+fn parse_positive<T: std::str::FromStr + Default + PartialEq>(value: &str) -> Result<T, String> {
+    match value.trim().parse::<T>() {
+        Ok(n) if n != T::default() => Ok(n),
+        _ => Err(format!("expected a positive whole number, got {:?}", value)),
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(format!("expected true or false, got {:?}", value)),
+    }
+}
+
+// Mirrors the names accepted by ratatui's `Color::from_str`, so a config that
+// validates here also renders in the TUI.
+fn is_color(value: &str) -> bool {
+    let name = value
+        .to_lowercase()
+        .replace([' ', '-', '_'], "")
+        .replace("bright", "light")
+        .replace("grey", "gray");
+    COLOR_NAMES.contains(&name.as_str())
+        || value.parse::<u8>().is_ok()
+        || (value.len() == 7
+            && value.starts_with('#')
+            && u32::from_str_radix(&value[1..], 16).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repo_sample_config_loads() {
+        let mut config = Config::new();
+        config
+            .apply_json("config.json", include_str!("../../../config.json"))
+            .unwrap();
+        assert_eq!(config.theme, "dark");
+        assert_eq!(config.texts_path, Some(PathBuf::from("texts.json")));
+        assert_eq!(config.colors.accent.as_deref(), Some("Blue"));
+        // Without a mode the TUI starts on a passage
+        assert_eq!(config.game_mode(), GameMode::Normal);
+    }
+
+    #[test]
+    fn test_errors_name_the_bad_key() {
+        let mut config = Config::new();
+        let err = config
+            .apply_json("cfg.json", r#"{"mode": "time", "time": 0}"#)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "cfg.json: invalid value for `time`: expected a positive whole number, got \"0\""
+        );
+
+        let err = config
+            .apply_json("cfg.json", r#"{"colors": {"accent": "blu"}}"#)
+            .unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid { ref key, .. } if key == "colors.accent"));
+
+        let err = config.apply_json("cfg.json", r#"{"normalize": {"nfc": true}}"#).unwrap_err();
+        assert!(matches!(err, ConfigError::UnknownKey { ref key, .. } if key == "normalize.nfc"));
+
+        let err = config.apply_json("cfg.json", r#"{"speed": 3}"#).unwrap_err();
+        assert!(matches!(err, ConfigError::UnknownKey { ref key, .. } if key == "speed"));
+
+        config.apply_json("cfg.json", r#"{"adaptive": true, "adaptive_strength": 0.5}"#).unwrap();
+        assert!(config.adaptive);
+        assert_eq!(config.adaptive_strength, 0.5);
+        let err = config.apply_json("cfg.json", r#"{"adaptive_strength": -1}"#).unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid { ref key, .. } if key == "adaptive_strength"));
+        let err = config.apply_json("cfg.json", r#"{"adaptive_strength": 1e300}"#).unwrap_err();
+        assert!(matches!(err, ConfigError::Invalid { ref key, .. } if key == "adaptive_strength"));
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let mut config = Config::new();
+        config.apply_json("cfg.json", r#"{"mode": "words", "words": 25}"#).unwrap();
+        config
+            .apply_env(vec![
+                ("TYPERPUNK_MODE".to_string(), "time".to_string()),
+                ("TYPERPUNK_TIME".to_string(), "30".to_string()),
+                ("HOME".to_string(), "/ignored".to_string()),
+            ])
+            .unwrap();
+        assert_eq!(config.game_mode(), GameMode::Time(30));
+        assert_eq!(config.words, 25);
+        config.apply_env(vec![("TYPERPUNK_MODE".to_string(), "zen".to_string())]).unwrap();
+        assert_eq!(config.game_mode(), GameMode::Zen);
+        config.apply_json("cfg.json", r#"{"mode": "code", "auto_indent": false}"#).unwrap();
+        assert_eq!(config.game_mode(), GameMode::Programming);
+        assert!(!config.auto_indent);
+
+        let err = config
+            .apply_env(vec![("TYPERPUNK_WORDS".to_string(), "many".to_string())])
+            .unwrap_err();
+        assert!(err.to_string().starts_with("TYPERPUNK_WORDS: invalid value for `words`"));
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let path = std::env::temp_dir()
+            .join(format!("typerpunk-config-test-{}", std::process::id()))
+            .join("config.json");
+        let mut config = Config::new();
+        config.set_game_mode(GameMode::Words(100));
+        config.set("test", "colors.error", "#ff0000").unwrap();
+        config.apply_json("test", r#"{"normalize": {"strip_diacritics": true}}"#).unwrap();
+        assert!(config.normalize.unicode && config.normalize.strip_diacritics);
+        config.save(&path).unwrap();
+
+        let loaded = Config::load(&path).unwrap();
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+        assert_eq!(loaded, config);
+    }
+}
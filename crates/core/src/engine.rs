@@ -0,0 +1,905 @@
+//! The typing engine: the text, what has been typed against it, the backspace
+//! rules, the clock and the stats. The TUI and both wasm front-ends drive this
+//! one type, so a run plays out the same wherever it is typed.
+//!
+//! Positions are counted in grapheme clusters (see [`graphemes`](crate::graphemes)),
+//! so a backspace takes off a whole accented letter or emoji. Keystrokes are still
+//! logged per char, with deletions recording how many chars they removed.
+//!
+//! The input is kept in the form the [`Normalization`] settles on: composed when
+//! equivalent encodings count as equal, and with the real character in place of
+//! an accepted ASCII stand-in. The log records that form, so replaying it types
+//! the text exactly.
+//!
+//! Code snippets are typed line by line: Enter types the line break, Tab the
+//! indentation at the cursor, and with [auto-indent](Engine::set_auto_indent)
+//! the indentation of each new line is skipped: it is filled in without counting
+//! as keystrokes and logged as [`KeyAction::Indent`].
+//!
+//! A [`GameMode::Zen`] run has no text: anything typed counts, and the run
+//! ends when it is [stopped](Engine::stop) rather than when a text is done.
+
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+use crate::game::GameMode;
+use crate::graphemes;
+use crate::keystats::KeyStats;
+use crate::metrics::{Metrics, Second};
+use crate::normalize::Normalization;
+use crate::replay::{KeyAction, KeystrokeLog};
+use crate::stats::Stats;
+use crate::words::{self, CharCounts, WordResult};
+
+/// Columns between tab stops, for [`Engine::type_indent`].
+pub const TAB_WIDTH: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct Engine {
+    mode: GameMode,
+    text: String,
+    input: String,
+    stats: Stats,
+    keystrokes: KeystrokeLog,
+    normalization: Normalization,
+    // Rest of a multi-key ASCII stand-in still to come, e.g. ".." after the
+    // first dot of an ellipsis
+    stand_in: &'static str,
+    // Every position typed wrong at some point, fixed since or not
+    mistakes: BTreeSet<usize>,
+    auto_indent: bool,
+}
+
+impl Engine {
+    pub fn new(mode: GameMode, text: &str) -> Self {
+        Self::with_normalization(mode, text, Normalization::default())
+    }
+
+    pub fn with_normalization(mode: GameMode, text: &str, normalization: Normalization) -> Self {
+        let text = normalization.prepare(text);
+        let mut stats = Stats::new();
+        stats.set_time_limit(time_limit(mode));
+        Self {
+            mode,
+            keystrokes: KeystrokeLog::new(mode, &text),
+            text,
+            input: String::new(),
+            stats,
+            normalization,
+            stand_in: "",
+            mistakes: BTreeSet::new(),
+            auto_indent: false,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub fn mode(&self) -> GameMode {
+        self.mode
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    pub fn normalization(&self) -> Normalization {
+        self.normalization
+    }
+
+    /// Every keystroke of the run, for saving and replaying it.
+    pub fn keystroke_log(&self) -> &KeystrokeLog {
+        &self.keystrokes
+    }
+
+    /// Per-key and per-bigram accuracy and latency of the run.
+    pub fn key_stats(&self) -> &KeyStats {
+        self.stats.key_stats()
+    }
+
+    /// Starts a fresh run on `text`.
+    pub fn set_text(&mut self, text: &str) {
+        self.restart(self.mode, text);
+    }
+
+    /// Starts a fresh run on the same text in `mode`.
+    pub fn set_mode(&mut self, mode: GameMode) {
+        self.restart(mode, &self.text.clone());
+    }
+
+    /// Starts a fresh run on the same text under `normalization`. Stripped
+    /// accents stay stripped.
+    pub fn set_normalization(&mut self, normalization: Normalization) {
+        self.normalization = normalization;
+        self.restart(self.mode, &self.text.clone());
+    }
+
+    /// Whether a correct line break also skips the indentation that starts the
+    /// next line. Kept across restarts.
+    pub fn set_auto_indent(&mut self, auto_indent: bool) {
+        self.auto_indent = auto_indent;
+    }
+
+    // Starts over on `text` in `mode`, keeping the typing options
+    fn restart(&mut self, mode: GameMode, text: &str) {
+        let auto_indent = self.auto_indent;
+        *self = Self::with_normalization(mode, text, self.normalization);
+        self.auto_indent = auto_indent;
+    }
+
+    /// Appends more text without touching the run, so a timed run never runs
+    /// out of words before the deadline.
+    pub fn append_text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        if !self.text.is_empty() && !self.text.ends_with(char::is_whitespace) {
+            self.text.push(' ');
+        }
+        self.text.push_str(&self.normalization.prepare(text));
+        self.keystrokes.target = self.text.clone();
+    }
+
+    /// Starts the clock. Otherwise it starts with the first typed character.
+    pub fn start(&mut self) {
+        self.stats.start();
+    }
+
+    /// Stops the clock, e.g. when a race ends before the text does. A timed run
+    /// stopped after its deadline is scored on the deadline, and a zen run ends.
+    pub fn stop(&mut self) {
+        if self.stats.is_running() {
+            self.stats.stop();
+        }
+    }
+
+    /// Types `ch`. Returns false, leaving the input alone, once the run is over.
+    pub fn type_char(&mut self, ch: char) -> bool {
+        if self.is_finished() {
+            return false;
+        }
+        if !self.stats.is_started() {
+            self.stats.start();
+        } else if !self.stats.is_running() {
+            return false;
+        }
+        if let Some(rest) = self.stand_in.strip_prefix(ch) {
+            self.stand_in = rest;
+            return true;
+        }
+        // The key due is the one that starts the next cluster of the text. Taken by
+        // cluster, so a mistake on a multi-char cluster doesn't shift later keys.
+        let position = graphemes::count(&self.input);
+        let expected = graphemes::graphemes(&self.text).get(position).and_then(|g| g.chars().next());
+        let (ch, stand_in) = self.normalization.substitute(expected, ch);
+        self.stand_in = stand_in;
+
+        let mut input = self.input.clone();
+        input.push(ch);
+        let input = self.normalization.canonical(&input);
+        let typed = graphemes::graphemes(&input);
+        let at = typed.len() - 1;
+        let correct = self.is_zen()
+            || graphemes::graphemes(&self.text)
+                .get(at)
+                .is_some_and(|expected| self.normalization.is_typing(typed[at], expected));
+        // Key stats go by the key that starts a character; an accent added to
+        // it only counts towards accuracy. Free typing has no keys to miss.
+        if self.is_zen() {
+            self.stats.note_keypress(true);
+        } else if at >= position {
+            self.stats.note_key(position, expected, if correct { expected.unwrap_or(ch) } else { ch });
+        } else {
+            self.stats.note_keypress(correct);
+        }
+        if !correct {
+            self.mistakes.insert(at);
+        }
+        self.replace_input(input);
+        if ch == '\n' && correct && self.auto_indent {
+            self.type_indentation();
+        }
+        true
+    }
+
+    /// Tab: types the spaces the text has from the cursor to the next tab stop,
+    /// or a tab character where the text has no spaces.
+    pub fn type_indent(&mut self) -> bool {
+        let text = graphemes::graphemes(&self.text);
+        let at = graphemes::count(&self.input);
+        let column = text[..at.min(text.len())].iter().rev().take_while(|&&g| g != "\n").count();
+        let spaces = text
+            .get(at..)
+            .unwrap_or_default()
+            .iter()
+            .take(TAB_WIDTH - column % TAB_WIDTH)
+            .take_while(|&&g| g == " ")
+            .count();
+        if spaces == 0 {
+            return self.type_char('\t');
+        }
+        (0..spaces).all(|_| self.type_char(' '))
+    }
+
+    // Skips the spaces and tabs at the cursor. Nobody pressed those keys, so they
+    // go into the input without touching the keystroke stats
+    fn type_indentation(&mut self) {
+        let at = graphemes::count(&self.input);
+        let indentation: String = graphemes::graphemes(&self.text)
+            .get(at..)
+            .unwrap_or_default()
+            .iter()
+            .take_while(|&&g| g == " " || g == "\t")
+            .copied()
+            .collect();
+        if indentation.is_empty() {
+            return;
+        }
+        for ch in indentation.chars() {
+            self.log(KeyAction::Indent { ch });
+        }
+        self.input.push_str(&indentation);
+        self.update();
+    }
+
+    // Moves the input to `input`, logged as the fewest keystrokes that get there
+    fn replace_input(&mut self, input: String) {
+        let common = self.input.chars().zip(input.chars()).take_while(|(a, b)| a == b).count();
+        let removed = self.input.chars().count() - common;
+        if removed > 0 {
+            self.log(KeyAction::Backspace { removed });
+        }
+        for ch in input.chars().skip(common) {
+            self.log(KeyAction::Insert { ch });
+        }
+        self.input = input;
+        self.update();
+    }
+
+    /// Backspace, or with `word` a word delete (Ctrl+Backspace). Returns whether
+    /// anything was deleted.
+    ///
+    /// Within the current word both always work. Going back past the start of the
+    /// word is only allowed with a mistake somewhere before it: a backspace then
+    /// steps back a character, and a word delete jumps back to the start of the
+    /// latest word with a mistake in it, one such word at a time.
+    pub fn handle_backspace(&mut self, word: bool) -> bool {
+        if !self.can_backspace() {
+            return false;
+        }
+        let clusters = graphemes::graphemes(&self.input);
+        let length = clusters.len();
+        let word_start = graphemes::word_start(&clusters);
+        let keep = if !word {
+            if !self.can_backspace_to(length - 1) {
+                return false;
+            }
+            length - 1
+        } else if word_start < length {
+            word_start
+        } else {
+            match self.last_error_before(word_start) {
+                Some(error) => graphemes::word_start(&clusters[..error]),
+                None => return false,
+            }
+        };
+        self.stand_in = "";
+        let kept = graphemes::prefix(&self.input, keep).len();
+        let removed = self.input[kept..].chars().count();
+        self.input.truncate(kept);
+        self.log(if word { KeyAction::DeleteWord { removed } } else { KeyAction::Backspace { removed } });
+        self.update();
+        true
+    }
+
+    pub fn can_backspace(&self) -> bool {
+        !self.is_finished() && !self.input.is_empty()
+    }
+
+    /// Whether a backspace could delete back to `position`: always within the
+    /// current word, and into earlier words only past a mistake.
+    pub fn can_backspace_to(&self, position: usize) -> bool {
+        position < graphemes::count(&self.input) && self.may_delete_to(position)
+    }
+
+    fn may_delete_to(&self, position: usize) -> bool {
+        let word_start = self.current_word_start();
+        self.is_zen() || position >= word_start || self.last_error_before(word_start).is_some()
+    }
+
+    /// Replaces the whole input, for front-ends that report the input box rather
+    /// than single keys. Deletions follow the backspace rules and are refused,
+    /// with the input left alone, if those would not allow them. Returns whether
+    /// the new input was taken.
+    pub fn set_input(&mut self, input: &str) -> bool {
+        if self.is_finished() {
+            return false;
+        }
+        self.stand_in = "";
+        let input = &self.normalization.canonical(input);
+        // Compared by char, so adding a combining mark to the last letter is a
+        // keystroke rather than a retype of the letter
+        let common = self.input.chars().zip(input.chars()).take_while(|(a, b)| a == b).count();
+        let removed = self.input.chars().count() - common;
+        if removed > 0 {
+            let kept: String = self.input.chars().take(common).collect();
+            if !self.may_delete_to(graphemes::count(&kept)) {
+                return false;
+            }
+            self.input = kept;
+            self.log(if removed == 1 { KeyAction::Backspace { removed } } else { KeyAction::DeleteWord { removed } });
+            self.update();
+        }
+        for ch in input.chars().skip(common) {
+            if !self.type_char(ch) {
+                break;
+            }
+        }
+        true
+    }
+
+    /// Whether the text is done: typed out, or in a word-count run every word
+    /// committed, typos and all. A zen run is done once stopped after typing.
+    pub fn is_complete(&self) -> bool {
+        match self.mode {
+            GameMode::Zen => self.stats.is_started() && !self.stats.is_running(),
+            GameMode::Words(_) => {
+                let total = self.text.split_whitespace().count();
+                total > 0 && self.words_committed() >= total
+            }
+            _ => !self.input.is_empty() && self.input.trim() == self.text.trim(),
+        }
+    }
+
+    /// Whether the run is over, because the text is done or the time is up.
+    pub fn is_finished(&self) -> bool {
+        self.is_complete() || self.is_time_up()
+    }
+
+    /// Deadline of the run, only set in timed mode.
+    pub fn time_limit(&self) -> Option<Duration> {
+        time_limit(self.mode)
+    }
+
+    /// Time left before the deadline in timed mode. Before the clock starts this
+    /// is the full duration.
+    pub fn time_remaining(&self) -> Option<Duration> {
+        self.time_limit().map(|limit| limit.saturating_sub(self.elapsed()))
+    }
+
+    pub fn is_time_up(&self) -> bool {
+        self.stats.is_started() && self.time_remaining() == Some(Duration::ZERO)
+    }
+
+    /// Time on the clock, never past the deadline of a timed run.
+    pub fn elapsed(&self) -> Duration {
+        self.stats.elapsed_time()
+    }
+
+    /// Net WPM. The figures are defined in [`metrics`](crate::metrics).
+    pub fn wpm(&self) -> f64 {
+        self.stats.wpm()
+    }
+
+    pub fn raw_wpm(&self) -> f64 {
+        self.stats.get_raw_wpm()
+    }
+
+    pub fn cpm(&self) -> f64 {
+        self.stats.get_cpm()
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        self.stats.accuracy()
+    }
+
+    /// How even the pace has been from second to second.
+    pub fn consistency(&self) -> f64 {
+        self.stats.get_consistency()
+    }
+
+    /// Fastest net WPM over a whole second.
+    pub fn burst(&self) -> f64 {
+        self.stats.get_burst()
+    }
+
+    /// Raw and net WPM and errors for each second so far.
+    pub fn timeline(&self) -> Vec<Second> {
+        self.stats.timeline()
+    }
+
+    /// Every figure of the run so far.
+    pub fn metrics(&self) -> Metrics {
+        Metrics {
+            net_wpm: self.wpm(),
+            raw_wpm: self.raw_wpm(),
+            cpm: self.cpm(),
+            accuracy: self.accuracy(),
+            consistency: self.consistency(),
+            burst: self.burst(),
+            errors: self.errors(),
+            elapsed: self.elapsed().as_secs_f64(),
+        }
+    }
+
+    /// Keystrokes that were wrong when typed, including ones fixed afterwards.
+    pub fn errors(&self) -> usize {
+        self.stats.get_keystroke_errors()
+    }
+
+    /// How far through the text the cursor is, as a percentage.
+    pub fn progress(&self) -> f64 {
+        let total = graphemes::count(&self.text);
+        if self.input.is_empty() || total == 0 {
+            return 0.0;
+        }
+        graphemes::count(&self.input) as f64 / total as f64 * 100.0
+    }
+
+    /// Number of words typed so far, right or wrong.
+    pub fn word_count(&self) -> usize {
+        self.stats.get_total_words()
+    }
+
+    /// Number of words committed so far, for word-count runs.
+    pub fn words_committed(&self) -> usize {
+        words::committed_words(&self.input, &self.text)
+    }
+
+    pub fn word_results(&self) -> Vec<WordResult> {
+        words::word_results(&self.input, &self.text)
+    }
+
+    /// Correct, incorrect, extra and missed characters of the input.
+    pub fn char_counts(&self) -> CharCounts {
+        words::char_counts(&self.input, &self.text)
+    }
+
+    /// Positions typed wrong during the run, including those fixed since.
+    pub fn mistake_positions(&self) -> Vec<usize> {
+        self.mistakes.iter().copied().collect()
+    }
+
+    /// Position where the word being typed starts.
+    pub fn current_word_start(&self) -> usize {
+        graphemes::word_start(&graphemes::graphemes(&self.input))
+    }
+
+    // Latest position before `end` typed wrong, counting anything past the end
+    // of the text
+    fn last_error_before(&self, end: usize) -> Option<usize> {
+        let text = graphemes::graphemes(&self.text);
+        let typed = graphemes::graphemes(&self.input);
+        (0..end.min(typed.len())).rev().find(|&i| text.get(i) != Some(&typed[i]))
+    }
+
+    fn log(&mut self, action: KeyAction) {
+        self.keystrokes.push(self.stats.elapsed_time(), action);
+    }
+
+    fn is_zen(&self) -> bool {
+        self.mode == GameMode::Zen
+    }
+
+    fn update(&mut self) {
+        if self.is_zen() {
+            self.stats.update_free(&self.input);
+        } else {
+            self.stats.update(&self.input, &self.text);
+        }
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new(GameMode::default(), "")
+    }
+}
+
+fn time_limit(mode: GameMode) -> Option<Duration> {
+    match mode {
+        GameMode::Time(secs) => Some(Duration::from_secs(secs)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics;
+
+    fn typed(text: &str, input: &str) -> Engine {
+        let mut engine = Engine::new(GameMode::Normal, text);
+        for ch in input.chars() {
+            engine.type_char(ch);
+        }
+        engine
+    }
+
+    #[test]
+    fn test_backspace_within_current_word() {
+        let mut engine = typed("Hello world", "Hello");
+        assert!(engine.handle_backspace(false));
+        assert_eq!(engine.input(), "Hell");
+    }
+
+    #[test]
+    fn test_backspace_to_previous_word_with_error() {
+        let mut engine = typed("Hello world", "Hallo wo");
+        assert!(engine.handle_backspace(false));
+        assert!(engine.handle_backspace(false));
+        // Back over the space into the word with the mistake
+        assert!(engine.handle_backspace(false));
+        assert_eq!(engine.input(), "Hallo");
+    }
+
+    #[test]
+    fn test_backspace_to_previous_word_without_error() {
+        let mut engine = typed("Hello world", "Hello ");
+        assert!(!engine.handle_backspace(false));
+        assert_eq!(engine.input(), "Hello ");
+        assert!(!engine.handle_backspace(true));
+    }
+
+    #[test]
+    fn test_ctrl_backspace() {
+        let mut engine = typed("Hello world test", "Hallo world te");
+        // The word being typed goes first
+        assert!(engine.handle_backspace(true));
+        assert_eq!(engine.input(), "Hallo world ");
+
+        // Then back to the word with the mistake, over the correct one between
+        assert!(engine.handle_backspace(true));
+        assert_eq!(engine.input(), "");
+        assert!(!engine.handle_backspace(true));
+
+        // A finished run takes no more deletes
+        let mut done = typed("Hello world test", "Hello world test");
+        assert!(done.is_finished());
+        assert!(!done.handle_backspace(true));
+    }
+
+    #[test]
+    fn test_ctrl_backspace_one_error_word_at_a_time() {
+        let mut engine = typed("foo bar baz qux", "fao bar bzz ");
+        assert!(engine.handle_backspace(true));
+        assert_eq!(engine.input(), "fao bar ");
+        assert!(engine.handle_backspace(true));
+        assert_eq!(engine.input(), "");
+        assert_eq!(engine.keystroke_log().input_after(engine.keystroke_log().events.len()), "");
+    }
+
+    #[test]
+    fn test_game_completion_detection() {
+        let mut engine = typed("Hello world", "Hello worl");
+        assert!(!engine.is_finished());
+        assert!(engine.type_char('d'));
+        assert!(engine.is_finished());
+        // Nothing more is taken once the text is done
+        assert!(!engine.type_char('x'));
+        assert_eq!(engine.input(), "Hello world");
+    }
+
+    #[test]
+    fn test_error_counting() {
+        let mut engine = typed("Hello world", "Ha");
+        assert_eq!(engine.errors(), 1);
+
+        // Fixing a mistake does not take it back
+        engine.handle_backspace(false);
+        engine.type_char('e');
+        assert_eq!(engine.errors(), 1);
+        assert!(engine.stats().get_error_positions().is_empty());
+        assert_eq!(engine.mistake_positions(), vec![1]);
+
+        // Characters past the end of a word are mistakes too
+        for ch in "llox".chars() {
+            engine.type_char(ch);
+        }
+        assert_eq!(engine.errors(), 2);
+        assert_eq!(engine.stats().get_error_positions(), vec![5]);
+        assert_eq!(engine.mistake_positions(), vec![1, 5]);
+        assert_eq!(engine.char_counts().extra, 1);
+        assert!(engine.accuracy() < 100.0);
+    }
+
+    #[test]
+    fn test_set_input_follows_the_rules() {
+        let mut engine = Engine::new(GameMode::Normal, "Hello world");
+        assert!(engine.set_input("Hello w"));
+        // Deleting back into a correct word is refused as a whole
+        assert!(!engine.set_input("Hel"));
+        assert_eq!(engine.input(), "Hello w");
+        assert!(engine.set_input("Hello wz"));
+        assert!(engine.set_input("Hello world"));
+        assert!(engine.is_finished());
+
+        let actions: Vec<KeyAction> = engine.keystroke_log().events.iter().map(|e| e.action).collect();
+        assert_eq!(actions.iter().filter(|a| matches!(a, KeyAction::Backspace { removed: 1 })).count(), 1);
+        assert_eq!(engine.errors(), 1);
+    }
+
+    #[test]
+    fn test_time_mode_finishes_on_deadline() {
+        let mut engine = Engine::new(GameMode::Time(30), "Hello world");
+        engine.start();
+        engine.set_input("Hel");
+        assert!(!engine.is_finished());
+
+        // Pretend the run started 31 seconds ago
+        engine.stats.start_at(std::time::Instant::now() - Duration::from_secs(31));
+        assert!(engine.is_finished());
+        assert_eq!(engine.elapsed(), Duration::from_secs(30));
+        assert_eq!(engine.time_remaining(), Some(Duration::ZERO));
+
+        // Input after the deadline is ignored
+        assert!(!engine.type_char('l'));
+        assert_eq!(engine.input(), "Hel");
+        assert!(!engine.can_backspace());
+    }
+
+    #[test]
+    fn test_append_text_keeps_progress() {
+        let mut engine = typed("Hello", "Hell");
+        engine.append_text("world");
+        assert_eq!(engine.text(), "Hello world");
+        assert_eq!(engine.keystroke_log().target, "Hello world");
+        for ch in "o w".chars() {
+            engine.type_char(ch);
+        }
+        assert_eq!(engine.input(), "Hello w");
+        assert!(!engine.is_finished());
+        assert!(engine.stats().get_error_positions().is_empty());
+    }
+
+    #[test]
+    fn test_words_mode_finishes_on_last_word() {
+        let mut engine = Engine::new(GameMode::Words(3), "one two three");
+        engine.set_input("one twp thre");
+        assert!(!engine.is_finished());
+        assert_eq!(engine.words_committed(), 2);
+
+        // Committing the last word with a space finishes even with typos
+        engine.set_input("one twp thre ");
+        assert!(engine.is_finished());
+        let results = engine.word_results();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results.iter().filter(|r| r.correct).count(), 1);
+    }
+
+    #[test]
+    fn test_code_indentation() {
+        let code = "if x {\n    y();\n}";
+        let mut engine = Engine::new(GameMode::Programming, code);
+        engine.set_auto_indent(true);
+        for ch in "if x {\n".chars() {
+            engine.type_char(ch);
+        }
+        // The next line's indentation comes with the line break
+        assert_eq!(engine.input(), "if x {\n    ");
+        for ch in "y();\n}".chars() {
+            engine.type_char(ch);
+        }
+        assert!(engine.is_finished());
+        assert_eq!(engine.errors(), 0);
+        // Only the keys pressed count: the four spaces were skipped
+        let pressed = "if x {\ny();\n}".chars().count();
+        let log = engine.keystroke_log();
+        assert_eq!(log.events.iter().filter(|e| matches!(e.action, KeyAction::Insert { .. })).count(), pressed);
+        assert_eq!(log.events.iter().filter(|e| matches!(e.action, KeyAction::Indent { .. })).count(), 4);
+        assert_eq!(log.input_after(log.events.len()), code);
+        engine.stop();
+        assert_eq!(engine.raw_wpm(), metrics::raw_wpm(pressed, engine.elapsed()));
+        assert_eq!(engine.accuracy(), 100.0);
+
+        // Without it, Tab types up to the next tab stop
+        let mut engine = Engine::new(GameMode::Programming, "a\n      b");
+        for ch in "a\n".chars() {
+            engine.type_char(ch);
+        }
+        assert!(engine.type_indent());
+        assert_eq!(engine.input(), "a\n    ");
+        assert!(engine.type_indent());
+        assert_eq!(engine.input(), "a\n      ");
+        engine.type_indent();
+        assert_eq!(engine.input(), "a\n      \t");
+        assert_eq!(engine.errors(), 1);
+    }
+
+    #[test]
+    fn test_zen_types_freely_until_stopped() {
+        let mut engine = typed("", "");
+        engine.set_mode(GameMode::Zen);
+        for ch in "free words here".chars() {
+            engine.type_char(ch);
+        }
+        assert!(!engine.is_finished());
+        assert_eq!(engine.word_count(), 3);
+        assert_eq!(engine.errors(), 0);
+        assert_eq!(engine.accuracy(), 100.0);
+
+        // Anything can be taken back, across words too
+        assert!(engine.handle_backspace(true));
+        assert!(engine.handle_backspace(true));
+        assert!(engine.handle_backspace(false));
+        assert_eq!(engine.input(), "free");
+        assert_eq!(engine.stats().get_error_positions(), Vec::<usize>::new());
+
+        engine.stop();
+        assert!(engine.is_finished());
+        assert!(!engine.type_char('x'));
+        assert!(engine.wpm() > 0.0);
+    }
+
+    #[test]
+    fn test_graphemes_type_and_delete_whole() {
+        let engine = typed("ne\u{301}e 👩‍👩‍👧", "ne\u{301}e 👩‍👩‍👧");
+        assert!(engine.is_complete());
+        assert_eq!(engine.progress(), 100.0);
+
+        let mut engine = typed("ne\u{301}e 👩‍👩‍👧 x", "ne\u{301}e 👩‍👩‍👧");
+        assert_eq!(engine.current_word_start(), 4);
+        assert!(engine.handle_backspace(false));
+        assert_eq!(engine.input(), "n\u{e9}e ");
+
+        // A combining mark typed on its own joins the letter before it
+        let mut engine = typed("ne\u{301}es", "ne");
+        assert!(engine.set_input("ne\u{301}"));
+        assert_eq!(engine.progress(), 50.0);
+        assert!(engine.handle_backspace(false));
+        assert_eq!(engine.input(), "n");
+        assert_eq!(engine.keystroke_log().position_at(Duration::from_secs(60)), 1);
+    }
+
+    #[test]
+    fn test_normalization() {
+        // A decomposed accent typed for a precomposed one, and the other way round
+        let engine = typed("caf\u{e9} ne\u{301}e", "cafe\u{301} n\u{e9}e");
+        assert!(engine.is_complete());
+        assert_eq!(engine.errors(), 0);
+        assert_eq!(engine.keystroke_log().input_after(usize::MAX), engine.text());
+
+        let mut exact = Engine::with_normalization(GameMode::Normal, "caf\u{e9}", Normalization::EXACT);
+        for ch in "cafe\u{301}".chars() {
+            exact.type_char(ch);
+        }
+        assert!(!exact.is_complete());
+
+        let ascii = Normalization { ascii_punctuation: true, ..Normalization::default() };
+        let mut engine = Engine::with_normalization(GameMode::Normal, "\u{201c}Wait\u{2026}\u{201d} \u{2014} no", ascii);
+        for ch in "\"Wait...\" - no".chars() {
+            engine.type_char(ch);
+        }
+        assert!(engine.is_complete());
+        assert_eq!(engine.errors(), 0);
+
+        let strip = Normalization { strip_diacritics: true, ..Normalization::default() };
+        let engine = Engine::with_normalization(GameMode::Normal, "Ana\u{ef}s na\u{ef}ve", strip);
+        assert_eq!(engine.text(), "Anais naive");
+    }
+
+    mod props {
+        use proptest::prelude::*;
+
+        use super::*;
+
+        // Arbitrary Unicode, salted with clusters made of several code points
+        fn text() -> impl Strategy<Value = String> {
+            let piece = prop_oneof![
+                any::<char>().prop_map(String::from),
+                Just("é".to_string()),
+                Just("e\u{301}".to_string()),
+                Just("日本".to_string()),
+                Just("👩‍👩‍👧".to_string()),
+                Just("🇫🇷".to_string()),
+                Just(" ".to_string()),
+                Just("\u{301}".to_string()),
+            ];
+            prop::collection::vec(piece, 0..16).prop_map(|pieces| pieces.concat())
+        }
+
+        #[derive(Debug, Clone)]
+        enum Op {
+            Type(char),
+            Backspace,
+            DeleteWord,
+            SetInput(String),
+        }
+
+        fn op() -> impl Strategy<Value = Op> {
+            prop_oneof![
+                4 => text().prop_flat_map(|s| {
+                    let chars: Vec<char> = s.chars().chain(Some('x')).collect();
+                    prop::sample::select(chars).prop_map(Op::Type)
+                }),
+                2 => Just(Op::Backspace),
+                1 => Just(Op::DeleteWord),
+                1 => text().prop_map(Op::SetInput),
+            ]
+        }
+
+        proptest! {
+            #[test]
+            fn typing_the_text_completes_it(text in text()) {
+                prop_assume!(!text.trim().is_empty());
+                let engine = typed(&text, &text);
+                prop_assert!(engine.is_complete());
+                prop_assert!(engine.text().starts_with(engine.input()));
+                prop_assert_eq!(engine.errors(), 0);
+                prop_assert!(engine.stats().get_error_positions().is_empty());
+            }
+
+            #[test]
+            fn any_keys_keep_the_engine_consistent(text in text(), ops in prop::collection::vec(op(), 0..40)) {
+                let mut engine = Engine::new(GameMode::Normal, &text);
+                for op in ops {
+                    let before = graphemes::count(engine.input());
+                    match op {
+                        Op::Type(ch) => {
+                            engine.type_char(ch);
+                        }
+                        Op::Backspace => {
+                            if engine.handle_backspace(false) {
+                                prop_assert_eq!(graphemes::count(engine.input()), before - 1);
+                            }
+                        }
+                        Op::DeleteWord => {
+                            if engine.handle_backspace(true) {
+                                prop_assert!(graphemes::count(engine.input()) < before);
+                            }
+                        }
+                        Op::SetInput(input) => {
+                            let old = engine.input().to_string();
+                            if engine.set_input(&input) {
+                                let input = engine.normalization().canonical(&input);
+                                prop_assert!(input.starts_with(engine.input()));
+                            } else {
+                                prop_assert_eq!(engine.input(), old.as_str());
+                            }
+                        }
+                    }
+                    let length = graphemes::count(engine.input());
+                    prop_assert!(engine.current_word_start() <= length);
+                    prop_assert!(engine.stats().get_error_positions().iter().all(|&p| p < length));
+                    prop_assert!((0.0..=100.0).contains(&engine.accuracy()));
+                    let log = engine.keystroke_log();
+                    prop_assert_eq!(log.input_after(log.events.len()), engine.input());
+                }
+            }
+
+            #[test]
+            fn key_stats_follow_clusters_after_mistakes(
+                pieces in prop::collection::vec(
+                    prop::sample::select(vec!["a", "o", " ", "é", "e\u{301}", "👍🏽", "日本", "👩‍👩‍👧", "🇫🇷"]),
+                    1..16,
+                ),
+                wrong in prop::collection::vec(any::<bool>(), 16),
+            ) {
+                // Ends on a letter so the run isn't over before every cluster is typed
+                let mut engine = Engine::new(GameMode::Normal, &format!("{}o", pieces.concat()));
+                let clusters: Vec<String> =
+                    graphemes::graphemes(engine.text()).iter().map(|g| g.to_string()).collect();
+                let mut hits = 0;
+                for (i, cluster) in clusters.iter().enumerate() {
+                    if wrong[i % wrong.len()] {
+                        engine.type_char('x');
+                    } else {
+                        cluster.chars().for_each(|ch| { engine.type_char(ch); });
+                        hits += 1;
+                    }
+                }
+                // Every key is judged against the start of its own cluster
+                let starts: BTreeSet<char> = clusters.iter().filter_map(|c| c.chars().next()).collect();
+                let keys = &engine.key_stats().keys;
+                prop_assert!(keys.keys().all(|key| starts.contains(key)));
+                prop_assert_eq!(keys.values().map(|k| k.hits as usize).sum::<usize>(), hits);
+                prop_assert_eq!(keys.values().map(|k| k.attempts() as usize).sum::<usize>(), clusters.len());
+            }
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Text as a reader sees it: grapheme clusters rather than bytes or chars, so an
+//! accented letter, a CJK character or an emoji family counts as one character
+//! however many code points it is made of.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The grapheme clusters of `text`.
+pub fn graphemes(text: &str) -> Vec<&str> {
+    text.graphemes(true).collect()
+}
+
+/// Number of grapheme clusters in `text`.
+pub fn count(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+/// The first `n` grapheme clusters of `text`, or all of it if it is shorter.
+pub fn prefix(text: &str, n: usize) -> &str {
+    match text.grapheme_indices(true).nth(n) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+/// Whether a cluster is whitespace, e.g. a space or a `\r\n` line break.
+pub fn is_whitespace(grapheme: &str) -> bool {
+    grapheme.chars().next().is_some_and(char::is_whitespace)
+}
+
+/// Index of the cluster where the last word of `clusters` starts: just past the
+/// last whitespace that follows a word.
+pub fn word_start(clusters: &[&str]) -> usize {
+    let mut start = 0;
+    let mut in_word = false;
+    for (i, grapheme) in clusters.iter().enumerate() {
+        if is_whitespace(grapheme) {
+            if in_word {
+                start = i + 1;
+            }
+            in_word = false;
+        } else {
+            in_word = true;
+        }
+    }
+    start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clusters_are_what_a_reader_sees() {
+        // A decomposed é, a family emoji and a flag are one cluster each
+        let text = "ne\u{301}e 👩‍👩‍👧 🇫🇷日本";
+        assert_eq!(count(text), 9);
+        assert_eq!(prefix(text, 2), "ne\u{301}");
+        assert_eq!(prefix(text, 99), text);
+        assert_eq!(word_start(&graphemes(text)), 6);
+        assert!(is_whitespace("\r\n"));
+    }
+}
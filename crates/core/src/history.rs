@@ -0,0 +1,485 @@
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::game::GameMode;
+use crate::keystats::KeyStats;
+use crate::metrics::{Second, CHARS_PER_WORD};
+use crate::replay::KeystrokeLog;
+
+/// One completed run, as stored in the history file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub timestamp: DateTime<Utc>,
+    pub mode: GameMode,
+    /// `Text::id` of the passage; generated word runs and mixed timed runs have none.
+    #[serde(default)]
+    pub text_id: Option<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+    pub wpm: f64,
+    pub raw_wpm: f64,
+    pub accuracy: f64,
+    pub errors: usize,
+    pub duration_secs: f64,
+    /// WPM sampled once per second of the run.
+    #[serde(default)]
+    pub wpm_samples: Vec<u64>,
+    /// Raw and net WPM and errors for each second of the run.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub timeline: Vec<Second>,
+    #[serde(default)]
+    pub consistency: f64,
+    #[serde(default)]
+    pub burst: f64,
+    /// Per-key and per-bigram results of the run.
+    #[serde(default, skip_serializing_if = "KeyStats::is_empty")]
+    pub keys: KeyStats,
+    /// File name of the run's keystroke log in the replay directory.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replay: Option<String>,
+}
+
+impl RunRecord {
+    /// Mode name as used in settings and on the command line.
+    pub fn mode_name(&self) -> &'static str {
+        mode_name(self.mode)
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.duration_secs.max(0.0))
+    }
+
+    /// Correct characters this run had typed `elapsed` into it, following its
+    /// per-second WPM samples (five characters to a word). Used to race a ghost.
+    pub fn chars_at(&self, elapsed: Duration) -> f64 {
+        let t = elapsed.as_secs_f64().min(self.duration_secs).max(0.0);
+        let whole = t.floor() as usize;
+        let sample = |i: usize| -> f64 {
+            if i == 0 {
+                return self.wpm_samples.first().copied().unwrap_or(0) as f64;
+            }
+            self.wpm_samples.get(i - 1).map(|&w| w as f64).unwrap_or(self.wpm)
+        };
+        let wpm = if whole >= self.wpm_samples.len() {
+            self.wpm
+        } else {
+            let frac = t - whole as f64;
+            sample(whole) + (sample(whole + 1) - sample(whole)) * frac
+        };
+        wpm * CHARS_PER_WORD * t / 60.0
+    }
+}
+
+/// What a personal best is kept for: the exact mode, including its duration or
+/// word count, and the passage category.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PbKey {
+    pub mode: String,
+    pub category: Option<String>,
+}
+
+impl PbKey {
+    pub fn new(mode: GameMode, category: Option<&str>) -> Self {
+        let mode = match mode {
+            GameMode::Time(secs) => format!("time {}s", secs),
+            GameMode::Words(count) => format!("words {}", count),
+            other => mode_name(other).to_string(),
+        };
+        Self { mode, category: category.map(str::to_string) }
+    }
+
+    pub fn of(run: &RunRecord) -> Self {
+        Self::new(run.mode, run.category.as_deref())
+    }
+}
+
+pub fn mode_name(mode: GameMode) -> &'static str {
+    match mode {
+        GameMode::Normal | GameMode::Quote => "passage",
+        GameMode::Programming => "code",
+        GameMode::Security => "security",
+        GameMode::Multiplayer => "multiplayer",
+        GameMode::Zen => "zen",
+        GameMode::Time(_) => "time",
+        GameMode::Words(_) => "words",
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum HistoryError {
+    #[error("{path}: {message}")]
+    Io { path: String, message: String },
+    #[error("could not encode run: {0}")]
+    Encode(String),
+    #[error("{0}")]
+    Replay(String),
+}
+
+impl HistoryError {
+    fn io(path: &Path, err: std::io::Error) -> Self {
+        Self::Io { path: path.display().to_string(), message: err.to_string() }
+    }
+}
+
+/// Filter for `History::query`. Every field left as `None` matches all runs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunQuery {
+    /// Mode name, e.g. `"time"`, matched against `RunRecord::mode_name`.
+    pub mode: Option<String>,
+    pub category: Option<String>,
+    pub text_id: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    /// Keep only this many of the newest matching runs.
+    pub limit: Option<usize>,
+}
+
+impl RunQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mode(mut self, mode: &str) -> Self {
+        self.mode = Some(mode.to_string());
+        self
+    }
+
+    pub fn category(mut self, category: &str) -> Self {
+        self.category = Some(category.to_string());
+        self
+    }
+
+    pub fn text_id(mut self, text_id: &str) -> Self {
+        self.text_id = Some(text_id.to_string());
+        self
+    }
+
+    pub fn since(mut self, since: DateTime<Utc>) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn matches(&self, run: &RunRecord) -> bool {
+        self.mode.as_deref().is_none_or(|m| m == run.mode_name())
+            && self.category.as_ref().is_none_or(|c| run.category.as_ref() == Some(c))
+            && self.text_id.as_ref().is_none_or(|id| run.text_id.as_ref() == Some(id))
+            && self.since.is_none_or(|since| run.timestamp >= since)
+    }
+}
+
+/// Aggregates over a set of runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub runs: usize,
+    pub best_wpm: f64,
+    pub average_wpm: f64,
+    pub average_raw_wpm: f64,
+    pub average_accuracy: f64,
+    pub total_time: Duration,
+}
+
+impl Summary {
+    pub fn of<'a>(runs: impl IntoIterator<Item = &'a RunRecord>) -> Option<Self> {
+        let runs: Vec<&RunRecord> = runs.into_iter().collect();
+        if runs.is_empty() {
+            return None;
+        }
+        let n = runs.len() as f64;
+        let mean = |f: fn(&RunRecord) -> f64| runs.iter().map(|r| f(r)).sum::<f64>() / n;
+        Some(Self {
+            runs: runs.len(),
+            best_wpm: runs.iter().map(|r| r.wpm).fold(0.0, f64::max),
+            average_wpm: mean(|r| r.wpm),
+            average_raw_wpm: mean(|r| r.raw_wpm),
+            average_accuracy: mean(|r| r.accuracy),
+            total_time: runs.iter().map(|r| r.duration()).sum(),
+        })
+    }
+}
+
+/// Completed runs, kept as one JSON object per line so saving a run is a single
+/// append and the file stays readable by other tools.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    path: Option<PathBuf>,
+    runs: Vec<RunRecord>,
+    /// Lines that could not be parsed when the file was opened; they are left in place.
+    pub skipped: usize,
+}
+
+impl History {
+    /// A history that lives only in memory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `history.jsonl` in the platform data directory, e.g. `~/.local/share/typerpunk/` on Linux.
+    #[cfg(feature = "dirs")]
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("typerpunk").join("history.jsonl"))
+    }
+
+    #[cfg(not(feature = "dirs"))]
+    pub fn default_path() -> Option<PathBuf> {
+        None
+    }
+
+    /// Reads the history at `path`. A missing file is an empty history; it is created
+    /// on the first `record`.
+    pub fn open(path: &Path) -> Result<Self, HistoryError> {
+        let mut history = Self { path: Some(path.to_path_buf()), ..Self::default() };
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(history),
+            Err(e) => return Err(HistoryError::io(path, e)),
+        };
+        for line in contents.lines().filter(|l| !l.trim().is_empty()) {
+            match serde_json::from_str(line) {
+                Ok(run) => history.runs.push(run),
+                Err(_) => history.skipped += 1,
+            }
+        }
+        Ok(history)
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Adds a run and appends it to the file, if there is one.
+    pub fn record(&mut self, run: RunRecord) -> Result<(), HistoryError> {
+        if let Some(path) = &self.path {
+            let mut line = serde_json::to_string(&run).map_err(|e| HistoryError::Encode(e.to_string()))?;
+            line.push('\n');
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).map_err(|e| HistoryError::io(dir, e))?;
+            }
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| file.write_all(line.as_bytes()))
+                .map_err(|e| HistoryError::io(path, e))?;
+        }
+        self.runs.push(run);
+        Ok(())
+    }
+
+    /// Saves `log` next to the history file and records the run pointing at it.
+    /// An in-memory history records the run without the log.
+    pub fn record_with_replay(&mut self, mut run: RunRecord, log: &KeystrokeLog) -> Result<(), HistoryError> {
+        if let Some(dir) = self.replay_dir() {
+            let name = format!("{}.json", run.timestamp.format("%Y%m%dT%H%M%S%.3fZ"));
+            log.save(&dir.join(&name)).map_err(HistoryError::Replay)?;
+            run.replay = Some(name);
+        }
+        self.record(run)
+    }
+
+    /// `replays/` beside the history file, where keystroke logs are kept.
+    pub fn replay_dir(&self) -> Option<PathBuf> {
+        let path = self.path.as_ref()?;
+        Some(path.parent().unwrap_or(Path::new(".")).join("replays"))
+    }
+
+    /// The keystroke log saved with `run`, if it has one.
+    pub fn load_replay(&self, run: &RunRecord) -> Result<KeystrokeLog, HistoryError> {
+        let name = run
+            .replay
+            .as_ref()
+            .ok_or_else(|| HistoryError::Replay("this run has no saved replay".to_string()))?;
+        let dir = self
+            .replay_dir()
+            .ok_or_else(|| HistoryError::Replay("history is not saved to disk".to_string()))?;
+        KeystrokeLog::load(&dir.join(name)).map_err(HistoryError::Replay)
+    }
+
+    /// Every run, oldest first.
+    pub fn runs(&self) -> &[RunRecord] {
+        &self.runs
+    }
+
+    /// Matching runs, newest first.
+    pub fn query(&self, query: &RunQuery) -> Vec<&RunRecord> {
+        let mut runs: Vec<&RunRecord> = self.runs.iter().filter(|r| query.matches(r)).collect();
+        runs.sort_by_key(|r| std::cmp::Reverse(r.timestamp));
+        if let Some(limit) = query.limit {
+            runs.truncate(limit);
+        }
+        runs
+    }
+
+    pub fn summary(&self, query: &RunQuery) -> Option<Summary> {
+        Summary::of(self.query(query))
+    }
+
+    /// Key and bigram results of the matching runs added together.
+    pub fn key_stats(&self, query: &RunQuery) -> KeyStats {
+        let mut keys = KeyStats::new();
+        for run in self.query(query) {
+            keys.merge(&run.keys);
+        }
+        keys
+    }
+
+    /// Summary for each passage category that has runs, by name. Runs without a
+    /// category (generated words) are left out.
+    pub fn category_summaries(&self) -> Vec<(String, Summary)> {
+        let mut by_category: BTreeMap<&str, Vec<&RunRecord>> = BTreeMap::new();
+        for run in &self.runs {
+            if let Some(category) = &run.category {
+                by_category.entry(category.as_str()).or_default().push(run);
+            }
+        }
+        by_category
+            .into_iter()
+            .filter_map(|(category, runs)| Some((category.to_string(), Summary::of(runs)?)))
+            .collect()
+    }
+
+    /// Fastest run with the same key; ties go to the earlier run.
+    pub fn personal_best(&self, key: &PbKey) -> Option<&RunRecord> {
+        self.runs
+            .iter()
+            .filter(|r| PbKey::of(r) == *key)
+            .fold(None, |best: Option<&RunRecord>, r| match best {
+                Some(b) if b.wpm >= r.wpm => Some(b),
+                _ => Some(r),
+            })
+    }
+
+    /// The personal best for every key that has runs, ordered by key.
+    pub fn personal_bests(&self) -> Vec<(PbKey, &RunRecord)> {
+        let mut bests: BTreeMap<PbKey, &RunRecord> = BTreeMap::new();
+        for run in &self.runs {
+            let best = bests.entry(PbKey::of(run)).or_insert(run);
+            if run.wpm > best.wpm {
+                *best = run;
+            }
+        }
+        bests.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn run(minute: u32, mode: GameMode, wpm: f64) -> RunRecord {
+        RunRecord {
+            timestamp: Utc.with_ymd_and_hms(2024, 5, 1, 12, minute, 0).unwrap(),
+            mode,
+            text_id: None,
+            category: None,
+            wpm,
+            raw_wpm: wpm + 5.0,
+            accuracy: 95.0,
+            errors: 3,
+            duration_secs: 30.0,
+            wpm_samples: vec![40, 50, 60],
+            timeline: Vec::new(),
+            consistency: 90.0,
+            burst: wpm + 20.0,
+            keys: KeyStats::default(),
+            replay: None,
+        }
+    }
+
+    #[test]
+    fn test_record_and_reopen() {
+        let path = std::env::temp_dir()
+            .join(format!("typerpunk-history-test-{}", std::process::id()))
+            .join("history.jsonl");
+        let _ = fs::remove_file(&path);
+
+        let mut history = History::open(&path).unwrap();
+        assert!(history.runs().is_empty());
+        history.record(run(0, GameMode::Time(30), 60.0)).unwrap();
+        history.record(run(1, GameMode::Words(25), 70.0)).unwrap();
+
+        let mut log = KeystrokeLog::new(GameMode::Words(25), "a b");
+        log.push(Duration::from_millis(120), crate::replay::KeyAction::Insert { ch: 'a' });
+        history.record_with_replay(run(2, GameMode::Words(25), 65.0), &log).unwrap();
+
+        let reopened = History::open(&path).unwrap();
+        assert_eq!(reopened.runs(), history.runs());
+        assert_eq!(reopened.skipped, 0);
+        assert!(reopened.runs()[0].replay.is_none());
+        assert_eq!(reopened.load_replay(&reopened.runs()[2]).unwrap(), log);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn test_bad_lines_are_skipped() {
+        let path = std::env::temp_dir().join(format!("typerpunk-history-bad-{}.jsonl", std::process::id()));
+        let good = serde_json::to_string(&run(0, GameMode::Normal, 50.0)).unwrap();
+        fs::write(&path, format!("{}\n{{not json\n\n{}\n", good, good)).unwrap();
+
+        let history = History::open(&path).unwrap();
+        assert_eq!(history.runs().len(), 2);
+        assert_eq!(history.skipped, 1);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_query_and_summary() {
+        let mut history = History::new();
+        history.record(run(0, GameMode::Time(30), 60.0)).unwrap();
+        history.record(run(1, GameMode::Words(25), 70.0)).unwrap();
+        history.record(run(2, GameMode::Time(60), 80.0)).unwrap();
+
+        let timed = history.query(&RunQuery::new().mode("time"));
+        assert_eq!(timed.iter().map(|r| r.wpm).collect::<Vec<_>>(), vec![80.0, 60.0]);
+        assert_eq!(history.query(&RunQuery::new().limit(1))[0].wpm, 80.0);
+
+        let summary = history.summary(&RunQuery::new().mode("time")).unwrap();
+        assert_eq!(summary.runs, 2);
+        assert_eq!(summary.best_wpm, 80.0);
+        assert_eq!(summary.average_wpm, 70.0);
+        assert_eq!(summary.total_time, Duration::from_secs(60));
+        assert!(history.summary(&RunQuery::new().mode("zen")).is_none());
+        assert!(history.category_summaries().is_empty());
+    }
+
+    #[test]
+    fn test_personal_bests_are_per_mode_and_category() {
+        let mut history = History::new();
+        history.record(run(0, GameMode::Time(30), 60.0)).unwrap();
+        history.record(run(1, GameMode::Time(30), 75.0)).unwrap();
+        history.record(run(2, GameMode::Time(60), 90.0)).unwrap();
+        let mut quotes = run(3, GameMode::Time(30), 99.0);
+        quotes.category = Some("quotes".to_string());
+        history.record(quotes).unwrap();
+
+        let best = history.personal_best(&PbKey::new(GameMode::Time(30), None)).unwrap();
+        assert_eq!(best.wpm, 75.0);
+        assert!(history.personal_best(&PbKey::new(GameMode::Words(10), None)).is_none());
+        assert_eq!(history.personal_bests().len(), 3);
+        let categories = history.category_summaries();
+        assert_eq!(categories.len(), 1);
+        assert_eq!(categories[0].0, "quotes");
+    }
+
+    #[test]
+    fn test_chars_at_follows_samples() {
+        let mut pb = run(0, GameMode::Time(30), 60.0);
+        pb.duration_secs = 3.0;
+        pb.wpm_samples = vec![60, 60, 60];
+        // 60 WPM is five characters a second
+        assert_eq!(pb.chars_at(Duration::from_secs(2)), 10.0);
+        // The ghost stops where the run ended
+        assert_eq!(pb.chars_at(Duration::from_secs(10)), 15.0);
+    }
+}
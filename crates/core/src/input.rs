@@ -1,5 +1,4 @@
 use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
-use std::time::{Duration, Instant};
 use crate::app::App;
 
 #[derive(Debug, Clone)]
@@ -167,16 +166,10 @@ impl Default for Input {
 
 pub struct InputHandler {
     pub app: App,
-    last_tick: Instant,
-    tick_rate: Duration,
 }
 
 impl InputHandler {
     pub fn new(app: App) -> Self {
-        Self {
-            app,
-            last_tick: Instant::now(),
-            tick_rate: Duration::from_millis(100),
-        }
+        Self { app }
     }
 } 
\ No newline at end of file
@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+// Gaps longer than this are pauses, not the time it took to find a key
+const MAX_LATENCY: Duration = Duration::from_secs(2);
+
+// US QWERTY rows, unshifted, as drawn by the heatmap
+pub const KEYBOARD_ROWS: [&str; 4] = ["`1234567890-=", "qwertyuiop[]\\", "asdfghjkl;'", "zxcvbnm,./"];
+
+/// Tally for one key or bigram: how often it was expected, how often the wrong
+/// key was pressed instead, and how long it took to reach it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyStat {
+    pub hits: u32,
+    pub misses: u32,
+    /// Sum of the gaps before each timed keystroke, in milliseconds.
+    #[serde(default)]
+    pub latency_ms: u64,
+    /// Keystrokes that have a gap in `latency_ms`; the first key of a run has none.
+    #[serde(default)]
+    pub timed: u32,
+}
+
+impl KeyStat {
+    pub fn attempts(&self) -> u32 {
+        self.hits + self.misses
+    }
+
+    /// Share of attempts that hit the right key, as a percentage.
+    pub fn accuracy(&self) -> f64 {
+        match self.attempts() {
+            0 => 100.0,
+            n => self.hits as f64 / n as f64 * 100.0,
+        }
+    }
+
+    pub fn mean_latency_ms(&self) -> Option<f64> {
+        (self.timed > 0).then(|| self.latency_ms as f64 / self.timed as f64)
+    }
+
+    fn add(&mut self, correct: bool, latency: Option<Duration>) {
+        if correct {
+            self.hits += 1;
+        } else {
+            self.misses += 1;
+        }
+        if let Some(latency) = latency {
+            self.latency_ms += latency.as_millis() as u64;
+            self.timed += 1;
+        }
+    }
+
+    fn merge(&mut self, other: &KeyStat) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.latency_ms += other.latency_ms;
+        self.timed += other.timed;
+    }
+}
+
+/// Per-character and per-bigram results, keyed by the character that should have
+/// been typed. Bigrams are the previous expected character followed by this one.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct KeyStats {
+    #[serde(default)]
+    pub keys: BTreeMap<char, KeyStat>,
+    #[serde(default)]
+    pub bigrams: BTreeMap<String, KeyStat>,
+}
+
+impl KeyStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty() && self.bigrams.is_empty()
+    }
+
+    pub fn merge(&mut self, other: &KeyStats) {
+        for (key, stat) in &other.keys {
+            self.keys.entry(*key).or_default().merge(stat);
+        }
+        for (bigram, stat) in &other.bigrams {
+            self.bigrams.entry(bigram.clone()).or_default().merge(stat);
+        }
+    }
+
+    /// Folds characters onto the physical key that types them (see [`key_cap`]),
+    /// e.g. `A` and `a` both count for the `a` key.
+    pub fn by_key_cap(&self) -> BTreeMap<char, KeyStat> {
+        let mut caps: BTreeMap<char, KeyStat> = BTreeMap::new();
+        for (key, stat) in &self.keys {
+            caps.entry(key_cap(*key)).or_default().merge(stat);
+        }
+        caps
+    }
+
+    /// Keys with at least `min_attempts` attempts, least accurate first; ties go
+    /// to the slower key.
+    pub fn weakest_keys(&self, min_attempts: u32) -> Vec<(char, KeyStat)> {
+        let mut keys: Vec<(char, KeyStat)> = self
+            .keys
+            .iter()
+            .filter(|(c, s)| !c.is_whitespace() && s.attempts() >= min_attempts)
+            .map(|(c, s)| (*c, *s))
+            .collect();
+        keys.sort_by(|(_, a), (_, b)| weakness(a, b));
+        keys
+    }
+
+    /// Bigrams with at least `min_attempts` attempts, least accurate first.
+    pub fn weakest_bigrams(&self, min_attempts: u32) -> Vec<(String, KeyStat)> {
+        let mut bigrams: Vec<(String, KeyStat)> = self
+            .bigrams
+            .iter()
+            .filter(|(b, s)| !b.contains(char::is_whitespace) && s.attempts() >= min_attempts)
+            .map(|(b, s)| (b.clone(), *s))
+            .collect();
+        bigrams.sort_by(|(_, a), (_, b)| weakness(a, b));
+        bigrams
+    }
+}
+
+fn weakness(a: &KeyStat, b: &KeyStat) -> std::cmp::Ordering {
+    a.accuracy()
+        .total_cmp(&b.accuracy())
+        .then_with(|| b.mean_latency_ms().unwrap_or(0.0).total_cmp(&a.mean_latency_ms().unwrap_or(0.0)))
+}
+
+/// The unshifted US QWERTY key that types `c`; letters are lowercased and other
+/// characters are returned as they are.
+pub fn key_cap(c: char) -> char {
+    const SHIFTED: &str = "~!@#$%^&*()_+{}|:\"<>?";
+    const UNSHIFTED: &str = "`1234567890-=[]\\;',./";
+    if let Some(i) = SHIFTED.chars().position(|s| s == c) {
+        return UNSHIFTED.chars().nth(i).unwrap_or(c);
+    }
+    c.to_ascii_lowercase()
+}
+
+/// Feeds the keystrokes of one run into [`KeyStats`], timing each against the one
+/// before it. Only keystrokes typed in order (position after position) form a
+/// bigram or get a latency, so corrections don't skew either.
+#[derive(Debug, Clone, Default)]
+pub struct KeyRecorder {
+    stats: KeyStats,
+    last: Option<(usize, char, Instant)>,
+}
+
+impl KeyRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `typed` at character `position`, where `expected` should have been typed.
+    pub fn record(&mut self, position: usize, expected: char, typed: char) {
+        self.record_at(position, expected, typed, Instant::now());
+    }
+
+    pub fn record_at(&mut self, position: usize, expected: char, typed: char, at: Instant) {
+        let correct = typed == expected;
+        let previous = self
+            .last
+            .filter(|(last_position, _, _)| last_position + 1 == position);
+        let latency = previous
+            .map(|(_, _, last_at)| at.saturating_duration_since(last_at))
+            .filter(|gap| *gap <= MAX_LATENCY);
+
+        self.stats.keys.entry(expected).or_default().add(correct, latency);
+        if let Some((_, previous_expected, _)) = previous {
+            let bigram: String = [previous_expected, expected].iter().collect();
+            self.stats.bigrams.entry(bigram).or_default().add(correct, latency);
+        }
+        self.last = Some((position, expected, at));
+    }
+
+    pub fn stats(&self) -> &KeyStats {
+        &self.stats
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_keys_bigrams_and_latency() {
+        let start = Instant::now();
+        let mut recorder = KeyRecorder::new();
+        recorder.record_at(0, 't', 't', start);
+        recorder.record_at(1, 'h', 'j', start + Duration::from_millis(150));
+        recorder.record_at(2, 'e', 'e', start + Duration::from_millis(250));
+        // A pause is not counted as latency
+        recorder.record_at(3, ' ', ' ', start + Duration::from_secs(10));
+
+        let stats = recorder.stats();
+        assert_eq!(stats.keys[&'t'], KeyStat { hits: 1, misses: 0, latency_ms: 0, timed: 0 });
+        assert_eq!(stats.keys[&'h'], KeyStat { hits: 0, misses: 1, latency_ms: 150, timed: 1 });
+        assert_eq!(stats.bigrams["th"].misses, 1);
+        assert_eq!(stats.bigrams["he"].mean_latency_ms(), Some(100.0));
+        assert_eq!(stats.keys[&' '].timed, 0);
+    }
+
+    #[test]
+    fn test_out_of_order_keys_skip_bigrams() {
+        let start = Instant::now();
+        let mut recorder = KeyRecorder::new();
+        recorder.record_at(0, 'a', 'a', start);
+        recorder.record_at(1, 'b', 'x', start);
+        // Backspaced and retyped position 1
+        recorder.record_at(1, 'b', 'b', start);
+        assert_eq!(recorder.stats().bigrams["ab"].attempts(), 1);
+        assert_eq!(recorder.stats().keys[&'b'].attempts(), 2);
+    }
+
+    #[test]
+    fn test_weakest_and_key_caps() {
+        let mut stats = KeyStats::new();
+        stats.keys.insert('a', KeyStat { hits: 9, misses: 1, ..KeyStat::default() });
+        stats.keys.insert('A', KeyStat { hits: 0, misses: 1, ..KeyStat::default() });
+        stats.keys.insert('q', KeyStat { hits: 5, misses: 5, ..KeyStat::default() });
+        stats.keys.insert('z', KeyStat { hits: 1, misses: 0, ..KeyStat::default() });
+
+        let weakest: Vec<char> = stats.weakest_keys(2).into_iter().map(|(c, _)| c).collect();
+        assert_eq!(weakest, vec!['q', 'a']);
+        assert_eq!(stats.by_key_cap()[&'a'].attempts(), 11);
+        assert_eq!(key_cap('?'), '/');
+        assert_eq!(key_cap('"'), '\'');
+    }
+}
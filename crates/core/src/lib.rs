@@ -1,8 +1,21 @@
+pub mod adaptive;
+pub mod anticheat;
 pub mod config;
+pub mod engine;
 pub mod game;
+pub mod graphemes;
+pub mod history;
+pub mod keystats;
+pub mod metrics;
+pub mod normalize;
+pub mod packs;
+pub mod protocol;
+pub mod replay;
+pub mod room;
 pub mod stats;
 pub mod text;
 pub mod types;
+pub mod words;
 
 #[cfg(feature = "tui")]
 pub mod app;
@@ -0,0 +1,180 @@
+//! Speed and accuracy figures, defined once so the TUI, the web client and the
+//! race server report the same numbers for the same run.
+//!
+//! - A character is a grapheme cluster, and a word is [`CHARS_PER_WORD`] of them.
+//! - **Net WPM** counts the characters of the input that match the text, so a
+//!   typo left in costs its character and a corrected one costs the time it took.
+//! - **Raw WPM** counts every keystroke, right or wrong, deleted or not.
+//! - **CPM** is net characters per minute, i.e. net WPM without the division.
+//! - **Accuracy** is the share of keystrokes that were right when typed, so a
+//!   typo still counts against it after being fixed.
+//! - **Consistency** is how even the pace is from second to second: 100 less the
+//!   coefficient of variation of the per-second raw WPM, as a percentage.
+//! - **Burst** is the fastest net WPM over any whole second.
+//!
+//! Times are taken with sub-second precision; nothing rounds to whole seconds.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+pub const CHARS_PER_WORD: f64 = 5.0;
+
+/// Every figure of a run at one moment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Metrics {
+    pub net_wpm: f64,
+    pub raw_wpm: f64,
+    pub cpm: f64,
+    pub accuracy: f64,
+    pub consistency: f64,
+    pub burst: f64,
+    /// Keystrokes that were wrong when typed.
+    pub errors: usize,
+    /// Seconds since the run started.
+    pub elapsed: f64,
+}
+
+/// Characters per minute for `chars` typed over `elapsed`; 0 before any time passes.
+pub fn cpm(chars: usize, elapsed: Duration) -> f64 {
+    let minutes = elapsed.as_secs_f64() / 60.0;
+    if minutes > 0.0 {
+        chars as f64 / minutes
+    } else {
+        0.0
+    }
+}
+
+/// Words per minute for `chars` typed over `elapsed`.
+pub fn wpm(chars: usize, elapsed: Duration) -> f64 {
+    cpm(chars, elapsed) / CHARS_PER_WORD
+}
+
+/// Net WPM: `correct` is how many characters of the input match the text.
+pub fn net_wpm(correct: usize, elapsed: Duration) -> f64 {
+    wpm(correct, elapsed)
+}
+
+/// Raw WPM: `keystrokes` is every character typed.
+pub fn raw_wpm(keystrokes: usize, elapsed: Duration) -> f64 {
+    wpm(keystrokes, elapsed)
+}
+
+/// Percentage of `total` that was `correct`; 0 when nothing was typed.
+pub fn accuracy(correct: usize, total: usize) -> f64 {
+    if total > 0 {
+        correct as f64 / total as f64 * 100.0
+    } else {
+        0.0
+    }
+}
+
+/// One second of a run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Second {
+    /// Every keystroke of the second.
+    pub raw_wpm: f64,
+    /// Keystrokes of the second that were right when typed.
+    pub net_wpm: f64,
+    /// Keystrokes of the second that were wrong when typed.
+    pub errors: usize,
+}
+
+/// Each second of a run, from the time of every keystroke (in ms from the
+/// start) and whether it was right. A final part second is scaled up to a
+/// whole one, or if under half a second goes in with the second before it, so
+/// the last few keys don't show as a spike.
+pub fn timeline(keystrokes: impl IntoIterator<Item = (u64, bool)>, elapsed: Duration) -> Vec<Second> {
+    let total_ms = elapsed.as_millis() as u64;
+    let mut seconds = total_ms.div_ceil(1000);
+    if seconds > 1 && (1..500).contains(&(total_ms % 1000)) {
+        seconds -= 1;
+    }
+    let mut counts = vec![(0usize, 0usize); seconds as usize];
+    for (at, correct) in keystrokes {
+        let second = ((at / 1000) as usize).min(counts.len().saturating_sub(1));
+        if let Some((typed, wrong)) = counts.get_mut(second) {
+            *typed += 1;
+            if !correct {
+                *wrong += 1;
+            }
+        }
+    }
+    counts
+        .iter()
+        .enumerate()
+        .map(|(second, &(typed, wrong))| {
+            let rest = total_ms - second as u64 * 1000;
+            let length = Duration::from_millis(if second + 1 == counts.len() { rest } else { rest.min(1000) });
+            Second {
+                raw_wpm: raw_wpm(typed, length),
+                net_wpm: net_wpm(typed - wrong, length),
+                errors: wrong,
+            }
+        })
+        .collect()
+}
+
+/// Fastest net WPM over a whole second of `timeline`, or over the run if it
+/// lasted under a second.
+pub fn burst(timeline: &[Second], elapsed: Duration) -> f64 {
+    let whole = (elapsed.as_secs() as usize).max(1);
+    timeline.iter().take(whole).map(|s| s.net_wpm).fold(0.0, f64::max)
+}
+
+/// How steady `samples` are, from 100 for a flat pace down to 0 for one that
+/// varies as much as it averages. 0 without samples to judge.
+pub fn consistency(samples: &[f64]) -> f64 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+    if mean <= 0.0 {
+        return 0.0;
+    }
+    let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / samples.len() as f64;
+    (1.0 - variance.sqrt() / mean).max(0.0) * 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_definitions() {
+        // 50 characters in 30.5s is not rounded down to 30s
+        let elapsed = Duration::from_millis(30_500);
+        assert!((cpm(50, elapsed) - 50.0 / (30.5 / 60.0)).abs() < 1e-9);
+        assert!((net_wpm(50, elapsed) - cpm(50, elapsed) / 5.0).abs() < 1e-9);
+        assert!(raw_wpm(55, elapsed) > net_wpm(50, elapsed));
+        assert_eq!(wpm(10, Duration::from_millis(400)), 300.0);
+        assert_eq!(wpm(10, Duration::ZERO), 0.0);
+        assert_eq!(accuracy(9, 10), 90.0);
+        assert_eq!(accuracy(0, 0), 0.0);
+    }
+
+    #[test]
+    fn test_timeline_and_consistency() {
+        // Five keys a second for two seconds, one of them wrong, then five in
+        // the last half second
+        let elapsed = Duration::from_millis(2500);
+        let keys = (0..10).map(|i| (i * 200, i != 7)).chain((0..5).map(|i| (2000 + i * 100, true)));
+        let seconds = timeline(keys, elapsed);
+        let samples: Vec<f64> = seconds.iter().map(|s| s.raw_wpm).collect();
+        assert_eq!(samples, vec![60.0, 60.0, 120.0]);
+        assert_eq!(seconds[1], Second { raw_wpm: 60.0, net_wpm: 48.0, errors: 1 });
+        // The half second is quick but too short to count as a burst
+        assert_eq!(burst(&seconds, elapsed), 60.0);
+        assert_eq!(burst(&seconds[2..], Duration::from_millis(500)), 120.0);
+
+        // A last key 100ms into the third second counts towards the second one
+        let seconds = timeline([(500, true), (1500, true), (2050, true)], Duration::from_millis(2100));
+        assert_eq!(seconds.len(), 2);
+        assert!((seconds[1].raw_wpm - wpm(2, Duration::from_millis(1100))).abs() < 1e-9);
+
+        assert_eq!(consistency(&[60.0, 60.0, 60.0]), 100.0);
+        assert!(consistency(&samples) < 100.0);
+        assert_eq!(consistency(&[0.0, 120.0]), 0.0);
+        assert_eq!(consistency(&[]), 0.0);
+    }
+}
@@ -1,132 +1,975 @@
-#[cfg(feature = "multiplayer")]
-use tokio_tungstenite::tungstenite::Message;
-#[cfg(feature = "multiplayer")]
-use futures_util::{SinkExt, StreamExt};
-use serde::{Serialize, Deserialize};
-use std::net::SocketAddr;
-use crate::{app::App, config::GameConfig, game::Platform};
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_util::{SinkExt, StreamExt};
+use rand::Rng;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio_tungstenite::tungstenite::Message;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum MultiplayerMessage {
-    Join,
-    Progress { progress: f32, wpm: f32 },
-    Finish { wpm: f32, time: f32 },
+use crate::anticheat::{self, Flag, Limits};
+use crate::game::GameConfig;
+use crate::replay::Keystroke;
+pub use crate::protocol::{ErrorCode, MultiplayerMessage, PlayerId, PlayerInfo, Standing, PROTOCOL_VERSION};
+use crate::protocol::check_version;
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Send + Sync>>;
+
+/// Countdown between the host starting a race and the race beginning.
+pub const DEFAULT_COUNTDOWN: Duration = Duration::from_secs(3);
+
+/// How this crate's clients introduce themselves in their hello.
+pub const CLIENT_NAME: &str = concat!("typerpunk/", env!("CARGO_PKG_VERSION"));
+
+// How long a client waits for the server's welcome
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// A refused message: the error sent back to its sender
+type Refusal = (ErrorCode, String);
+
+fn not_allowed(message: &str) -> Refusal {
+    (ErrorCode::NotAllowed, message.to_string())
 }
 
-#[derive(Debug)]
-pub struct MultiplayerManager {
-    config: GameConfig,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RacePhase {
+    Lobby,
+    Countdown,
+    Racing,
 }
 
-impl MultiplayerManager {
-    pub fn new(config: GameConfig) -> Self {
-        Self { config }
+#[derive(Debug, Clone)]
+struct Racer {
+    info: PlayerInfo,
+    progress: f32,
+    wpm: f32,
+    finish: Option<(usize, f32)>,
+    flags: Vec<Flag>,
+    // Why their run was rejected, if it was
+    disqualified: Option<String>,
+}
+
+impl Racer {
+    fn new(info: PlayerInfo) -> Self {
+        Self { info, progress: 0.0, wpm: 0.0, finish: None, flags: Vec::new(), disqualified: None }
     }
 
-    #[cfg(feature = "multiplayer")]
-    pub async fn start_server(&self, addr: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
-        tokio::spawn(async move {
-            let server = tokio::net::TcpListener::bind(addr).await?;
-            while let Ok((stream, _)) = server.accept().await {
-                let ws_stream = tokio_tungstenite::accept_async(stream).await?;
-                let (write, read) = ws_stream.split();
-                read.forward(write).await?;
-            }
-            Ok::<(), Box<dyn Error + Send + Sync>>(())
-        });
-        Ok(())
+    fn done(&self) -> bool {
+        self.finish.is_some() || self.disqualified.is_some()
     }
+}
 
-    #[cfg(feature = "multiplayer")]
-    pub async fn host_game(&mut self, addr: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
-        tokio::spawn(async move {
-            let server = tokio::net::TcpListener::bind(addr).await?;
-            while let Ok((stream, _)) = server.accept().await {
-                let ws_stream = tokio_tungstenite::accept_async(stream).await?;
-                let (write, read) = ws_stream.split();
-                read.forward(write).await?;
+#[derive(Debug, Clone)]
+struct Room {
+    host: PlayerId,
+    racers: Vec<Racer>,
+    text: String,
+    phase: RacePhase,
+    finished: usize,
+    // When the race was let go, to hold finish times up against
+    started: Option<Instant>,
+}
+
+impl Room {
+    fn ids(&self) -> impl Iterator<Item = PlayerId> + '_ {
+        self.racers.iter().map(|r| r.info.id)
+    }
+
+    fn racer_mut(&mut self, id: PlayerId) -> Option<&mut Racer> {
+        self.racers.iter_mut().find(|r| r.info.id == id)
+    }
+
+    fn standings(&self) -> Vec<Standing> {
+        let mut standings: Vec<Standing> = self
+            .racers
+            .iter()
+            .map(|r| Standing {
+                player_id: r.info.id,
+                name: r.info.name.clone(),
+                place: r.finish.map(|(place, _)| place),
+                progress: r.progress,
+                wpm: r.wpm,
+                time: r.finish.map(|(_, time)| time),
+                flags: r.flags.clone(),
+                disqualified: r.disqualified.clone(),
+            })
+            .collect();
+        standings.sort_by(|a, b| match (a.place, b.place) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => {
+                a.disqualified.is_some().cmp(&b.disqualified.is_some()).then(b.progress.total_cmp(&a.progress))
             }
-            Ok::<(), Box<dyn Error + Send + Sync>>(())
         });
-        Ok(())
+        standings
     }
+}
+
+/// What the server should do after a [`Lobby`] call.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Effect {
+    Send(PlayerId, MultiplayerMessage),
+    /// Call [`Lobby::go`] for this room once the delay has passed.
+    StartAfter(String, Duration),
+    /// Close the player's connection once what was sent to them has gone out.
+    Close(PlayerId),
+}
 
-    #[cfg(feature = "multiplayer")]
-    pub async fn join_game(&mut self, url: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let (ws_stream, _) = tokio_tungstenite::connect_async(url).await?;
-        let (mut write, mut read) = ws_stream.split();
+/// Rooms and the players in them, with the race rules but no networking, so the
+/// server's behaviour can be driven directly in tests.
+#[derive(Debug)]
+pub struct Lobby {
+    rooms: HashMap<String, Room>,
+    player_rooms: HashMap<PlayerId, String>,
+    // Players whose hello was accepted
+    greeted: HashSet<PlayerId>,
+    next_id: PlayerId,
+    countdown: Duration,
+}
 
-        // Send join message
-        let join_msg = serde_json::to_string(&MultiplayerMessage::Join)?;
-        write.send(Message::Text(join_msg)).await?;
+impl Lobby {
+    pub fn new(countdown: Duration) -> Self {
+        Self { rooms: HashMap::new(), player_rooms: HashMap::new(), greeted: HashSet::new(), next_id: 1, countdown }
+    }
 
-        // Handle incoming messages
-        tokio::spawn(async move {
-            while let Some(msg) = read.next().await {
-                match msg {
-                    Ok(Message::Text(text)) => {
-                        println!("Received: {}", text);
+    /// Id for a newly connected player.
+    pub fn connect(&mut self) -> PlayerId {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    pub fn phase(&self, room: &str) -> Option<RacePhase> {
+        self.rooms.get(room).map(|r| r.phase)
+    }
+
+    pub fn handle(&mut self, from: PlayerId, message: MultiplayerMessage) -> Vec<Effect> {
+        match message {
+            MultiplayerMessage::Hello { version, .. } => match check_version(version) {
+                Ok(()) => {
+                    self.greeted.insert(from);
+                    vec![Effect::Send(from, MultiplayerMessage::Welcome { version: PROTOCOL_VERSION, player_id: from })]
+                }
+                Err(refusal) => vec![Effect::Send(from, refusal), Effect::Close(from)],
+            },
+            MultiplayerMessage::Ping { nonce } => vec![Effect::Send(from, MultiplayerMessage::Pong { nonce })],
+            MultiplayerMessage::Pong { .. } => Vec::new(),
+            _ if !self.greeted.contains(&from) => {
+                vec![Effect::Send(from, MultiplayerMessage::error(ErrorCode::NotAllowed, "send a hello first"))]
+            }
+            message => match self.try_handle(from, message) {
+                Ok(effects) => effects,
+                Err((code, message)) => vec![Effect::Send(from, MultiplayerMessage::Error { code, message })],
+            },
+        }
+    }
+
+    fn try_handle(&mut self, from: PlayerId, message: MultiplayerMessage) -> std::result::Result<Vec<Effect>, Refusal> {
+        match message {
+            MultiplayerMessage::CreateRoom { name } => {
+                self.ensure_outside(from)?;
+                let code = self.new_code();
+                self.rooms.insert(
+                    code.clone(),
+                    Room {
+                        host: from,
+                        racers: Vec::new(),
+                        text: String::new(),
+                        phase: RacePhase::Lobby,
+                        finished: 0,
+                        started: None,
+                    },
+                );
+                Ok(self.enter(from, &code, name))
+            }
+            MultiplayerMessage::JoinRoom { room, name } => {
+                self.ensure_outside(from)?;
+                let code = room.trim().to_uppercase();
+                match self.rooms.get(&code) {
+                    None => Err((ErrorCode::NoSuchRoom, format!("no room {}", code))),
+                    Some(r) if r.phase != RacePhase::Lobby => Err(not_allowed("that room is already racing")),
+                    Some(_) => Ok(self.enter(from, &code, name)),
+                }
+            }
+            MultiplayerMessage::LeaveRoom => {
+                self.room_of(from)?;
+                Ok(self.leave(from))
+            }
+            MultiplayerMessage::Ready { ready } => {
+                let (_, room) = self.room_of(from)?;
+                if room.phase != RacePhase::Lobby {
+                    return Err(not_allowed("the race has already started"));
+                }
+                if let Some(racer) = room.racer_mut(from) {
+                    racer.info.ready = ready;
+                }
+                Ok(broadcast(room.ids(), None, MultiplayerMessage::PlayerReady { player_id: from, ready }))
+            }
+            MultiplayerMessage::StartRace { text } => {
+                let countdown = self.countdown;
+                let (code, room) = self.room_of(from)?;
+                if room.host != from {
+                    return Err(not_allowed("only the host can start the race"));
+                }
+                if room.phase != RacePhase::Lobby {
+                    return Err(not_allowed("the race has already started"));
+                }
+                if text.trim().is_empty() {
+                    return Err(not_allowed("the race needs a text"));
+                }
+                // The host readies up by starting
+                if let Some(waiting) = room.racers.iter().find(|r| r.info.id != from && !r.info.ready) {
+                    return Err(not_allowed(&format!("waiting for {} to be ready", waiting.info.name)));
+                }
+                room.text = text.clone();
+                room.phase = RacePhase::Countdown;
+                room.finished = 0;
+                for racer in &mut room.racers {
+                    *racer = Racer::new(racer.info.clone());
+                }
+                let start_in_ms = countdown.as_millis() as u64;
+                let mut effects = broadcast(room.ids(), None, MultiplayerMessage::RaceText { text });
+                effects.extend(broadcast(room.ids(), None, MultiplayerMessage::Countdown { start_in_ms }));
+                effects.push(Effect::StartAfter(code, countdown));
+                Ok(effects)
+            }
+            MultiplayerMessage::Progress { progress, wpm } => {
+                let (_, room) = self.room_of(from)?;
+                if room.phase != RacePhase::Racing {
+                    return Err(not_allowed("the race has not started"));
+                }
+                let racer = room.racer_mut(from).ok_or_else(|| not_allowed("not in a race"))?;
+                if racer.done() {
+                    return Ok(Vec::new());
+                }
+                let progress = progress.clamp(0.0, 100.0);
+                racer.progress = progress;
+                racer.wpm = wpm.max(0.0);
+                let update = MultiplayerMessage::PlayerProgress { player_id: from, progress, wpm: racer.wpm };
+                Ok(broadcast(room.ids(), Some(from), update))
+            }
+            MultiplayerMessage::Finish { events } => {
+                let (code, room) = self.room_of(from)?;
+                if room.phase != RacePhase::Racing {
+                    return Err(not_allowed("the race has not started"));
+                }
+                let limits = Limits::default();
+                let elapsed = room.started.map(|at| at.elapsed()).unwrap_or_default();
+                let verdict = anticheat::check_clock(&events, elapsed, &limits)
+                    .and_then(|()| anticheat::check_run(&room.text, &events, &limits));
+                let place = room.finished + 1;
+                let racer = room.racer_mut(from).ok_or_else(|| not_allowed("not in a race"))?;
+                if racer.done() {
+                    return Err(not_allowed("already finished"));
+                }
+                let mut effects = Vec::new();
+                match verdict {
+                    Ok(verdict) => {
+                        let (wpm, time) = (verdict.wpm as f32, verdict.time as f32);
+                        racer.finish = Some((place, time));
+                        racer.progress = 100.0;
+                        racer.wpm = wpm;
+                        racer.flags = verdict.flags.clone();
+                        room.finished = place;
+                        let flags = verdict.flags;
+                        let update = MultiplayerMessage::PlayerFinished { player_id: from, place, wpm, time, flags };
+                        effects.extend(broadcast(room.ids(), None, update));
                     }
-                    Err(e) => {
-                        eprintln!("Error receiving message: {}", e);
-                        break;
+                    Err(rejection) => {
+                        let reason = rejection.to_string();
+                        racer.disqualified = Some(reason.clone());
+                        effects.push(Effect::Send(
+                            from,
+                            MultiplayerMessage::error(ErrorCode::RejectedRun, format!("run rejected: {}", reason)),
+                        ));
+                        let update = MultiplayerMessage::PlayerDisqualified { player_id: from, reason };
+                        effects.extend(broadcast(room.ids(), None, update));
                     }
-                    _ => {}
                 }
+                effects.extend(self.results_if_done(&code));
+                Ok(effects)
             }
-        });
+            _ => Err(not_allowed("that message is only sent by the server")),
+        }
+    }
 
-        Ok(())
+    /// Ends the countdown of `room` and starts its race.
+    pub fn go(&mut self, room: &str) -> Vec<Effect> {
+        match self.rooms.get_mut(room) {
+            Some(r) if r.phase == RacePhase::Countdown => {
+                r.phase = RacePhase::Racing;
+                r.started = Some(Instant::now());
+                broadcast(r.ids(), None, MultiplayerMessage::Go)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Forgets a player whose connection went away.
+    pub fn disconnect(&mut self, id: PlayerId) -> Vec<Effect> {
+        self.greeted.remove(&id);
+        self.leave(id)
+    }
+
+    // Takes a player out of their room, handing it to someone else if they were the
+    // host and closing the race if they were the last one still typing
+    fn leave(&mut self, id: PlayerId) -> Vec<Effect> {
+        let Some(code) = self.player_rooms.remove(&id) else {
+            return Vec::new();
+        };
+        let Some(room) = self.rooms.get_mut(&code) else {
+            return Vec::new();
+        };
+        room.racers.retain(|r| r.info.id != id);
+        let Some(first) = room.racers.first() else {
+            self.rooms.remove(&code);
+            return Vec::new();
+        };
+        if room.host == id {
+            room.host = first.info.id;
+        }
+        let left = MultiplayerMessage::PlayerLeft { player_id: id, host: room.host };
+        let mut effects = broadcast(room.ids(), None, left);
+        effects.extend(self.results_if_done(&code));
+        effects
+    }
+
+    fn results_if_done(&mut self, code: &str) -> Vec<Effect> {
+        let Some(room) = self.rooms.get_mut(code) else {
+            return Vec::new();
+        };
+        if room.phase != RacePhase::Racing || room.racers.iter().any(|r| !r.done()) {
+            return Vec::new();
+        }
+        room.phase = RacePhase::Lobby;
+        for racer in &mut room.racers {
+            racer.info.ready = false;
+        }
+        let standings = room.standings();
+        broadcast(room.ids(), None, MultiplayerMessage::Results { standings })
     }
 
-    pub fn send_progress(&self, _progress: f32, _wpm: f32) {
-        #[cfg(feature = "multiplayer")]
-        let _msg = MultiplayerMessage::Progress { progress: _progress, wpm: _wpm };
-        // Send message implementation
+    fn enter(&mut self, id: PlayerId, code: &str, name: String) -> Vec<Effect> {
+        let name = match name.trim() {
+            "" => format!("player {}", id),
+            name => name.to_string(),
+        };
+        let info = PlayerInfo { id, name, ready: false };
+        let room = self.rooms.get_mut(code).expect("room exists");
+        let mut effects = broadcast(room.ids(), None, MultiplayerMessage::PlayerJoined { player: info.clone() });
+        room.racers.push(Racer::new(info));
+        let players = room.racers.iter().map(|r| r.info.clone()).collect();
+        effects.push(Effect::Send(
+            id,
+            MultiplayerMessage::Joined { room: code.to_string(), player_id: id, host: room.host, players },
+        ));
+        self.player_rooms.insert(id, code.to_string());
+        effects
     }
 
-    pub fn send_finish(&self, _wpm: f32, _time: f32) {
-        #[cfg(feature = "multiplayer")]
-        let _msg = MultiplayerMessage::Finish { wpm: _wpm, time: _time };
-        // Send message implementation
+    fn ensure_outside(&self, id: PlayerId) -> std::result::Result<(), Refusal> {
+        match self.player_rooms.get(&id) {
+            Some(code) => Err(not_allowed(&format!("already in room {}", code))),
+            None => Ok(()),
+        }
+    }
+
+    fn room_of(&mut self, id: PlayerId) -> std::result::Result<(String, &mut Room), Refusal> {
+        let code = self.player_rooms.get(&id).ok_or_else(|| not_allowed("not in a room"))?.clone();
+        let room = self.rooms.get_mut(&code).ok_or_else(|| not_allowed("not in a room"))?;
+        Ok((code, room))
+    }
+
+    // Four letters, leaving out I and O so codes read back unambiguously
+    fn new_code(&self) -> String {
+        const LETTERS: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
+        let mut rng = rand::thread_rng();
+        loop {
+            let code: String = (0..4).map(|_| LETTERS[rng.gen_range(0..LETTERS.len())] as char).collect();
+            if !self.rooms.contains_key(&code) {
+                return code;
+            }
+        }
     }
 }
 
-#[cfg(target_arch = "wasm32")]
-impl MultiplayerManager {
-    pub async fn connect_web(&mut self, _room_id: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
-        // Connect to WebSocket server for web multiplayer
-        Ok(())
+fn broadcast(to: impl Iterator<Item = PlayerId>, except: Option<PlayerId>, message: MultiplayerMessage) -> Vec<Effect> {
+    to.filter(|id| Some(*id) != except).map(|id| Effect::Send(id, message.clone())).collect()
+}
+
+// The lobby together with the channel to each connected player's socket writer
+struct Hub {
+    lobby: Lobby,
+    senders: HashMap<PlayerId, UnboundedSender<MultiplayerMessage>>,
+}
+
+type SharedHub = Arc<Mutex<Hub>>;
+
+fn apply(hub: &SharedHub, effects: Vec<Effect>) {
+    for effect in effects {
+        match effect {
+            Effect::Send(id, message) => {
+                if let Some(sender) = hub.lock().unwrap().senders.get(&id) {
+                    let _ = sender.send(message);
+                }
+            }
+            // Dropping the sender ends the writer, which closes the socket
+            Effect::Close(id) => {
+                hub.lock().unwrap().senders.remove(&id);
+            }
+            Effect::StartAfter(room, delay) => {
+                let hub = Arc::clone(hub);
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    let effects = hub.lock().unwrap().lobby.go(&room);
+                    apply(&hub, effects);
+                });
+            }
+        }
     }
 }
 
-#[cfg(feature = "multiplayer")]
-impl MultiplayerManager {
-    pub async fn connect_to_server(&self, url: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let (ws_stream, _) = tokio_tungstenite::connect_async(url).await?;
-        let (mut write, mut read) = ws_stream.split();
+/// WebSocket race server. Each connection is a player; rooms, the countdown and
+/// standings are handled by a [`Lobby`].
+pub struct RaceServer {
+    listener: TcpListener,
+    countdown: Duration,
+}
+
+impl RaceServer {
+    pub async fn bind(addr: &str) -> Result<Self> {
+        Ok(Self { listener: TcpListener::bind(addr).await?, countdown: DEFAULT_COUNTDOWN })
+    }
+
+    pub fn countdown(mut self, countdown: Duration) -> Self {
+        self.countdown = countdown;
+        self
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Accepts players until the listener fails.
+    pub async fn run(self) -> Result<()> {
+        let hub = Arc::new(Mutex::new(Hub { lobby: Lobby::new(self.countdown), senders: HashMap::new() }));
+        loop {
+            let (stream, _) = self.listener.accept().await?;
+            let hub = Arc::clone(&hub);
+            tokio::spawn(async move {
+                let _ = serve_player(stream, hub).await;
+            });
+        }
+    }
+}
+
+async fn serve_player(stream: TcpStream, hub: SharedHub) -> Result<()> {
+    let socket = tokio_tungstenite::accept_async(stream).await?;
+    let (mut write, mut read) = socket.split();
+    let (sender, mut outgoing) = mpsc::unbounded_channel::<MultiplayerMessage>();
+    let id = {
+        let mut hub = hub.lock().unwrap();
+        let id = hub.lobby.connect();
+        hub.senders.insert(id, sender);
+        id
+    };
+
+    let writer = tokio::spawn(async move {
+        while let Some(message) = outgoing.recv().await {
+            if write.send(Message::Text(message.to_json())).await.is_err() {
+                break;
+            }
+        }
+        let _ = write.close().await;
+    });
+
+    while let Some(frame) = read.next().await {
+        match frame {
+            Ok(Message::Text(text)) => {
+                let effects = match MultiplayerMessage::from_json(&text) {
+                    Ok(message) => hub.lock().unwrap().lobby.handle(id, message),
+                    Err(refusal) => vec![Effect::Send(id, refusal)],
+                };
+                apply(&hub, effects);
+            }
+            Ok(Message::Close(_)) | Err(_) => break,
+            Ok(_) => {}
+        }
+    }
 
-        // Send join message
-        let join_msg = serde_json::to_string(&MultiplayerMessage::Join)?;
-        write.send(Message::Text(join_msg)).await?;
+    let effects = {
+        let mut hub = hub.lock().unwrap();
+        hub.senders.remove(&id);
+        hub.lobby.disconnect(id)
+    };
+    apply(&hub, effects);
+    let _ = writer.await;
+    Ok(())
+}
+
+/// A connection to a race server. Messages arrive on a background task and are
+/// queued, so a UI can poll with [`try_recv`](Self::try_recv) between frames.
+pub struct RaceClient {
+    /// This connection's id, from the server's welcome.
+    pub player_id: PlayerId,
+    outgoing: UnboundedSender<MultiplayerMessage>,
+    incoming: UnboundedReceiver<MultiplayerMessage>,
+}
+
+impl RaceClient {
+    /// Connects to `url`, e.g. `ws://127.0.0.1:7878`, and says hello. Fails if the
+    /// server speaks another protocol version.
+    pub async fn connect(url: &str) -> Result<Self> {
+        let (socket, _) = tokio_tungstenite::connect_async(url).await?;
+        let (mut write, mut read) = socket.split();
+        let (outgoing, mut to_send) = mpsc::unbounded_channel::<MultiplayerMessage>();
+        let (received, incoming) = mpsc::unbounded_channel();
 
-        // Handle incoming messages
         tokio::spawn(async move {
-            while let Some(msg) = read.next().await {
-                match msg {
-                    Ok(Message::Text(text)) => {
-                        println!("Received: {}", text);
-                    }
-                    Err(e) => {
-                        eprintln!("Error receiving message: {}", e);
+            while let Some(message) = to_send.recv().await {
+                if write.send(Message::Text(message.to_json())).await.is_err() {
+                    break;
+                }
+            }
+            let _ = write.close().await;
+        });
+        tokio::spawn(async move {
+            while let Some(Ok(frame)) = read.next().await {
+                if let Message::Text(text) = frame {
+                    let message = MultiplayerMessage::from_json(&text).unwrap_or_else(|refusal| refusal);
+                    if received.send(message).is_err() {
                         break;
                     }
-                    _ => {}
                 }
             }
         });
 
+        let mut client = Self { player_id: 0, outgoing, incoming };
+        client.send(MultiplayerMessage::hello(CLIENT_NAME))?;
+        match tokio::time::timeout(HANDSHAKE_TIMEOUT, client.recv()).await {
+            Ok(Some(MultiplayerMessage::Welcome { player_id, .. })) => {
+                client.player_id = player_id;
+                Ok(client)
+            }
+            Ok(Some(MultiplayerMessage::Error { message, .. })) => Err(message.into()),
+            Ok(Some(other)) => Err(format!("unexpected reply to hello: {:?}", other).into()),
+            Ok(None) => Err("the server closed the connection".into()),
+            Err(_) => Err("the server did not answer the hello".into()),
+        }
+    }
+
+    pub fn send(&self, message: MultiplayerMessage) -> Result<()> {
+        self.outgoing.send(message).map_err(|_| "connection closed".into())
+    }
+
+    /// Next message from the server, or `None` once the connection is gone.
+    pub async fn recv(&mut self) -> Option<MultiplayerMessage> {
+        self.incoming.recv().await
+    }
+
+    /// Next queued message without waiting.
+    pub fn try_recv(&mut self) -> Option<MultiplayerMessage> {
+        self.incoming.try_recv().ok()
+    }
+}
+
+/// Hosts or joins races for a front-end: one connection at a time.
+pub struct MultiplayerManager {
+    pub config: GameConfig,
+    client: Option<RaceClient>,
+}
+
+impl MultiplayerManager {
+    pub fn new(config: GameConfig) -> Self {
+        Self { config, client: None }
+    }
+
+    /// Runs a race server on `addr` in the background and returns the address it
+    /// listens on (useful with port 0).
+    pub async fn start_server(&self, addr: &str) -> Result<SocketAddr> {
+        let server = RaceServer::bind(addr).await?;
+        let local = server.local_addr()?;
+        tokio::spawn(server.run());
+        Ok(local)
+    }
+
+    /// Starts a server on `addr`, connects to it and opens a room. The room code
+    /// arrives in a `Joined` message.
+    pub async fn host_game(&mut self, addr: &str, name: &str) -> Result<SocketAddr> {
+        let local = self.start_server(addr).await?;
+        self.connect_to_server(&format!("ws://{}", local)).await?;
+        self.send(MultiplayerMessage::CreateRoom { name: name.to_string() })?;
+        Ok(local)
+    }
+
+    pub async fn join_game(&mut self, url: &str, room: &str, name: &str) -> Result<()> {
+        self.connect_to_server(url).await?;
+        self.send(MultiplayerMessage::JoinRoom { room: room.to_string(), name: name.to_string() })
+    }
+
+    pub async fn connect_to_server(&mut self, url: &str) -> Result<()> {
+        self.client = Some(RaceClient::connect(url).await?);
         Ok(())
     }
-} 
\ No newline at end of file
+
+    pub fn send(&self, message: MultiplayerMessage) -> Result<()> {
+        self.client.as_ref().ok_or("not connected")?.send(message)
+    }
+
+    pub fn send_progress(&self, progress: f32, wpm: f32) -> Result<()> {
+        self.send(MultiplayerMessage::Progress { progress, wpm })
+    }
+
+    /// Reports reaching the end of the text with the keystrokes that got there.
+    pub fn send_finish(&self, events: Vec<Keystroke>) -> Result<()> {
+        self.send(MultiplayerMessage::Finish { events })
+    }
+
+    /// Messages received since the last call.
+    pub fn poll(&mut self) -> Vec<MultiplayerMessage> {
+        let Some(client) = &mut self.client else {
+            return Vec::new();
+        };
+        std::iter::from_fn(|| client.try_recv()).collect()
+    }
+
+    /// Whether the server is still there. Messages it sent before going away can
+    /// still be polled.
+    pub fn is_connected(&self) -> bool {
+        self.client.as_ref().is_some_and(|c| !c.incoming.is_closed())
+    }
+}
+
+/// The `ws://` URL of a server given as `host:port` or as a URL already.
+pub fn server_url(addr: &str) -> String {
+    if addr.contains("://") {
+        addr.to_string()
+    } else {
+        format!("ws://{}", addr)
+    }
+}
+
+/// A [`MultiplayerManager`] for front-ends without an async runtime of their own,
+/// such as the TUI. It owns the runtime its connection, and any server it hosts,
+/// runs on; connecting blocks until the server has answered.
+pub struct RaceConnection {
+    runtime: tokio::runtime::Runtime,
+    manager: MultiplayerManager,
+}
+
+impl RaceConnection {
+    /// Starts a server listening on `addr` and opens a room on it. The server
+    /// stops when the connection is dropped.
+    pub fn host(addr: &str, name: &str) -> Result<(Self, SocketAddr)> {
+        let mut connection = Self::new()?;
+        let local = connection.runtime.block_on(connection.manager.host_game(addr, name))?;
+        Ok((connection, local))
+    }
+
+    /// Joins `room` on the server at `addr`.
+    pub fn join(addr: &str, room: &str, name: &str) -> Result<Self> {
+        let mut connection = Self::new()?;
+        connection.runtime.block_on(connection.manager.join_game(&server_url(addr), room, name))?;
+        Ok(connection)
+    }
+
+    fn new() -> Result<Self> {
+        Ok(Self { runtime: tokio::runtime::Runtime::new()?, manager: MultiplayerManager::new(GameConfig::default()) })
+    }
+
+    pub fn send(&self, message: MultiplayerMessage) -> Result<()> {
+        self.manager.send(message)
+    }
+
+    pub fn poll(&mut self) -> Vec<MultiplayerMessage> {
+        self.manager.poll()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.manager.is_connected()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sent_to(effects: &[Effect], id: PlayerId) -> Vec<&MultiplayerMessage> {
+        effects
+            .iter()
+            .filter_map(|e| match e {
+                Effect::Send(to, message) if *to == id => Some(message),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // A connection whose hello was accepted
+    fn greeted(lobby: &mut Lobby) -> PlayerId {
+        let id = lobby.connect();
+        lobby.handle(id, MultiplayerMessage::hello("test"));
+        id
+    }
+
+    // Finishing `text` typed one key every `gap_ms`
+    fn finish(text: &str, gap_ms: u64) -> MultiplayerMessage {
+        let events = text
+            .chars()
+            .zip(1..)
+            .map(|(ch, i)| Keystroke { at_ms: i * gap_ms, action: crate::replay::KeyAction::Insert { ch } })
+            .collect();
+        MultiplayerMessage::Finish { events }
+    }
+
+    fn room_code(effects: &[Effect]) -> String {
+        effects
+            .iter()
+            .find_map(|e| match e {
+                Effect::Send(_, MultiplayerMessage::Joined { room, .. }) => Some(room.clone()),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_lobby_runs_a_race() {
+        let mut lobby = Lobby::new(Duration::from_secs(3));
+        let (host, guest) = (greeted(&mut lobby), greeted(&mut lobby));
+        let code = room_code(&lobby.handle(host, MultiplayerMessage::CreateRoom { name: "ann".into() }));
+        let missing = lobby.handle(guest, MultiplayerMessage::JoinRoom { room: "ZZZZ".into(), name: "bo".into() });
+        assert!(matches!(sent_to(&missing, guest)[0], MultiplayerMessage::Error { code: ErrorCode::NoSuchRoom, .. }));
+        let joined = lobby.handle(guest, MultiplayerMessage::JoinRoom { room: code.to_lowercase(), name: "bo".into() });
+        assert!(matches!(sent_to(&joined, host)[0], MultiplayerMessage::PlayerJoined { .. }));
+
+        // Only the host starts, once everyone else is ready
+        let refused = lobby.handle(guest, MultiplayerMessage::StartRace { text: "go".into() });
+        assert!(matches!(sent_to(&refused, guest)[0], MultiplayerMessage::Error { code: ErrorCode::NotAllowed, .. }));
+        let unready = lobby.handle(host, MultiplayerMessage::StartRace { text: "cat hat".into() });
+        assert!(matches!(sent_to(&unready, host)[0], MultiplayerMessage::Error { .. }));
+        let ready = lobby.handle(guest, MultiplayerMessage::Ready { ready: true });
+        assert_eq!(sent_to(&ready, host), vec![&MultiplayerMessage::PlayerReady { player_id: guest, ready: true }]);
+        let started = lobby.handle(host, MultiplayerMessage::StartRace { text: "cat hat".into() });
+        assert!(started.contains(&Effect::StartAfter(code.clone(), Duration::from_secs(3))));
+        assert_eq!(sent_to(&started, guest), vec![
+            &MultiplayerMessage::RaceText { text: "cat hat".into() },
+            &MultiplayerMessage::Countdown { start_in_ms: 3000 }
+        ]);
+        // Progress before the go is refused
+        let early = lobby.handle(guest, MultiplayerMessage::Progress { progress: 10.0, wpm: 50.0 });
+        assert!(matches!(sent_to(&early, guest)[0], MultiplayerMessage::Error { .. }));
+
+        assert_eq!(lobby.go(&code).len(), 2);
+        assert_eq!(lobby.phase(&code), Some(RacePhase::Racing));
+        let progress = lobby.handle(guest, MultiplayerMessage::Progress { progress: 40.0, wpm: 60.0 });
+        assert_eq!(
+            sent_to(&progress, host),
+            vec![&MultiplayerMessage::PlayerProgress { player_id: guest, progress: 40.0, wpm: 60.0 }]
+        );
+        assert!(sent_to(&progress, guest).is_empty());
+
+        // Results come from the keystrokes: 7 characters in 1.4 seconds is 60 WPM
+        let finished = lobby.handle(guest, finish("cat hat", 200));
+        assert!(matches!(
+            sent_to(&finished, host)[0],
+            MultiplayerMessage::PlayerFinished { place: 1, wpm, time, .. } if *wpm == 60.0 && *time == 1.4
+        ));
+        let done = lobby.handle(host, finish("cat hat", 300));
+        let Some(MultiplayerMessage::Results { standings }) = sent_to(&done, host).last().cloned() else {
+            panic!("no results");
+        };
+        assert_eq!(standings.iter().map(|s| (s.player_id, s.place)).collect::<Vec<_>>(), vec![
+            (guest, Some(1)),
+            (host, Some(2))
+        ]);
+        assert_eq!(lobby.phase(&code), Some(RacePhase::Lobby));
+
+        // Everyone readies up again for the next race
+        let again = lobby.handle(host, MultiplayerMessage::StartRace { text: "cat hat".into() });
+        assert!(matches!(sent_to(&again, host)[0], MultiplayerMessage::Error { .. }));
+    }
+
+    #[test]
+    fn test_handshake_comes_first_and_checks_the_version() {
+        let mut lobby = Lobby::new(Duration::ZERO);
+        let (old, new) = (lobby.connect(), lobby.connect());
+        let early = lobby.handle(new, MultiplayerMessage::CreateRoom { name: "ann".into() });
+        assert!(matches!(sent_to(&early, new)[0], MultiplayerMessage::Error { code: ErrorCode::NotAllowed, .. }));
+        assert_eq!(lobby.handle(new, MultiplayerMessage::Ping { nonce: 9 }), vec![Effect::Send(
+            new,
+            MultiplayerMessage::Pong { nonce: 9 }
+        )]);
+
+        let welcomed = lobby.handle(new, MultiplayerMessage::hello("test"));
+        assert_eq!(sent_to(&welcomed, new), vec![&MultiplayerMessage::Welcome {
+            version: PROTOCOL_VERSION,
+            player_id: new
+        }]);
+
+        let refused = lobby.handle(old, MultiplayerMessage::Hello { version: 0, client: "old".into() });
+        assert!(matches!(
+            sent_to(&refused, old)[0],
+            MultiplayerMessage::Error { code: ErrorCode::IncompatibleVersion, .. }
+        ));
+        assert_eq!(refused.last(), Some(&Effect::Close(old)));
+        let still_refused = lobby.handle(old, MultiplayerMessage::CreateRoom { name: "bo".into() });
+        assert!(matches!(sent_to(&still_refused, old)[0], MultiplayerMessage::Error { .. }));
+    }
+
+    #[test]
+    fn test_host_leaving_hands_over_and_closes_race() {
+        let mut lobby = Lobby::new(Duration::ZERO);
+        let (host, guest) = (greeted(&mut lobby), greeted(&mut lobby));
+        let code = room_code(&lobby.handle(host, MultiplayerMessage::CreateRoom { name: "ann".into() }));
+        lobby.handle(guest, MultiplayerMessage::JoinRoom { room: code.clone(), name: "bo".into() });
+        lobby.handle(guest, MultiplayerMessage::Ready { ready: true });
+        lobby.handle(host, MultiplayerMessage::StartRace { text: "cat".into() });
+        lobby.go(&code);
+        lobby.handle(guest, finish("cat", 250));
+
+        // The host was the only one still typing, so the race ends with them gone
+        let left = lobby.disconnect(host);
+        let messages = sent_to(&left, guest);
+        assert_eq!(messages[0], &MultiplayerMessage::PlayerLeft { player_id: host, host: guest });
+        assert!(matches!(messages[1], MultiplayerMessage::Results { .. }));
+
+        lobby.disconnect(guest);
+        assert_eq!(lobby.phase(&code), None);
+    }
+
+    #[test]
+    fn test_rejected_run_is_disqualified() {
+        let mut lobby = Lobby::new(Duration::ZERO);
+        let (host, guest) = (greeted(&mut lobby), greeted(&mut lobby));
+        let code = room_code(&lobby.handle(host, MultiplayerMessage::CreateRoom { name: "ann".into() }));
+        lobby.handle(guest, MultiplayerMessage::JoinRoom { room: code.clone(), name: "bo".into() });
+        lobby.handle(guest, MultiplayerMessage::Ready { ready: true });
+        lobby.handle(host, MultiplayerMessage::StartRace { text: "cat hat".into() });
+        lobby.go(&code);
+
+        // Claiming a different text is not finishing this one
+        let wrong = lobby.handle(guest, finish("dog", 300));
+        assert!(matches!(sent_to(&wrong, guest)[0], MultiplayerMessage::Error { code: ErrorCode::RejectedRun, .. }));
+        assert!(matches!(
+            sent_to(&wrong, host)[0],
+            MultiplayerMessage::PlayerDisqualified { player_id, .. } if *player_id == guest
+        ));
+        let again = lobby.handle(guest, finish("cat hat", 300));
+        assert!(matches!(sent_to(&again, guest)[0], MultiplayerMessage::Error { code: ErrorCode::NotAllowed, .. }));
+
+        // The race ends once everyone left is done, with the disqualified last
+        let done = lobby.handle(host, finish("cat hat", 300));
+        let Some(MultiplayerMessage::Results { standings }) = sent_to(&done, guest).last().cloned() else {
+            panic!("no results");
+        };
+        assert_eq!(standings[0].place, Some(1));
+        assert_eq!(standings[1].player_id, guest);
+        assert!(standings[1].disqualified.is_some());
+    }
+
+    #[test]
+    fn test_run_shorter_than_the_race_is_rejected() {
+        let mut lobby = Lobby::new(Duration::ZERO);
+        let host = greeted(&mut lobby);
+        let code = room_code(&lobby.handle(host, MultiplayerMessage::CreateRoom { name: "ann".into() }));
+        lobby.handle(host, MultiplayerMessage::StartRace { text: "cat hat".into() });
+        lobby.go(&code);
+        // Pretend the go was half a minute ago
+        lobby.rooms.get_mut(&code).unwrap().started = Some(Instant::now() - Duration::from_secs(30));
+
+        // A human rhythm, but over in under two seconds
+        let squeezed = lobby.handle(host, finish("cat hat", 250));
+        assert!(matches!(
+            sent_to(&squeezed, host)[0],
+            MultiplayerMessage::Error { code: ErrorCode::RejectedRun, message } if message.contains("race had run")
+        ));
+    }
+
+    async fn next(client: &mut RaceClient) -> MultiplayerMessage {
+        tokio::time::timeout(Duration::from_secs(5), client.recv()).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_race_over_loopback() {
+        let server = RaceServer::bind("127.0.0.1:0").await.unwrap().countdown(Duration::from_millis(50));
+        let url = format!("ws://{}", server.local_addr().unwrap());
+        tokio::spawn(server.run());
+
+        let mut host = RaceClient::connect(&url).await.unwrap();
+        host.send(MultiplayerMessage::CreateRoom { name: "ann".into() }).unwrap();
+        let MultiplayerMessage::Joined { room, .. } = next(&mut host).await else { panic!("not joined") };
+
+        let mut guests = Vec::new();
+        for name in ["bo", "cy"] {
+            let mut guest = RaceClient::connect(&url).await.unwrap();
+            guest.send(MultiplayerMessage::JoinRoom { room: room.clone(), name: name.into() }).unwrap();
+            let MultiplayerMessage::Joined { players, .. } = next(&mut guest).await else { panic!("not joined") };
+            assert_eq!(players.last().unwrap().name, name);
+            guests.push(guest);
+        }
+        assert!(matches!(next(&mut host).await, MultiplayerMessage::PlayerJoined { .. }));
+        assert!(matches!(next(&mut host).await, MultiplayerMessage::PlayerJoined { .. }));
+        assert!(matches!(next(&mut guests[0]).await, MultiplayerMessage::PlayerJoined { .. }));
+
+        for guest in &guests {
+            guest.send(MultiplayerMessage::Ready { ready: true }).unwrap();
+        }
+        // Everyone hears both guests ready up
+        for client in std::iter::once(&mut host).chain(guests.iter_mut()) {
+            for _ in 0..2 {
+                assert!(matches!(next(client).await, MultiplayerMessage::PlayerReady { ready: true, .. }));
+            }
+        }
+
+        host.send(MultiplayerMessage::StartRace { text: "cat hat".into() }).unwrap();
+        for client in std::iter::once(&mut host).chain(guests.iter_mut()) {
+            assert_eq!(next(client).await, MultiplayerMessage::RaceText { text: "cat hat".into() });
+            assert!(matches!(next(client).await, MultiplayerMessage::Countdown { .. }));
+            assert_eq!(next(client).await, MultiplayerMessage::Go);
+        }
+
+        guests[1].send(MultiplayerMessage::Progress { progress: 50.0, wpm: 80.0 }).unwrap();
+        assert!(matches!(next(&mut host).await, MultiplayerMessage::PlayerProgress { progress, .. } if progress == 50.0));
+
+        // Wait for each finish to be announced so the arrival order is fixed
+        let mut places = Vec::new();
+        for (finisher, gap_ms) in [(2, 200), (0, 300), (1, 400)] {
+            let message = finish("cat hat", gap_ms);
+            match finisher {
+                0 => host.send(message).unwrap(),
+                i => guests[i - 1].send(message).unwrap(),
+            }
+            loop {
+                if let MultiplayerMessage::PlayerFinished { place, .. } = next(&mut guests[0]).await {
+                    places.push(place);
+                    break;
+                }
+            }
+        }
+        let MultiplayerMessage::Results { standings } = next(&mut guests[0]).await else { panic!("no results") };
+        assert_eq!(places, vec![1, 2, 3]);
+        let names: Vec<&str> = standings.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["cy", "ann", "bo"]);
+    }
+
+    #[tokio::test]
+    async fn test_incompatible_client_is_disconnected() {
+        let server = RaceServer::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", server.local_addr().unwrap());
+        tokio::spawn(server.run());
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        let hello = MultiplayerMessage::Hello { version: PROTOCOL_VERSION + 1, client: "future".into() };
+        socket.send(Message::Text(hello.to_json())).await.unwrap();
+        let Some(Ok(Message::Text(reply))) = socket.next().await else { panic!("no reply") };
+        assert!(matches!(
+            MultiplayerMessage::from_json(&reply),
+            Ok(MultiplayerMessage::Error { code: ErrorCode::IncompatibleVersion, .. })
+        ));
+        let closed = tokio::time::timeout(Duration::from_secs(5), socket.next()).await.unwrap();
+        assert!(matches!(closed, None | Some(Ok(Message::Close(_))) | Some(Err(_))));
+    }
+}
@@ -0,0 +1,110 @@
+//! How forgiving the engine is about characters that are awkward to type: the
+//! same letter encoded two ways, typographic punctuation on a US layout, and
+//! accented letters in names and quotes.
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Normalization {
+    /// Treat canonically equivalent text as equal, e.g. a precomposed `é` and an
+    /// `e` followed by a combining accent.
+    pub unicode: bool,
+    /// Accept ASCII stand-ins for curly quotes, dashes and ellipses.
+    pub ascii_punctuation: bool,
+    /// Strip accents from passages as they load, so `café` is typed `cafe`.
+    pub strip_diacritics: bool,
+}
+
+impl Default for Normalization {
+    fn default() -> Self {
+        Self { unicode: true, ascii_punctuation: false, strip_diacritics: false }
+    }
+}
+
+impl Normalization {
+    /// No normalization at all: every character must be typed exactly.
+    pub const EXACT: Self = Self { unicode: false, ascii_punctuation: false, strip_diacritics: false };
+
+    /// The text to type for `text`.
+    pub fn prepare(&self, text: &str) -> String {
+        if self.strip_diacritics {
+            strip_diacritics(text)
+        } else {
+            self.canonical(text)
+        }
+    }
+
+    /// `input` in the form the engine keeps it in, NFC when equivalents are equal.
+    pub fn canonical(&self, input: &str) -> String {
+        if self.unicode {
+            input.nfc().collect()
+        } else {
+            input.to_string()
+        }
+    }
+
+    /// The character to take for `typed` where `expected` is due, and the rest
+    /// of its ASCII stand-in still to swallow (the `..` of `...` for `…`).
+    pub fn substitute(&self, expected: Option<char>, typed: char) -> (char, &'static str) {
+        let stand_in = expected.filter(|_| self.ascii_punctuation).and_then(|e| Some((e, ascii_stand_in(e)?)));
+        match stand_in {
+            Some((expected, stand_in)) if stand_in.starts_with(typed) => (expected, &stand_in[typed.len_utf8()..]),
+            _ => (typed, ""),
+        }
+    }
+
+    /// Whether the grapheme cluster `typed` is `expected` or on its way to it,
+    /// as when the `e` of an `é` is typed before its accent.
+    pub fn is_typing(&self, typed: &str, expected: &str) -> bool {
+        if self.unicode {
+            expected.nfd().collect::<String>().starts_with(&typed.nfd().collect::<String>())
+        } else {
+            expected.starts_with(typed)
+        }
+    }
+}
+
+/// The ASCII a US keyboard types for a typographic character, if it has one.
+pub fn ascii_stand_in(ch: char) -> Option<&'static str> {
+    match ch {
+        '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{2032}' => Some("'"),
+        '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{2033}' => Some("\""),
+        '\u{2010}' | '\u{2011}' | '\u{2012}' | '\u{2013}' | '\u{2014}' | '\u{2015}' | '\u{2212}' => Some("-"),
+        '\u{2026}' => Some("..."),
+        '\u{00A0}' | '\u{202F}' => Some(" "),
+        _ => None,
+    }
+}
+
+/// `text` without combining accents. Letters that are not an accented base
+/// letter, like `ø` or `ß`, are kept.
+pub fn strip_diacritics(text: &str) -> String {
+    text.nfd().filter(|&ch| !is_combining_mark(ch)).nfc().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalization_options() {
+        let options = Normalization::default();
+        assert_eq!(options.prepare("cafe\u{301}"), "café");
+        assert!(options.is_typing("e", "é"));
+        assert!(options.is_typing("e\u{301}", "é"));
+        assert!(!options.is_typing("é", "e"));
+        assert!(!Normalization::EXACT.is_typing("e", "é"));
+        assert_eq!(options.substitute(Some('\u{2019}'), '\''), ('\'', ""));
+
+        let options = Normalization { ascii_punctuation: true, ..options };
+        assert_eq!(options.substitute(Some('\u{2019}'), '\''), ('\u{2019}', ""));
+        assert_eq!(options.substitute(Some('\u{2026}'), '.'), ('\u{2026}', ".."));
+        assert_eq!(options.substitute(Some('\u{2014}'), 'x'), ('x', ""));
+
+        let options = Normalization { strip_diacritics: true, ..options };
+        assert_eq!(options.prepare("Dvořák née Zoë, Øre"), "Dvorak nee Zoe, Øre");
+    }
+}
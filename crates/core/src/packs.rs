@@ -0,0 +1,336 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::text::Text;
+
+// Packs shipped with the binary, so there is always something to type
+const BUNDLED_PACKS: [(&str, &str); 5] = [
+    ("data/packs/code.json", include_str!("../../../data/packs/code.json")),
+    ("data/packs/general.json", include_str!("../../../data/packs/general.json")),
+    ("data/packs/literature.json", include_str!("../../../data/packs/literature.json")),
+    ("data/packs/programming.json", include_str!("../../../data/packs/programming.json")),
+    ("data/packs/quotes.json", include_str!("../../../data/packs/quotes.json")),
+];
+
+/// A problem found while loading a pack. Loading never fails as a whole: bad files
+/// and entries are skipped and reported with enough context to fix them.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum PackWarning {
+    #[error("{origin}: {message}")]
+    Unreadable { origin: String, message: String },
+    #[error("{origin}:{line}:{column}: invalid JSON: {message}")]
+    Syntax { origin: String, line: usize, column: usize, message: String },
+    #[error("{origin}:{line}: skipped entry {index}: {reason}")]
+    Entry { origin: String, line: usize, index: usize, reason: String },
+}
+
+/// What one pack contributed to a library.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackInfo {
+    pub origin: String,
+    pub texts: usize,
+    pub duplicates: usize,
+    pub skipped: usize,
+    pub categories: BTreeSet<String>,
+}
+
+/// Texts merged from every pack source, deduplicated the same way as
+/// `scripts/merge_packs.js` (by category and case-insensitive content).
+#[derive(Debug, Default)]
+pub struct TextLibrary {
+    pub texts: Vec<Text>,
+    pub warnings: Vec<PackWarning>,
+    pub packs: Vec<PackInfo>,
+    seen: HashSet<(String, String)>,
+}
+
+impl TextLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Bundled packs, then packs in the user data directory, then `extra`
+    /// (a pack file or a directory of them, e.g. from `--texts`).
+    pub fn load(extra: Option<&Path>) -> Self {
+        let mut library = Self::new();
+        library.add_bundled();
+        if let Some(dir) = user_packs_dir() {
+            if dir.is_dir() {
+                library.add_path(&dir);
+            }
+        }
+        if let Some(path) = extra {
+            library.add_path(path);
+        }
+        library
+    }
+
+    /// Adds a single text that did not come from a pack, such as `--file`.
+    /// Returns false if the library already had it.
+    pub fn add_text(&mut self, text: Text) -> bool {
+        let key = (text.category.clone(), text.content.trim().to_lowercase());
+        if !self.seen.insert(key) {
+            return false;
+        }
+        self.texts.push(text);
+        true
+    }
+
+    pub fn add_bundled(&mut self) {
+        for (origin, json) in BUNDLED_PACKS {
+            self.add_pack(origin, json);
+        }
+    }
+
+    /// Adds a single pack file, or every `*.json` file in a directory in name order.
+    pub fn add_path(&mut self, path: &Path) {
+        if path.is_dir() {
+            let entries = match fs::read_dir(path) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    self.warn_unreadable(path, &e);
+                    return;
+                }
+            };
+            let mut files: Vec<PathBuf> = entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|ext| ext == "json"))
+                .collect();
+            files.sort();
+            for file in files {
+                self.add_file(&file);
+            }
+        } else {
+            self.add_file(path);
+        }
+    }
+
+    fn add_file(&mut self, path: &Path) {
+        match fs::read_to_string(path) {
+            Ok(json) => self.add_pack(&path.display().to_string(), &json),
+            Err(e) => self.warn_unreadable(path, &e),
+        }
+    }
+
+    fn warn_unreadable(&mut self, path: &Path, err: &std::io::Error) {
+        self.warnings.push(PackWarning::Unreadable {
+            origin: path.display().to_string(),
+            message: err.to_string(),
+        });
+    }
+
+    /// Parses one pack: a JSON array of `{ category, content, attribution }` objects.
+    /// `origin` names the pack in warnings and is the fallback attribution. Code
+    /// snippets also give their `language`; prose is taken to be English.
+    pub fn add_pack(&mut self, origin: &str, json: &str) {
+        let entries: Vec<Value> = match serde_json::from_str::<Value>(json) {
+            Ok(Value::Array(entries)) => entries,
+            Ok(_) => {
+                self.warnings.push(PackWarning::Syntax {
+                    origin: origin.to_string(),
+                    line: 1,
+                    column: 1,
+                    message: "expected a top-level array of texts".to_string(),
+                });
+                return;
+            }
+            Err(e) => {
+                self.warnings.push(PackWarning::Syntax {
+                    origin: origin.to_string(),
+                    line: e.line(),
+                    column: e.column(),
+                    message: e.to_string(),
+                });
+                return;
+            }
+        };
+
+        let mut info = PackInfo {
+            origin: origin.to_string(),
+            texts: 0,
+            duplicates: 0,
+            skipped: 0,
+            categories: BTreeSet::new(),
+        };
+        let lines = entry_lines(json);
+        for (index, entry) in entries.iter().enumerate() {
+            let line = lines.get(index).copied().unwrap_or(1);
+            match parse_entry(entry, origin) {
+                Ok(text) => {
+                    let category = text.category.clone();
+                    if self.add_text(text) {
+                        info.texts += 1;
+                        info.categories.insert(category);
+                    } else {
+                        info.duplicates += 1;
+                    }
+                }
+                Err(reason) => {
+                    info.skipped += 1;
+                    self.warnings.push(PackWarning::Entry {
+                        origin: origin.to_string(),
+                        line,
+                        index,
+                        reason,
+                    });
+                }
+            }
+        }
+        self.packs.push(info);
+    }
+}
+
+/// Directory scanned for user packs, e.g. `~/.local/share/typerpunk/packs` on Linux.
+#[cfg(feature = "dirs")]
+pub fn user_packs_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("typerpunk").join("packs"))
+}
+
+#[cfg(not(feature = "dirs"))]
+pub fn user_packs_dir() -> Option<PathBuf> {
+    None
+}
+
+fn parse_entry(entry: &Value, origin: &str) -> Result<Text, String> {
+    let obj = entry.as_object().ok_or("expected an object")?;
+    let field = |name: &str| -> Result<Option<&str>, String> {
+        match obj.get(name) {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(s)) => Ok(Some(s.as_str())),
+            Some(_) => Err(format!("`{}` must be a string", name)),
+        }
+    };
+
+    let content = field("content")?.unwrap_or_default();
+    if content.trim().is_empty() {
+        return Err("missing `content`".to_string());
+    }
+    let category = field("category")?.filter(|c| !c.is_empty()).unwrap_or("general");
+    let attribution = field("attribution")?.unwrap_or(origin);
+    let language = field("language")?.filter(|l| !l.is_empty()).unwrap_or("en");
+    Ok(Text::from_all(content, attribution, language, category))
+}
+
+// Line on which each element of the top-level array starts. serde_json only
+// reports positions for syntax errors, so find them with a small scan instead.
+fn entry_lines(json: &str) -> Vec<usize> {
+    let mut lines = Vec::new();
+    let mut line = 1;
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut expecting_entry = false;
+
+    for c in json.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                '\n' => line += 1,
+                _ => {}
+            }
+            continue;
+        }
+        if c == '\n' {
+            line += 1;
+            continue;
+        }
+        if c.is_whitespace() {
+            continue;
+        }
+        if depth == 1 && expecting_entry && c != ']' {
+            lines.push(line);
+            expecting_entry = false;
+        }
+        match c {
+            '"' => in_string = true,
+            '[' | '{' => {
+                depth += 1;
+                if depth == 1 {
+                    expecting_entry = true;
+                }
+            }
+            ']' | '}' => depth = depth.saturating_sub(1),
+            ',' if depth == 1 => expecting_entry = true,
+            _ => {}
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_packs_load_cleanly() {
+        let mut library = TextLibrary::new();
+        library.add_bundled();
+        assert!(!library.texts.is_empty());
+        assert!(library.warnings.is_empty(), "{:?}", library.warnings);
+        let code: Vec<&Text> = library.texts.iter().filter(|t| t.is_code()).collect();
+        assert!(!code.is_empty());
+        assert!(code.iter().all(|t| t.language != "en" && t.content.contains('\n')));
+    }
+
+    #[test]
+    fn test_malformed_entries_are_reported_with_line() {
+        let json = r#"[
+  {"category":"general","content":"First text.","attribution":"A"},
+  {"category":"general","attribution":"B"},
+  {
+    "category": "general",
+    "content": 42
+  },
+  {"content":"No category."}
+]"#;
+        let mut library = TextLibrary::new();
+        library.add_pack("test.json", json);
+
+        assert_eq!(library.texts.len(), 2);
+        assert_eq!(library.texts[1].category, "general");
+        assert_eq!(library.texts[1].source, "test.json");
+        assert_eq!(library.warnings, vec![
+            PackWarning::Entry {
+                origin: "test.json".to_string(),
+                line: 3,
+                index: 1,
+                reason: "missing `content`".to_string(),
+            },
+            PackWarning::Entry {
+                origin: "test.json".to_string(),
+                line: 4,
+                index: 2,
+                reason: "`content` must be a string".to_string(),
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_syntax_error_skips_only_that_pack() {
+        let mut library = TextLibrary::new();
+        library.add_pack("broken.json", "[\n  {\"content\": \"oops\"\n]");
+        library.add_pack("ok.json", r#"[{"content":"Fine."}]"#);
+
+        assert_eq!(library.texts.len(), 1);
+        assert!(matches!(
+            &library.warnings[..],
+            [PackWarning::Syntax { line: 3, .. }]
+        ));
+    }
+
+    #[test]
+    fn test_duplicates_are_merged() {
+        let mut library = TextLibrary::new();
+        library.add_pack("a.json", r#"[{"category":"x","content":"Same text."}]"#);
+        library.add_pack("b.json", r#"[{"category":"x","content":"same TEXT. "}]"#);
+        assert_eq!(library.texts.len(), 1);
+        assert_eq!(library.packs[1].texts, 0);
+        assert_eq!(library.packs[1].duplicates, 1);
+    }
+}
@@ -0,0 +1,275 @@
+//! Race protocol spoken between clients and the race server.
+//!
+//! Every message is a JSON object in its own WebSocket text frame, tagged with
+//! `"type"`, e.g. `{"type":"progress","progress":42.0,"wpm":71.5}`.
+//!
+//! A session goes:
+//!
+//! 1. The client sends [`Hello`](MultiplayerMessage::Hello) with its
+//!    [`PROTOCOL_VERSION`]. The server answers [`Welcome`](MultiplayerMessage::Welcome)
+//!    with the player's id, or an [`IncompatibleVersion`](ErrorCode::IncompatibleVersion)
+//!    error and closes the connection. Nothing else is accepted before the hello.
+//! 2. The client creates or joins a room; the server answers
+//!    [`Joined`](MultiplayerMessage::Joined) and tells the rest of the room.
+//! 3. Players mark themselves [`Ready`](MultiplayerMessage::Ready). Once all are, the
+//!    host sends [`StartRace`](MultiplayerMessage::StartRace) with the text.
+//! 4. The server sends everyone [`RaceText`](MultiplayerMessage::RaceText), then
+//!    [`Countdown`](MultiplayerMessage::Countdown), then [`Go`](MultiplayerMessage::Go)
+//!    when the countdown runs out.
+//! 5. Clients report [`Progress`](MultiplayerMessage::Progress) as they go and
+//!    [`Finish`](MultiplayerMessage::Finish) with their keystrokes at the end. The
+//!    server relays progress, works out each finisher's result from their
+//!    keystrokes (see [`anticheat`](crate::anticheat)), and sends
+//!    [`Results`](MultiplayerMessage::Results) once everyone is done, after which
+//!    the room is back in its lobby.
+//!
+//! Either side may send [`Ping`](MultiplayerMessage::Ping) at any time and gets a
+//! [`Pong`](MultiplayerMessage::Pong) with the same nonce.
+
+use serde::{Deserialize, Serialize};
+
+use crate::anticheat::Flag;
+use crate::replay::Keystroke;
+
+/// Version of this protocol. Bumped on any change an older peer would misread;
+/// peers on different versions refuse each other at the hello.
+pub const PROTOCOL_VERSION: u32 = 2;
+
+pub type PlayerId = u32;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerInfo {
+    pub id: PlayerId,
+    pub name: String,
+    #[serde(default)]
+    pub ready: bool,
+}
+
+/// One line of the final standings. Players who never finished have no place
+/// and are ranked by how far they got; disqualified players come last.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Standing {
+    pub player_id: PlayerId,
+    pub name: String,
+    pub place: Option<usize>,
+    pub progress: f32,
+    pub wpm: f32,
+    pub time: Option<f32>,
+    /// What was unusual about the run, if anything.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<Flag>,
+    /// Why the run was rejected, for players who were disqualified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disqualified: Option<String>,
+}
+
+/// Why a message was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The peers speak different protocol versions; the connection is closed.
+    IncompatibleVersion,
+    /// The frame was not a message of this protocol.
+    BadMessage,
+    /// No room has the requested code.
+    NoSuchRoom,
+    /// The message is not allowed right now, e.g. starting a race as a guest.
+    NotAllowed,
+    /// The keystrokes of a finished run failed the server's checks.
+    RejectedRun,
+}
+
+/// Messages of the race protocol. The side that sends each is noted first.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MultiplayerMessage {
+    /// Client: first message on a connection.
+    Hello { version: u32, client: String },
+    /// Server: the hello was accepted; this connection is `player_id`.
+    Welcome { version: u32, player_id: PlayerId },
+    /// Client: open a new room and become its host.
+    CreateRoom { name: String },
+    /// Client: join the room with this code.
+    JoinRoom { room: String, name: String },
+    /// Client: leave the current room.
+    LeaveRoom,
+    /// Server: you are in `room`, with everyone already there.
+    Joined { room: String, player_id: PlayerId, host: PlayerId, players: Vec<PlayerInfo> },
+    /// Server: someone else joined your room.
+    PlayerJoined { player: PlayerInfo },
+    /// Server: someone left your room; `host` is the host from now on.
+    PlayerLeft { player_id: PlayerId, host: PlayerId },
+    /// Client: ready (or no longer ready) for the next race.
+    Ready { ready: bool },
+    /// Server: a player's ready state changed.
+    PlayerReady { player_id: PlayerId, ready: bool },
+    /// Host: start a race on `text`.
+    StartRace { text: String },
+    /// Server: the text of the coming race.
+    RaceText { text: String },
+    /// Server: the race begins in `start_in_ms`.
+    Countdown { start_in_ms: u64 },
+    /// Server: the race has begun; start the clock.
+    Go,
+    /// Client: your progress through the text, as a percentage.
+    Progress { progress: f32, wpm: f32 },
+    /// Server: another player's progress.
+    PlayerProgress { player_id: PlayerId, progress: f32, wpm: f32 },
+    /// Client: you reached the end of the text; these are your keystrokes, timed
+    /// from the go. Your result is worked out from them.
+    Finish { events: Vec<Keystroke> },
+    /// Server: a player finished in `place` (1 for the winner), flagged if
+    /// anything about the run looked unusual.
+    PlayerFinished {
+        player_id: PlayerId,
+        place: usize,
+        wpm: f32,
+        time: f32,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        flags: Vec<Flag>,
+    },
+    /// Server: a player's run was rejected and they are out of this race.
+    PlayerDisqualified { player_id: PlayerId, reason: String },
+    /// Server: everyone has finished; the room is back in its lobby.
+    Results { standings: Vec<Standing> },
+    /// Server: the last message was refused.
+    Error { code: ErrorCode, message: String },
+    /// Either side: check the connection is alive.
+    Ping { nonce: u64 },
+    /// Either side: answer to a ping.
+    Pong { nonce: u64 },
+}
+
+impl MultiplayerMessage {
+    /// The hello a client of this version sends, naming itself e.g. `"typerpunk-tui"`.
+    pub fn hello(client: &str) -> Self {
+        Self::Hello { version: PROTOCOL_VERSION, client: client.to_string() }
+    }
+
+    pub fn error(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self::Error { code, message: message.into() }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("protocol messages always serialize")
+    }
+
+    /// Parses a frame, turning anything unreadable into a `BadMessage` error
+    /// that can be sent straight back.
+    pub fn from_json(json: &str) -> Result<Self, Self> {
+        serde_json::from_str(json).map_err(|e| Self::error(ErrorCode::BadMessage, format!("bad message: {}", e)))
+    }
+}
+
+/// Checks a peer's version against ours.
+pub fn check_version(version: u32) -> Result<(), MultiplayerMessage> {
+    if version == PROTOCOL_VERSION {
+        return Ok(());
+    }
+    Err(MultiplayerMessage::error(
+        ErrorCode::IncompatibleVersion,
+        format!("protocol version {} is not supported (this side speaks {})", version, PROTOCOL_VERSION),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn every_message() -> Vec<MultiplayerMessage> {
+        let player = PlayerInfo { id: 2, name: "bo".to_string(), ready: true };
+        vec![
+            MultiplayerMessage::hello("test"),
+            MultiplayerMessage::Welcome { version: PROTOCOL_VERSION, player_id: 2 },
+            MultiplayerMessage::CreateRoom { name: "ann".to_string() },
+            MultiplayerMessage::JoinRoom { room: "ABCD".to_string(), name: "bo".to_string() },
+            MultiplayerMessage::LeaveRoom,
+            MultiplayerMessage::Joined { room: "ABCD".to_string(), player_id: 2, host: 1, players: vec![player.clone()] },
+            MultiplayerMessage::PlayerJoined { player },
+            MultiplayerMessage::PlayerLeft { player_id: 1, host: 2 },
+            MultiplayerMessage::Ready { ready: true },
+            MultiplayerMessage::PlayerReady { player_id: 2, ready: false },
+            MultiplayerMessage::StartRace { text: "cat hat".to_string() },
+            MultiplayerMessage::RaceText { text: "cat hat".to_string() },
+            MultiplayerMessage::Countdown { start_in_ms: 3000 },
+            MultiplayerMessage::Go,
+            MultiplayerMessage::Progress { progress: 42.5, wpm: 71.0 },
+            MultiplayerMessage::PlayerProgress { player_id: 2, progress: 42.5, wpm: 71.0 },
+            MultiplayerMessage::Finish {
+                events: vec![Keystroke { at_ms: 250, action: KeyAction::Insert { ch: 'c' } }],
+            },
+            MultiplayerMessage::PlayerFinished {
+                player_id: 2,
+                place: 1,
+                wpm: 80.0,
+                time: 12.5,
+                flags: vec![Flag::UniformRhythm],
+            },
+            MultiplayerMessage::PlayerDisqualified { player_id: 3, reason: "pasted".to_string() },
+            MultiplayerMessage::Results {
+                standings: vec![Standing {
+                    player_id: 2,
+                    name: "bo".to_string(),
+                    place: Some(1),
+                    progress: 100.0,
+                    wpm: 80.0,
+                    time: Some(12.5),
+                    flags: Vec::new(),
+                    disqualified: None,
+                }],
+            },
+            MultiplayerMessage::error(ErrorCode::NoSuchRoom, "no room WXYZ"),
+            MultiplayerMessage::Ping { nonce: 7 },
+            MultiplayerMessage::Pong { nonce: 7 },
+        ]
+    }
+
+    use crate::replay::KeyAction;
+
+    #[test]
+    fn test_every_message_round_trips() {
+        for message in every_message() {
+            let json = message.to_json();
+            assert_eq!(MultiplayerMessage::from_json(&json), Ok(message), "{}", json);
+        }
+    }
+
+    #[test]
+    fn test_wire_format() {
+        assert_eq!(
+            MultiplayerMessage::Progress { progress: 42.5, wpm: 71.0 }.to_json(),
+            r#"{"type":"progress","progress":42.5,"wpm":71.0}"#
+        );
+        assert_eq!(MultiplayerMessage::Go.to_json(), r#"{"type":"go"}"#);
+        assert_eq!(
+            MultiplayerMessage::error(ErrorCode::IncompatibleVersion, "no").to_json(),
+            r#"{"type":"error","code":"incompatible_version","message":"no"}"#
+        );
+        assert_eq!(
+            MultiplayerMessage::Finish { events: vec![Keystroke { at_ms: 90, action: KeyAction::Backspace { removed: 1 } }] }
+                .to_json(),
+            r#"{"type":"finish","events":[{"at_ms":90,"kind":"backspace","removed":1}]}"#
+        );
+        // Older clients leave out the ready flag
+        let joined = r#"{"type":"player_joined","player":{"id":3,"name":"cy"}}"#;
+        assert!(matches!(
+            MultiplayerMessage::from_json(joined),
+            Ok(MultiplayerMessage::PlayerJoined { player }) if !player.ready
+        ));
+    }
+
+    #[test]
+    fn test_bad_frames_and_versions_are_refused() {
+        for frame in ["not json", r#"{"type":"teleport"}"#, r#"{"type":"progress","progress":"lots"}"#] {
+            assert!(matches!(
+                MultiplayerMessage::from_json(frame),
+                Err(MultiplayerMessage::Error { code: ErrorCode::BadMessage, .. })
+            ));
+        }
+        assert!(check_version(PROTOCOL_VERSION).is_ok());
+        assert!(matches!(
+            check_version(PROTOCOL_VERSION + 1),
+            Err(MultiplayerMessage::Error { code: ErrorCode::IncompatibleVersion, .. })
+        ));
+    }
+}
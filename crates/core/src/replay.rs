@@ -0,0 +1,299 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::GameMode;
+use crate::graphemes;
+
+/// What one keystroke did to the input. Deletions store how many characters they
+/// removed, so a replay doesn't depend on the backspace rules in force when it is played.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum KeyAction {
+    Insert { ch: char },
+    Backspace { removed: usize },
+    DeleteWord { removed: usize },
+    /// Indentation filled in by auto-indent after a line break. It moves the
+    /// cursor like an insert but was never typed, so it is not a keystroke.
+    Indent { ch: char },
+}
+
+impl KeyAction {
+    /// Actions that turn `before` into `after`, for front-ends that report the
+    /// whole input rather than single keys: one deletion back to the common prefix
+    /// (a word delete if it took more than one character), then an insert per new
+    /// character.
+    pub fn diff(before: &str, after: &str) -> Vec<KeyAction> {
+        let common = before.chars().zip(after.chars()).take_while(|(a, b)| a == b).count();
+        let removed = before.chars().count() - common;
+        let deletion = match removed {
+            0 => None,
+            1 => Some(KeyAction::Backspace { removed }),
+            _ => Some(KeyAction::DeleteWord { removed }),
+        };
+        deletion
+            .into_iter()
+            .chain(after.chars().skip(common).map(|ch| KeyAction::Insert { ch }))
+            .collect()
+    }
+}
+
+/// A keystroke, timed in milliseconds from the start of the run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Keystroke {
+    pub at_ms: u64,
+    #[serde(flatten)]
+    pub action: KeyAction,
+}
+
+/// Every keystroke of a run together with the text it was typed against.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct KeystrokeLog {
+    pub mode: GameMode,
+    pub target: String,
+    pub events: Vec<Keystroke>,
+}
+
+impl KeystrokeLog {
+    pub fn new(mode: GameMode, target: &str) -> Self {
+        Self { mode, target: target.to_string(), events: Vec::new() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn push(&mut self, at: Duration, action: KeyAction) {
+        self.events.push(Keystroke { at_ms: at.as_millis() as u64, action });
+    }
+
+    /// Time of the last keystroke.
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.events.last().map(|e| e.at_ms).unwrap_or(0))
+    }
+
+    /// The input as it stood after the first `count` keystrokes.
+    pub fn input_after(&self, count: usize) -> String {
+        let mut input: Vec<char> = Vec::new();
+        for event in self.events.iter().take(count) {
+            match event.action {
+                KeyAction::Insert { ch } | KeyAction::Indent { ch } => input.push(ch),
+                KeyAction::Backspace { removed } | KeyAction::DeleteWord { removed } => {
+                    input.truncate(input.len().saturating_sub(removed));
+                }
+            }
+        }
+        input.into_iter().collect()
+    }
+
+    /// Length in grapheme clusters of the input `elapsed` into the run, i.e.
+    /// where the cursor was. Used to race a ghost along the same text.
+    pub fn position_at(&self, elapsed: Duration) -> usize {
+        graphemes::count(&self.input_after(self.events_by(elapsed)))
+    }
+
+    /// Number of keystrokes made by `elapsed` into the run.
+    pub fn events_by(&self, elapsed: Duration) -> usize {
+        let ms = elapsed.as_millis() as u64;
+        self.events.partition_point(|e| e.at_ms <= ms)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        serde_json::from_str(&json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+        fs::write(path, json).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+/// Plays a log back on a clock of its own that can run faster or slower than real
+/// time and be paused. The caller advances it with the real time that has passed.
+#[derive(Debug, Clone)]
+pub struct ReplayPlayer {
+    pub log: KeystrokeLog,
+    pub speed: f64,
+    pub paused: bool,
+    position: Duration,
+}
+
+/// Playback speeds offered by [`ReplayPlayer::faster`] and [`ReplayPlayer::slower`].
+pub const REPLAY_SPEEDS: [f64; 6] = [0.5, 1.0, 2.0, 4.0, 8.0, 16.0];
+/// Fastest playback a player accepts.
+pub const MAX_REPLAY_SPEED: f64 = REPLAY_SPEEDS[REPLAY_SPEEDS.len() - 1];
+
+impl ReplayPlayer {
+    /// A player at `speed`, capped at [`MAX_REPLAY_SPEED`]. A speed that is not
+    /// positive (or NaN) would never move, so it plays in real time instead.
+    pub fn new(log: KeystrokeLog, speed: f64) -> Self {
+        let speed = if speed > 0.0 { speed.min(MAX_REPLAY_SPEED) } else { 1.0 };
+        Self { log, speed, paused: false, position: Duration::ZERO }
+    }
+
+    /// Moves the replay clock on by `real` time scaled by the speed.
+    pub fn advance(&mut self, real: Duration) {
+        if !self.paused && !self.is_done() {
+            self.position = (self.position + real.mul_f64(self.speed)).min(self.log.duration());
+        }
+    }
+
+    pub fn position(&self) -> Duration {
+        self.position
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.position >= self.log.duration()
+    }
+
+    /// Input as of the current position.
+    pub fn input(&self) -> String {
+        self.log.input_after(self.log.events_by(self.position))
+    }
+
+    /// Time since the last keystroke at the current position, to spot hesitations.
+    pub fn idle(&self) -> Duration {
+        let done = self.log.events_by(self.position);
+        let last = done
+            .checked_sub(1)
+            .map(|i| Duration::from_millis(self.log.events[i].at_ms))
+            .unwrap_or(Duration::ZERO);
+        self.position.saturating_sub(last)
+    }
+
+    /// Keystrokes played so far that typed the wrong character.
+    pub fn mistakes(&self) -> usize {
+        let target: Vec<char> = self.log.target.chars().collect();
+        let mut position = 0usize;
+        let mut mistakes = 0;
+        for event in self.log.events.iter().take(self.log.events_by(self.position)) {
+            match event.action {
+                KeyAction::Insert { ch } => {
+                    if target.get(position) != Some(&ch) {
+                        mistakes += 1;
+                    }
+                    position += 1;
+                }
+                KeyAction::Indent { .. } => position += 1,
+                KeyAction::Backspace { removed } | KeyAction::DeleteWord { removed } => {
+                    position = position.saturating_sub(removed);
+                }
+            }
+        }
+        mistakes
+    }
+
+    pub fn restart(&mut self) {
+        self.position = Duration::ZERO;
+        self.paused = false;
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = REPLAY_SPEEDS.iter().copied().find(|s| *s > self.speed).unwrap_or(self.speed);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = REPLAY_SPEEDS.iter().rev().copied().find(|s| *s < self.speed).unwrap_or(self.speed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn log() -> KeystrokeLog {
+        let mut log = KeystrokeLog::new(GameMode::Normal, "cat hat");
+        let ms = Duration::from_millis;
+        log.push(ms(0), KeyAction::Insert { ch: 'c' });
+        log.push(ms(100), KeyAction::Insert { ch: 'q' });
+        log.push(ms(300), KeyAction::Backspace { removed: 1 });
+        log.push(ms(400), KeyAction::Insert { ch: 'a' });
+        log.push(ms(500), KeyAction::Insert { ch: 't' });
+        log.push(ms(2500), KeyAction::Insert { ch: ' ' });
+        log.push(ms(2600), KeyAction::Insert { ch: 'x' });
+        log.push(ms(2700), KeyAction::DeleteWord { removed: 1 });
+        log
+    }
+
+    #[test]
+    fn test_log_replays_input() {
+        let log = log();
+        assert_eq!(log.input_after(2), "cq");
+        assert_eq!(log.input_after(5), "cat");
+        assert_eq!(log.input_after(log.events.len()), "cat ");
+        assert_eq!(log.events_by(Duration::from_millis(450)), 4);
+        assert_eq!(log.position_at(Duration::from_millis(150)), 2);
+        assert_eq!(log.position_at(Duration::from_millis(350)), 1);
+        assert_eq!(log.position_at(Duration::from_secs(60)), 4);
+    }
+
+    #[test]
+    fn test_position_counts_clusters() {
+        let mut log = KeystrokeLog::new(GameMode::Normal, "e\u{301}👍🏽 ok");
+        for (i, ch) in "e\u{301}👍🏽 o".chars().enumerate() {
+            log.push(Duration::from_millis(100 * i as u64), KeyAction::Insert { ch });
+        }
+        // The accent and the skin tone add to the cluster before them
+        assert_eq!(log.position_at(Duration::from_millis(100)), 1);
+        assert_eq!(log.position_at(Duration::from_millis(300)), 2);
+        assert_eq!(log.position_at(Duration::from_secs(1)), 4);
+    }
+
+    #[test]
+    fn test_log_round_trips_through_json() {
+        let log = log();
+        let json = serde_json::to_string(&log).unwrap();
+        assert!(json.contains(r#"{"at_ms":300,"kind":"backspace","removed":1}"#));
+        assert_eq!(serde_json::from_str::<KeystrokeLog>(&json).unwrap(), log);
+    }
+
+    #[test]
+    fn test_diff_of_whole_inputs() {
+        assert_eq!(KeyAction::diff("ca", "cat"), vec![KeyAction::Insert { ch: 't' }]);
+        assert_eq!(KeyAction::diff("cat", "ca"), vec![KeyAction::Backspace { removed: 1 }]);
+        assert_eq!(
+            KeyAction::diff("cat hxt", "cat ha"),
+            vec![KeyAction::DeleteWord { removed: 2 }, KeyAction::Insert { ch: 'a' }]
+        );
+    }
+
+    #[test]
+    fn test_player_speed_and_pause() {
+        let mut player = ReplayPlayer::new(log(), 2.0);
+        player.advance(Duration::from_millis(250));
+        assert_eq!(player.input(), "cat");
+        assert_eq!(player.mistakes(), 1);
+
+        player.paused = true;
+        player.advance(Duration::from_secs(1));
+        assert_eq!(player.position(), Duration::from_millis(500));
+
+        player.paused = false;
+        player.advance(Duration::from_millis(500));
+        // Halfway through the two-second pause after "cat"
+        assert_eq!(player.idle(), Duration::from_millis(1000));
+        player.advance(Duration::from_secs(10));
+        assert!(player.is_done());
+        assert_eq!(player.input(), "cat ");
+
+        player.faster();
+        assert_eq!(player.speed, 4.0);
+    }
+
+    #[test]
+    fn test_player_speed_is_clamped() {
+        for speed in [0.0, -2.0, f64::NAN] {
+            assert_eq!(ReplayPlayer::new(log(), speed).speed, 1.0);
+        }
+        let mut player = ReplayPlayer::new(log(), 1e300);
+        assert_eq!(player.speed, MAX_REPLAY_SPEED);
+        player.advance(Duration::from_secs(3600));
+        assert!(player.is_done());
+    }
+}
@@ -0,0 +1,251 @@
+use std::time::Duration;
+
+use crate::anticheat::Flag;
+use crate::protocol::{MultiplayerMessage, PlayerId, PlayerInfo, Standing};
+
+/// A player in the room as this client sees them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Racer {
+    pub info: PlayerInfo,
+    /// Progress through the race text, as a percentage.
+    pub progress: f32,
+    pub wpm: f32,
+    /// Finishing place and time in seconds, once they are done.
+    pub finish: Option<(usize, f32)>,
+    /// What the server found unusual about their finished run.
+    pub flags: Vec<Flag>,
+    /// Why the server rejected their run, if it did.
+    pub disqualified: Option<String>,
+}
+
+impl Racer {
+    fn new(info: PlayerInfo) -> Self {
+        Self { info, progress: 0.0, wpm: 0.0, finish: None, flags: Vec::new(), disqualified: None }
+    }
+}
+
+/// Something in a server message a front-end has to act on, rather than just
+/// show on its next frame.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoomEvent {
+    /// The race starts in this long; the text is in [`RoomView::text`].
+    Countdown(Duration),
+    /// The race has begun.
+    Go,
+    /// Everyone has finished; the standings are in [`RoomView::standings`].
+    Results,
+}
+
+/// Client-side state of a race room, kept up to date from the server's messages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoomView {
+    pub code: String,
+    pub player_id: PlayerId,
+    pub host: PlayerId,
+    /// Everyone in the room, yourself included, in the order they joined.
+    pub racers: Vec<Racer>,
+    /// Text of the coming or current race.
+    pub text: Option<String>,
+    /// Standings of the last race, until the next one starts.
+    pub standings: Vec<Standing>,
+    /// The last refusal from the server, until the next message that changes anything.
+    pub notice: Option<String>,
+}
+
+impl RoomView {
+    /// The view described by a `Joined` message, or `None` for any other message.
+    pub fn joined(message: &MultiplayerMessage) -> Option<Self> {
+        let MultiplayerMessage::Joined { room, player_id, host, players } = message else {
+            return None;
+        };
+        Some(Self {
+            code: room.clone(),
+            player_id: *player_id,
+            host: *host,
+            racers: players.iter().cloned().map(Racer::new).collect(),
+            text: None,
+            standings: Vec::new(),
+            notice: None,
+        })
+    }
+
+    pub fn is_host(&self) -> bool {
+        self.player_id == self.host
+    }
+
+    pub fn me(&self) -> Option<&Racer> {
+        self.racers.iter().find(|r| r.info.id == self.player_id)
+    }
+
+    /// Everyone but yourself.
+    pub fn opponents(&self) -> impl Iterator<Item = &Racer> {
+        self.racers.iter().filter(move |r| r.info.id != self.player_id)
+    }
+
+    /// Whether the host could start now: everyone but the host is ready.
+    pub fn all_ready(&self) -> bool {
+        self.racers.iter().all(|r| r.info.ready || r.info.id == self.host)
+    }
+
+    /// Updates the view with a message from the server.
+    pub fn apply(&mut self, message: &MultiplayerMessage) -> Option<RoomEvent> {
+        if !matches!(message, MultiplayerMessage::Error { .. }) {
+            self.notice = None;
+        }
+        match message {
+            MultiplayerMessage::PlayerJoined { player } => {
+                self.racers.push(Racer::new(player.clone()));
+            }
+            MultiplayerMessage::PlayerLeft { player_id, host } => {
+                self.racers.retain(|r| r.info.id != *player_id);
+                self.host = *host;
+            }
+            MultiplayerMessage::PlayerReady { player_id, ready } => {
+                if let Some(racer) = self.racer_mut(*player_id) {
+                    racer.info.ready = *ready;
+                }
+            }
+            MultiplayerMessage::RaceText { text } => {
+                self.text = Some(text.clone());
+                self.standings.clear();
+                for racer in &mut self.racers {
+                    *racer = Racer::new(racer.info.clone());
+                }
+            }
+            MultiplayerMessage::Countdown { start_in_ms } => {
+                return Some(RoomEvent::Countdown(Duration::from_millis(*start_in_ms)));
+            }
+            MultiplayerMessage::Go => return Some(RoomEvent::Go),
+            MultiplayerMessage::PlayerProgress { player_id, progress, wpm } => {
+                if let Some(racer) = self.racer_mut(*player_id) {
+                    racer.progress = *progress;
+                    racer.wpm = *wpm;
+                }
+            }
+            MultiplayerMessage::PlayerFinished { player_id, place, wpm, time, flags } => {
+                if let Some(racer) = self.racer_mut(*player_id) {
+                    racer.progress = 100.0;
+                    racer.wpm = *wpm;
+                    racer.finish = Some((*place, *time));
+                    racer.flags = flags.clone();
+                }
+            }
+            MultiplayerMessage::PlayerDisqualified { player_id, reason } => {
+                if let Some(racer) = self.racer_mut(*player_id) {
+                    racer.disqualified = Some(reason.clone());
+                }
+            }
+            MultiplayerMessage::Results { standings } => {
+                self.standings = standings.clone();
+                // The server clears everyone's ready flag for the next race
+                for racer in &mut self.racers {
+                    racer.info.ready = false;
+                }
+                return Some(RoomEvent::Results);
+            }
+            MultiplayerMessage::Error { message, .. } => self.notice = Some(message.clone()),
+            _ => {}
+        }
+        None
+    }
+
+    /// Records your own progress, which the server only relays to the others.
+    pub fn update_me(&mut self, progress: f32, wpm: f32) {
+        let id = self.player_id;
+        if let Some(racer) = self.racer_mut(id) {
+            racer.progress = progress;
+            racer.wpm = wpm;
+        }
+    }
+
+    fn racer_mut(&mut self, id: PlayerId) -> Option<&mut Racer> {
+        self.racers.iter_mut().find(|r| r.info.id == id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(id: PlayerId, name: &str) -> PlayerInfo {
+        PlayerInfo { id, name: name.to_string(), ready: false }
+    }
+
+    fn room() -> RoomView {
+        RoomView::joined(&MultiplayerMessage::Joined {
+            room: "ABCD".to_string(),
+            player_id: 2,
+            host: 1,
+            players: vec![player(1, "ann"), player(2, "bo")],
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_view_follows_the_lobby() {
+        let mut room = room();
+        assert!(!room.is_host());
+        assert_eq!(room.me().unwrap().info.name, "bo");
+
+        room.apply(&MultiplayerMessage::PlayerJoined { player: player(3, "cy") });
+        assert!(!room.all_ready());
+        room.apply(&MultiplayerMessage::PlayerReady { player_id: 2, ready: true });
+        room.apply(&MultiplayerMessage::PlayerReady { player_id: 3, ready: true });
+        assert!(room.all_ready());
+
+        room.apply(&MultiplayerMessage::error(crate::protocol::ErrorCode::NotAllowed, "nope"));
+        assert_eq!(room.notice.as_deref(), Some("nope"));
+
+        // The host leaving hands the room to the next player
+        room.apply(&MultiplayerMessage::PlayerLeft { player_id: 1, host: 2 });
+        assert!(room.is_host());
+        assert_eq!(room.opponents().map(|r| r.info.id).collect::<Vec<_>>(), vec![3]);
+        assert_eq!(room.notice, None);
+    }
+
+    #[test]
+    fn test_view_follows_a_race() {
+        let mut room = room();
+        room.apply(&MultiplayerMessage::RaceText { text: "cat hat".to_string() });
+        assert_eq!(room.text.as_deref(), Some("cat hat"));
+        assert_eq!(
+            room.apply(&MultiplayerMessage::Countdown { start_in_ms: 3000 }),
+            Some(RoomEvent::Countdown(Duration::from_secs(3)))
+        );
+        assert_eq!(room.apply(&MultiplayerMessage::Go), Some(RoomEvent::Go));
+
+        room.apply(&MultiplayerMessage::PlayerProgress { player_id: 1, progress: 40.0, wpm: 60.0 });
+        room.update_me(20.0, 50.0);
+        room.apply(&MultiplayerMessage::PlayerFinished {
+            player_id: 1,
+            place: 1,
+            wpm: 70.0,
+            time: 5.0,
+            flags: vec![Flag::UniformRhythm],
+        });
+        let ann = &room.racers[0];
+        assert_eq!((ann.progress, ann.wpm, ann.finish), (100.0, 70.0, Some((1, 5.0))));
+        assert_eq!(ann.flags, vec![Flag::UniformRhythm]);
+        assert_eq!(room.me().unwrap().progress, 20.0);
+        room.apply(&MultiplayerMessage::PlayerDisqualified { player_id: 2, reason: "pasted".to_string() });
+        assert_eq!(room.me().unwrap().disqualified.as_deref(), Some("pasted"));
+
+        let standings = vec![Standing {
+            player_id: 1,
+            name: "ann".to_string(),
+            place: Some(1),
+            progress: 100.0,
+            wpm: 70.0,
+            time: Some(5.0),
+            flags: Vec::new(),
+            disqualified: None,
+        }];
+        assert_eq!(room.apply(&MultiplayerMessage::Results { standings }), Some(RoomEvent::Results));
+        assert_eq!(room.standings.len(), 1);
+
+        // A new race starts everyone from scratch
+        room.apply(&MultiplayerMessage::RaceText { text: "dog".to_string() });
+        assert!(room.racers.iter().all(|r| r.progress == 0.0 && r.finish.is_none() && r.disqualified.is_none()));
+        assert!(room.standings.is_empty());
+    }
+}
@@ -1,10 +1,16 @@
 use std::time::{Duration, Instant};
 use serde::{Serialize, Deserialize};
 
+use crate::graphemes;
+use crate::keystats::{KeyRecorder, KeyStats};
+use crate::metrics::{self, Second};
+
 #[derive(Debug, Clone)]
 pub struct Stats {
     start_time: Option<Instant>,
     end_time: Option<Instant>,
+    // A timed run's deadline; the clock never runs past it
+    time_limit: Option<Duration>,
     error_positions: Vec<usize>,
     current_streak: usize,
     best_streak: usize,
@@ -18,11 +24,16 @@ pub struct Stats {
     // counts every typed character (excluding control sequences) and how many were incorrect at time of keypress
     keystrokes_total: usize,
     keystrokes_incorrect: usize,
+    // When each keystroke came, in ms from the start, and whether it was right
+    keystroke_times: Vec<(u64, bool)>,
+    keys: KeyRecorder,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SerializedStats {
     wpm: f64,
+    raw_wpm: f64,
+    cpm: f64,
     accuracy: f64,
     total_chars: usize,
     correct_chars: usize,
@@ -30,43 +41,35 @@ pub struct SerializedStats {
     total_words: usize,
     correct_words: usize,
     errors: usize,
-    time_elapsed_secs: u64,
+    time_elapsed_secs: f64,
     error_positions: Vec<usize>,
     current_streak: usize,
     best_streak: usize,
+    consistency: f64,
+    burst: f64,
+    timeline: Vec<Second>,
 }
 
 impl From<&Stats> for SerializedStats {
     fn from(stats: &Stats) -> Self {
-        let time_elapsed = stats.start_time
-            .and_then(|start| stats.end_time.map(|end| end.duration_since(start)))
-            .unwrap_or(Duration::from_secs(0));
-
-        let wpm = if time_elapsed.as_secs() > 0 {
-            (stats.correct_chars as f64 / 5.0) / (time_elapsed.as_secs_f64() / 60.0)
-        } else {
-            0.0
-        };
-
-        let accuracy = if stats.total_chars > 0 {
-            (stats.correct_chars as f64 / stats.total_chars as f64) * 100.0
-        } else {
-            0.0
-        };
-
         Self {
-            wpm,
-            accuracy,
+            wpm: stats.get_wpm(),
+            raw_wpm: stats.get_raw_wpm(),
+            cpm: stats.get_cpm(),
+            accuracy: stats.get_accuracy(),
             total_chars: stats.total_chars,
             correct_chars: stats.correct_chars,
             incorrect_chars: stats.incorrect_chars,
             total_words: stats.total_words,
             correct_words: stats.correct_words,
             errors: stats.errors,
-            time_elapsed_secs: time_elapsed.as_secs(),
+            time_elapsed_secs: stats.get_time_elapsed().as_secs_f64(),
             error_positions: stats.error_positions.clone(),
             current_streak: stats.current_streak,
             best_streak: stats.best_streak,
+            consistency: stats.get_consistency(),
+            burst: stats.get_burst(),
+            timeline: stats.timeline(),
         }
     }
 }
@@ -76,6 +79,7 @@ impl Stats {
         Self {
             start_time: None,
             end_time: None,
+            time_limit: None,
             error_positions: Vec::new(),
             current_streak: 0,
             best_streak: 0,
@@ -87,6 +91,8 @@ impl Stats {
             errors: 0,
             keystrokes_total: 0,
             keystrokes_incorrect: 0,
+            keystroke_times: Vec::new(),
+            keys: KeyRecorder::new(),
         }
     }
 
@@ -104,12 +110,25 @@ impl Stats {
         self.errors = 0;
         self.keystrokes_total = 0;
         self.keystrokes_incorrect = 0;
+        self.keystroke_times.clear();
+        self.keys.reset();
     }
 
     pub fn start(&mut self) {
         self.start_time = Some(Instant::now());
     }
 
+    #[cfg(test)]
+    pub(crate) fn start_at(&mut self, start: Instant) {
+        self.start_time = Some(start);
+    }
+
+    /// Caps the clock at `limit`, so a timed run is scored on its deadline even
+    /// if the end is only noticed later. Kept across [`reset`](Self::reset).
+    pub fn set_time_limit(&mut self, limit: Option<Duration>) {
+        self.time_limit = limit;
+    }
+
     pub fn update(&mut self, input: &str, target: &str) {
         // Recompute everything from scratch for current input
         self.error_positions.clear();
@@ -117,18 +136,21 @@ impl Stats {
         let mut best_streak_local = 0;
         let mut correct_chars = 0usize;
         let mut incorrect_chars = 0usize;
-        let mut total_words = 0usize;
         let mut correct_words = 0usize;
 
         // Tokenize by whitespace to count words
         let input_words: Vec<&str> = input.split_whitespace().collect();
         let target_words: Vec<&str> = target.split_whitespace().collect();
-        total_words = input_words.len();
+        let total_words = input_words.len();
         for (iw, tw) in input_words.iter().zip(target_words.iter()) {
             if *iw == *tw { correct_words += 1; }
         }
 
-        for (i, (input_char, target_char)) in input.chars().zip(target.chars()).enumerate() {
+        // Compared cluster by cluster, so an accented letter or an emoji is one
+        // character however many code points it takes
+        let input_clusters = graphemes::graphemes(input);
+        let target_clusters = graphemes::graphemes(target);
+        for (i, (input_char, target_char)) in input_clusters.iter().zip(target_clusters.iter()).enumerate() {
             if input_char == target_char {
                 streak += 1;
                 correct_chars += 1;
@@ -141,11 +163,11 @@ impl Stats {
         }
 
         // Extra characters beyond target count as incorrect
-        if input.len() > target.len() {
-            incorrect_chars += input.len() - target.len();
+        if input_clusters.len() > target_clusters.len() {
+            incorrect_chars += input_clusters.len() - target_clusters.len();
         }
 
-        self.total_chars = input.len();
+        self.total_chars = input_clusters.len();
         self.correct_chars = correct_chars;
         self.incorrect_chars = incorrect_chars;
         self.total_words = total_words;
@@ -155,6 +177,18 @@ impl Stats {
         self.best_streak = self.best_streak.max(best_streak_local);
     }
 
+    /// Like [`update`](Self::update) for free typing, where there is no text to
+    /// match: every character counts towards speed and none is a mistake.
+    pub fn update_free(&mut self, input: &str) {
+        self.error_positions.clear();
+        self.total_chars = graphemes::count(input);
+        self.correct_chars = self.total_chars;
+        self.incorrect_chars = 0;
+        self.total_words = input.split_whitespace().count();
+        self.correct_words = self.total_words;
+        self.errors = 0;
+    }
+
     // Record a single keypress for persistent accuracy tracking (CLI only).
     // If the typed char at the time of keypress was incorrect, mark it as incorrect permanently.
     pub fn note_keypress(&mut self, was_correct: bool) {
@@ -162,6 +196,22 @@ impl Stats {
         if !was_correct {
             self.keystrokes_incorrect = self.keystrokes_incorrect.saturating_add(1);
         }
+        let at = self.start_time.map(|start| start.elapsed().as_millis() as u64).unwrap_or(0);
+        self.keystroke_times.push((at, was_correct));
+    }
+
+    /// Records a typed character at grapheme `position`, where `expected` is the
+    /// character that starts the target cluster there (`None` past the end of the text). Besides the keystroke
+    /// counters this keeps per-key and per-bigram accuracy and latency.
+    pub fn note_key(&mut self, position: usize, expected: Option<char>, typed: char) {
+        self.note_keypress(expected == Some(typed));
+        if let Some(expected) = expected {
+            self.keys.record(position, expected, typed);
+        }
+    }
+
+    pub fn key_stats(&self) -> &KeyStats {
+        self.keys.stats()
     }
 
     pub fn finish(&mut self) {
@@ -180,36 +230,65 @@ impl Stats {
         self.best_streak
     }
 
+    /// Words in the input, whether or not they match the text.
+    pub fn get_total_words(&self) -> usize {
+        self.total_words
+    }
+
+    /// Net WPM, see [`metrics`](crate::metrics).
     pub fn get_wpm(&self) -> f64 {
-        let time_elapsed = match (self.start_time, self.end_time) {
-            (Some(start), Some(end)) => end.duration_since(start),
-            (Some(start), None) => Instant::now().duration_since(start),
-            _ => Duration::from_secs(0),
-        };
+        metrics::net_wpm(self.correct_chars, self.get_time_elapsed())
+    }
 
-        if time_elapsed.as_secs_f64() > 0.0 {
-            (self.correct_chars as f64 / 5.0) / (time_elapsed.as_secs_f64() / 60.0)
-        } else {
-            0.0
-        }
+    /// Typing speed counting every keystroke, right or wrong.
+    pub fn get_raw_wpm(&self) -> f64 {
+        let typed = if self.keystrokes_total > 0 { self.keystrokes_total } else { self.total_chars };
+        metrics::raw_wpm(typed, self.get_time_elapsed())
+    }
+
+    /// Correct characters per minute.
+    pub fn get_cpm(&self) -> f64 {
+        metrics::cpm(self.correct_chars, self.get_time_elapsed())
+    }
+
+    /// Raw and net WPM and errors for each second of the run.
+    pub fn timeline(&self) -> Vec<Second> {
+        metrics::timeline(self.keystroke_times.iter().copied(), self.get_time_elapsed())
+    }
+
+    /// How even the pace was from second to second, see [`metrics::consistency`].
+    pub fn get_consistency(&self) -> f64 {
+        let samples: Vec<f64> = self.timeline().iter().map(|second| second.raw_wpm).collect();
+        metrics::consistency(&samples)
+    }
+
+    /// Fastest net WPM over a whole second.
+    pub fn get_burst(&self) -> f64 {
+        metrics::burst(&self.timeline(), self.get_time_elapsed())
+    }
+
+    /// Keystrokes that were wrong when typed, including ones fixed afterwards.
+    pub fn get_keystroke_errors(&self) -> usize {
+        self.keystrokes_incorrect
     }
 
     pub fn get_accuracy(&self) -> f64 {
         // Prefer persistent keystroke accuracy for CLI to avoid resetting to 100% after fixes.
         if self.keystrokes_total > 0 {
-            let correct = (self.keystrokes_total - self.keystrokes_incorrect) as f64;
-            return (correct / self.keystrokes_total as f64) * 100.0;
+            return metrics::accuracy(self.keystrokes_total - self.keystrokes_incorrect, self.keystrokes_total);
         }
-        if self.total_chars > 0 {
-            (self.correct_chars as f64 / self.total_chars as f64) * 100.0
-        } else { 0.0 }
+        metrics::accuracy(self.correct_chars, self.total_chars)
     }
 
     pub fn get_time_elapsed(&self) -> Duration {
-        match (self.start_time, self.end_time) {
+        let elapsed = match (self.start_time, self.end_time) {
             (Some(start), Some(end)) => end.duration_since(start),
             (Some(start), None) => Instant::now().duration_since(start),
             _ => Duration::from_secs(0),
+        };
+        match self.time_limit {
+            Some(limit) => elapsed.min(limit),
+            None => elapsed,
         }
     }
 
@@ -217,21 +296,34 @@ impl Stats {
     pub fn accuracy(&self) -> f64 { self.get_accuracy() }
     pub fn elapsed_time(&self) -> std::time::Duration { self.get_time_elapsed() }
     pub fn is_running(&self) -> bool { self.start_time.is_some() && self.end_time.is_none() }
+    pub fn is_started(&self) -> bool { self.start_time.is_some() }
     pub fn stop(&mut self) { self.end_time = Some(std::time::Instant::now()); }
+
+    // Stop the clock exactly `limit` after the start, so a timed run is scored on its
+    // deadline even if the stop is only noticed a frame later.
+    pub fn stop_at(&mut self, limit: Duration) {
+        if let Some(start) = self.start_time {
+            self.end_time = Some(start + limit);
+        }
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::thread;
-    use std::time::Duration;
 
     #[test]
     fn test_stats_initialization() {
         let stats = Stats::new();
         assert_eq!(stats.start_time, None);
         assert_eq!(stats.end_time, None);
-        assert_eq!(stats.error_positions, Vec::new());
+        assert_eq!(stats.error_positions, Vec::<usize>::new());
         assert_eq!(stats.current_streak, 0);
         assert_eq!(stats.best_streak, 0);
     }
@@ -243,9 +335,9 @@ mod tests {
         stats.update("hello", "hello");
         stats.end_time = Some(Instant::now());
 
-        assert_eq!(stats.start_time, Some(Instant::now()));
-        assert_eq!(stats.end_time, Some(Instant::now()));
-        assert_eq!(stats.error_positions, Vec::new());
+        assert!(stats.start_time.is_some());
+        assert!(stats.end_time.is_some());
+        assert_eq!(stats.error_positions, Vec::<usize>::new());
         assert_eq!(stats.current_streak, 5);
         assert_eq!(stats.best_streak, 5);
     }
@@ -259,4 +351,47 @@ mod tests {
         assert_eq!(stats.total_words, 2);
         assert_eq!(stats.correct_words, 2);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_stats_stop_at_deadline() {
+        let mut stats = Stats::new();
+        stats.start_time = Some(Instant::now() - Duration::from_secs(31));
+        stats.update("hello world", "hello world");
+        stats.stop_at(Duration::from_secs(30));
+
+        assert!(!stats.is_running());
+        assert_eq!(stats.elapsed_time(), Duration::from_secs(30));
+        // 11 correct chars over half a minute
+        assert!((stats.wpm() - 4.4).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_timeline_marks_errors_in_their_second() {
+        let mut stats = Stats::new();
+        stats.start_at(Instant::now() - Duration::from_millis(1500));
+        stats.note_keypress(true);
+        stats.note_keypress(false);
+
+        let timeline = stats.timeline();
+        assert_eq!(timeline.len(), 2);
+        assert_eq!((timeline[0].errors, timeline[1].errors), (0, 1));
+        assert!(timeline[1].raw_wpm > timeline[1].net_wpm);
+        assert_eq!(stats.get_burst(), 0.0);
+    }
+
+    #[test]
+    fn test_serialized_stats_keep_sub_second_time() {
+        let mut stats = Stats::new();
+        let start = Instant::now();
+        stats.start_time = Some(start);
+        stats.end_time = Some(start + Duration::from_millis(1500));
+        stats.update("hello", "hello");
+
+        let serialized = SerializedStats::from(&stats);
+        assert_eq!(serialized.time_elapsed_secs, 1.5);
+        // 5 characters in 1.5s, not rounded to a whole second
+        assert!((serialized.wpm - 40.0).abs() < 1e-9);
+        assert_eq!(serialized.wpm, stats.get_wpm());
+        assert_eq!(serialized.cpm, 200.0);
+    }
+}
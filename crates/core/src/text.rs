@@ -1,5 +1,8 @@
 use std::fmt;
 
+/// Category of the source code snippets typed in code mode.
+pub const CODE_CATEGORY: &str = "code";
+
 #[derive(Debug, Clone)]
 pub struct Text {
     pub content: String,
@@ -18,6 +21,7 @@ impl Text {
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(content: &str) -> Self {
         Self {
             content: content.to_string(),
@@ -62,6 +66,28 @@ impl Text {
             category: category.to_string(),
         }
     }
+
+    /// Whether this is a code snippet for code mode rather than prose.
+    pub fn is_code(&self) -> bool {
+        self.category == CODE_CATEGORY
+    }
+
+    /// Stable identifier derived from the content (64-bit FNV-1a, in hex), so runs
+    /// on the same passage can be matched up across sessions and pack reloads.
+    pub fn id(&self) -> String {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in self.content.trim().bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        format!("{:016x}", hash)
+    }
+}
+
+impl Default for Text {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl fmt::Display for Text {
@@ -1,7 +1,12 @@
 #[cfg(feature = "tui")]
+use std::str::FromStr;
+#[cfg(feature = "tui")]
 use ratatui::style::Color;
+#[cfg(feature = "tui")]
+use crate::config::Config;
 
 #[cfg(feature = "tui")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Theme {
     pub background: Color,
     pub foreground: Color,
@@ -14,11 +19,46 @@ pub struct Theme {
 impl Default for Theme {
     fn default() -> Self {
         Self {
-            background: Color::Black,
-            foreground: Color::White,
+            background: Color::Reset,
+            foreground: Color::Reset,
             accent: Color::Cyan,
             error: Color::Red,
             success: Color::Green,
         }
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(feature = "tui")]
+impl Theme {
+    pub fn light() -> Self {
+        Self {
+            background: Color::Reset,
+            foreground: Color::Reset,
+            accent: Color::Blue,
+            error: Color::Red,
+            success: Color::Green,
+        }
+    }
+
+    /// The named theme from `config` with its colour overrides applied.
+    /// Colours are validated when the config is loaded, so bad names are ignored here.
+    pub fn from_config(config: &Config) -> Self {
+        let mut theme = match config.theme.as_str() {
+            "light" => Self::light(),
+            _ => Self::default(),
+        };
+        let colors = &config.colors;
+        for (slot, value) in [
+            (&mut theme.background, &colors.background),
+            (&mut theme.foreground, &colors.foreground),
+            (&mut theme.accent, &colors.accent),
+            (&mut theme.error, &colors.error),
+            (&mut theme.success, &colors.success),
+        ] {
+            if let Some(color) = value.as_deref().and_then(|v| Color::from_str(v).ok()) {
+                *slot = color;
+            }
+        }
+        theme
+    }
+}
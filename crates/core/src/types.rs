@@ -7,8 +7,9 @@ pub enum Platform {
     Mobile,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum GameMode {
+    #[default]
     Normal,
     Programming,
     Security,
@@ -19,12 +20,6 @@ pub enum GameMode {
     Quote,
 }
 
-impl Default for GameMode {
-    fn default() -> Self {
-        GameMode::Normal
-    }
-}
-
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Difficulty {
     Basic,
@@ -126,18 +121,13 @@ impl GameModeTrait for GameConfig {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Theme {
     Light,
+    #[default]
     Dark,
 }
 
-impl Default for Theme {
-    fn default() -> Self {
-        Theme::Dark
-    }
-}
-
 impl std::fmt::Display for Theme {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -1,19 +1,47 @@
 use ratatui::{
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
+    symbols,
     text::Span,
-    widgets::{Block, Paragraph, Wrap},
+    widgets::{Axis, Block, Borders, Chart, Dataset, GraphType, Paragraph, Sparkline, Wrap},
     Frame,
 };
 use ratatui::prelude::{Alignment, Line};
 
-use crate::app::{App, State};
+use crate::app::{App, JoinField, State, StatsView};
+use crate::game::GameMode;
+use crate::graphemes;
+use crate::history::{RunQuery, Summary};
+use crate::keystats::{KeyStat, KeyStats, KEYBOARD_ROWS};
+use crate::metrics::{self, Second};
+use crate::replay::ReplayPlayer;
+use crate::room::{Racer, RoomView};
+
+// Runs plotted on the stats screen's trend charts
+const TREND_RUNS: usize = 100;
+
+// A replay flags a pause between keystrokes once it runs longer than this
+const HESITATION: std::time::Duration = std::time::Duration::from_secs(1);
+
+// Width in cells of the progress bars on the race screens
+const RACE_BAR_WIDTH: usize = 30;
 
 pub fn draw(f: &mut Frame, app: &App) {
+    // Paint the theme's base colours; both default to the terminal's own
+    f.render_widget(
+        Block::default().style(Style::default().fg(app.theme.foreground).bg(app.theme.background)),
+        f.size(),
+    );
     match app.state {
         State::MainMenu => draw_main_menu(f, app),
         State::TypingGame => draw_typing_game(f, app),
         State::EndScreen => draw_end_screen(f, app),
+        State::StatsScreen => draw_stats_screen(f, app),
+        State::Replay => draw_replay(f, app),
+        State::JoinScreen => draw_join_screen(f, app),
+        State::Lobby => draw_lobby(f, app),
+        State::Countdown => draw_countdown(f, app),
+        State::RaceResults => draw_race_results(f, app),
     }
 }
 
@@ -27,21 +55,31 @@ pub fn draw_main_menu(f: &mut Frame, app: &App) {
     let category_label = {
         let cat = app
             .selected_category
-            .as_ref()
-            .map(String::as_str)
+            .as_deref()
             .unwrap_or("Random");
-        format!("Category: {}  (\u{2190}/\u{2192} to change)", cat)
+        // Code mode picks a language rather than a category
+        let label = if app.mode == GameMode::Programming { "Language" } else { "Category" };
+        format!("{}: {}  (\u{2190}/\u{2192} to change)", label, cat)
     };
+    let mode_label = format!("Mode: {}  (\u{2191}/\u{2193} to change)", mode_label(app.mode));
 
     let mut lines: Vec<Line> = vec![
         Line::from(Span::styled(
             "TYPERPUNK",
-            Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+            Style::default().fg(app.theme.success).add_modifier(Modifier::BOLD),
         )),
         Line::from(Span::from("")),
         Line::from(Span::styled(
             category_label,
-            Style::default().fg(Color::Cyan),
+            Style::default().fg(app.theme.accent),
+        )),
+        Line::from(Span::styled(
+            mode_label,
+            Style::default().fg(app.theme.accent),
+        )),
+        Line::from(Span::styled(
+            adaptive_label(app),
+            Style::default().fg(app.theme.accent),
         )),
         Line::from(Span::from("")),
         Line::from(Span::styled("Start: Enter", Style::default())),
@@ -49,8 +87,24 @@ pub fn draw_main_menu(f: &mut Frame, app: &App) {
             "Change Category: \u{2190} / \u{2192}",
             Style::default(),
         )),
-        Line::from(Span::styled("Quit: Esc", Style::default())),
+        Line::from(Span::styled(
+            "Change Mode: \u{2191} / \u{2193}",
+            Style::default(),
+        )),
+        Line::from(Span::styled("Adaptive Practice: A", Style::default())),
+        Line::from(Span::styled("Stats: S", Style::default())),
     ];
+    if cfg!(feature = "multiplayer") {
+        lines.push(Line::from(Span::styled("Race Online: O", Style::default())));
+    }
+    lines.push(Line::from(Span::styled("Quit: Esc", Style::default())));
+    if !app.text_warnings.is_empty() {
+        lines.push(Line::from(Span::from("")));
+        lines.push(Line::from(Span::styled(
+            format!("{} text pack problem(s) skipped; details are printed on exit", app.text_warnings.len()),
+            Style::default().fg(Color::Yellow),
+        )));
+    }
 
     f.render_widget(
         Paragraph::new(lines)
@@ -62,64 +116,114 @@ pub fn draw_main_menu(f: &mut Frame, app: &App) {
 
 pub fn draw_typing_game(f: &mut Frame, app: &App) {
     let area = f.size();
+    // Online races list every racer above the text
+    let race_height = app.room.as_ref().map(|room| room.racers.len() as u16 + 1).unwrap_or(0);
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(1)
-        .constraints([Constraint::Min(0)])
+        .constraints([Constraint::Length(race_height), Constraint::Min(0)])
         .split(area);
+    if let Some(room) = &app.room {
+        f.render_widget(Paragraph::new(racer_lines(app, room)).alignment(Alignment::Center), chunks[0]);
+    }
+    let text_area = chunks[1];
 
     // Build colored text
-    let text_chars: Vec<char> = app.current_text().content.chars().collect();
-    let input_chars: Vec<char> = app.input.chars().collect();
+    let text_chars = graphemes::graphemes(app.engine.text());
+    let input_chars = graphemes::graphemes(app.engine.input());
     let mut colored_text: Vec<Span> = Vec::new();
-    let cursor_pos = app.input.len();
+    let cursor_pos = input_chars.len();
+    let ghost_pos = app.ghost_position();
+    // Timed and zen runs keep growing, so only show the text from around the
+    // cursor onwards
+    let window_start = if app.mode == GameMode::Zen {
+        window_start(&input_chars, cursor_pos, text_area.width as usize)
+    } else if app.time_limit().is_some() {
+        window_start(&text_chars, cursor_pos, text_area.width as usize)
+    } else {
+        0
+    };
 
-    for (i, &c) in text_chars.iter().enumerate() {
+    // Zen runs have no text: what is typed is the text, with the cursor after it
+    if app.mode == GameMode::Zen {
+        for &c in &input_chars[window_start..] {
+            colored_text.push(Span::styled(c.to_string(), Style::default().fg(app.theme.foreground)));
+        }
+        colored_text.push(Span::styled(" ", Style::default().add_modifier(Modifier::REVERSED)));
+    }
+
+    for (i, &c) in text_chars.iter().enumerate().skip(window_start) {
         let style = if i < input_chars.len() {
             if input_chars[i] == c {
-                Style::default().fg(Color::Green)
+                Style::default().fg(app.theme.success)
             } else {
-                Style::default().fg(Color::Red)
+                Style::default().fg(app.theme.error)
             }
         } else {
             Style::default().fg(Color::Gray)
         };
         let span = if i == cursor_pos {
             Span::styled(c.to_string(), style.add_modifier(Modifier::REVERSED))
+        } else if Some(i) == ghost_pos {
+            // Where the personal best was at this point of its run
+            Span::styled(c.to_string(), style.fg(app.theme.accent).add_modifier(Modifier::UNDERLINED))
         } else {
             Span::styled(c.to_string(), style)
         };
         colored_text.push(span);
     }
 
-    if input_chars.len() > text_chars.len() {
+    if input_chars.len() > text_chars.len() && app.mode != GameMode::Zen {
         for &c in &input_chars[text_chars.len()..] {
-            colored_text.push(Span::styled(c.to_string(), Style::default().fg(Color::Red)));
+            colored_text.push(Span::styled(c.to_string(), Style::default().fg(app.theme.error)));
         }
     }
 
-    let lines = vec![Line::from(Span::from("")), Line::from(colored_text)];
-
-    f.render_widget(
-        Paragraph::new(lines)
-            .alignment(Alignment::Center)
-            .block(Block::default())
-            .wrap(Wrap { trim: true }),
-        chunks[0],
-    );
+    if app.mode == GameMode::Programming {
+        // Code keeps its lines and indentation, left-aligned in a centred block
+        // that scrolls to keep the cursor's line in view
+        let lines = code_lines(colored_text);
+        let width = lines.iter().map(Line::width).max().unwrap_or(0) as u16;
+        let code_area = Rect {
+            x: text_area.x + text_area.width.saturating_sub(width) / 2,
+            y: text_area.y + 1,
+            width: width.min(text_area.width),
+            height: text_area.height.saturating_sub(6),
+        };
+        let visible = code_area.height as usize;
+        let cursor_line = app.engine.input().matches('\n').count();
+        let scroll = if lines.len() > visible {
+            cursor_line.saturating_sub(visible / 3).min(lines.len() - visible)
+        } else {
+            0
+        };
+        f.render_widget(Paragraph::new(lines).scroll((scroll as u16, 0)), code_area);
+    } else {
+        let lines = vec![Line::from(Span::from("")), Line::from(colored_text)];
+        f.render_widget(
+            Paragraph::new(lines)
+                .alignment(Alignment::Center)
+                .block(Block::default())
+                .wrap(Wrap { trim: true }),
+            text_area,
+        );
+    }
 
-    // Attribution under text
-    if !app.current_text().source.is_empty() {
+    // Attribution under text, the language of a code snippet, or how to end a
+    // zen run
+    let footnote = match app.mode {
+        GameMode::Zen => Some("Type anything, Esc to finish".to_string()),
+        GameMode::Programming => app.language().map(|language| format!("{}   Enter: new line   Tab: indent", language)),
+        _ => app.attribution().map(|source| format!("— {}", source)),
+    };
+    if let Some(footnote) = footnote {
         let att_area = ratatui::layout::Rect {
-            x: chunks[0].x,
-            y: chunks[0].y.saturating_add(chunks[0].height.saturating_sub(5)),
-            width: chunks[0].width,
+            x: text_area.x,
+            y: text_area.y.saturating_add(text_area.height.saturating_sub(5)),
+            width: text_area.width,
             height: 2,
         };
-        let attribution_line = Line::from(Span::styled(
-            format!("— {}", app.current_text().source),
-            Style::default().fg(Color::Gray),
-        ));
+        let attribution_line = Line::from(Span::styled(footnote, Style::default().fg(Color::Gray)));
         f.render_widget(
             Paragraph::new(vec![attribution_line])
                 .alignment(Alignment::Center)
@@ -136,26 +240,36 @@ pub fn draw_typing_game(f: &mut Frame, app: &App) {
     let wpm_widget = Paragraph::new(vec![
         Line::from(Span::styled("WPM", Style::default().fg(Color::Gray))),
         Line::from(Span::styled(
-            format!("{:.0}", app.stats.wpm()),
-            Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+            format!("{:.0}", app.engine.wpm()),
+            Style::default().fg(app.theme.success).add_modifier(Modifier::BOLD),
         )),
     ])
     .alignment(Alignment::Left);
 
+    // Zen runs have nothing to be accurate to, so they count words instead
+    let (acc_label, acc_value) = match app.mode {
+        GameMode::Zen => ("WORDS", app.engine.word_count().to_string()),
+        _ => ("ACC", format!("{:.0}%", app.engine.accuracy())),
+    };
     let acc_widget = Paragraph::new(vec![
-        Line::from(Span::styled("ACC", Style::default().fg(Color::Gray))),
+        Line::from(Span::styled(acc_label, Style::default().fg(Color::Gray))),
         Line::from(Span::styled(
-            format!("{:.0}%", app.stats.accuracy()),
-            Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+            acc_value,
+            Style::default().fg(app.theme.success).add_modifier(Modifier::BOLD),
         )),
     ])
     .alignment(Alignment::Right);
 
+    // Timed runs count down to the deadline instead of up from the start
+    let time_secs = match app.time_remaining() {
+        Some(remaining) => remaining.as_secs_f64(),
+        None => app.engine.elapsed().as_secs_f64(),
+    };
     let time_widget = Paragraph::new(vec![
         Line::from(Span::styled("TIME", Style::default().fg(Color::Gray))),
         Line::from(Span::styled(
-            format!("{:.1}", app.stats.elapsed_time().as_secs_f64()),
-            Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+            format!("{:.1}", time_secs),
+            Style::default().fg(app.theme.success).add_modifier(Modifier::BOLD),
         )),
     ])
     .alignment(Alignment::Center);
@@ -163,54 +277,877 @@ pub fn draw_typing_game(f: &mut Frame, app: &App) {
     f.render_widget(wpm_widget, wpm_rect);
     f.render_widget(acc_widget, acc_rect);
     f.render_widget(time_widget, time_rect);
+
+    if let Some(gap) = app.ghost_gap() {
+        let ghost_rect = ratatui::layout::Rect { x: area.x + 1, y: area.y, width: area.width.saturating_sub(2), height: 1 };
+        f.render_widget(Paragraph::new(ghost_line(app, gap)).alignment(Alignment::Center), ghost_rect);
+    }
+}
+
+// How far ahead of or behind the ghost the cursor is, in characters
+fn ghost_line(app: &App, gap: i64) -> Line<'static> {
+    let label = if app.race.is_some() { "RACE" } else { "PB GHOST" };
+    let (text, color) = match gap {
+        0 => ("level".to_string(), Color::Gray),
+        g if g > 0 => (format!("+{} ahead", g), app.theme.success),
+        g => (format!("{} behind", -g), app.theme.error),
+    };
+    Line::from(vec![
+        Span::styled(format!("{}  ", label), Style::default().fg(app.theme.accent)),
+        Span::styled(text, Style::default().fg(color).add_modifier(Modifier::BOLD)),
+    ])
+}
+
+// "NEW PB" with the gain over the old best, or how far off the best this run was
+fn pb_line(app: &App) -> Option<Line<'static>> {
+    let run = app.last_run.as_ref()?;
+    if app.is_new_pb() {
+        let mut spans = vec![Span::styled(
+            "NEW PERSONAL BEST",
+            Style::default().fg(app.theme.success).add_modifier(Modifier::BOLD),
+        )];
+        if let Some(delta) = app.pb_delta() {
+            spans.push(Span::styled(format!("  +{:.1} wpm", delta), Style::default().fg(app.theme.success)));
+        }
+        return Some(Line::from(spans));
+    }
+    let pb = app.personal_best.as_ref()?;
+    Some(Line::from(vec![
+        Span::styled(format!("PB {:.0} wpm", pb.wpm), Style::default().fg(Color::Gray)),
+        Span::styled(format!("  {:+.1}", run.wpm - pb.wpm), Style::default().fg(app.theme.error)),
+    ]))
+}
+
+// Adaptive practice state with the keys it is currently aiming at and their weights
+fn adaptive_label(app: &App) -> String {
+    if !app.config.adaptive {
+        return "Adaptive: Off  (A to toggle)".to_string();
+    }
+    let targets = app.adaptive_weights.targets(5);
+    if targets.is_empty() {
+        return "Adaptive: On, waiting for enough key data  (A to toggle)".to_string();
+    }
+    let targets: Vec<String> = targets.iter().map(|(key, weight)| format!("{} {:.1}", key, weight)).collect();
+    format!(
+        "Adaptive: On x{:.1}, targeting {}  (A to toggle)",
+        app.config.adaptive_strength,
+        targets.join(", ")
+    )
+}
+
+fn mode_label(mode: GameMode) -> String {
+    match mode {
+        GameMode::Time(secs) => format!("Time {}s", secs),
+        GameMode::Words(count) => format!("Words {}", count),
+        GameMode::Zen => "Zen".to_string(),
+        GameMode::Programming => "Code".to_string(),
+        _ => "Passage".to_string(),
+    }
+}
+
+// Spans of a code snippet, one per character, broken into its lines. A line
+// break shows as ↵ in its character's style, and a tab as four spaces.
+fn code_lines(spans: Vec<Span<'static>>) -> Vec<Line<'static>> {
+    let mut lines = Vec::new();
+    let mut line = Vec::new();
+    for span in spans {
+        match span.content.as_ref() {
+            "\n" => {
+                line.push(Span::styled("\u{21b5}", span.style));
+                lines.push(Line::from(std::mem::take(&mut line)));
+            }
+            "\t" => line.push(Span::styled("    ", span.style)),
+            _ => line.push(span),
+        }
+    }
+    lines.push(Line::from(line));
+    lines
+}
+
+// First character to render for a text that is longer than the screen. The window
+// moves a whole line width at a time and snaps to a word start, so the text scrolls
+// in steps instead of shifting on every keystroke.
+fn window_start(text: &[&str], cursor: usize, width: usize) -> usize {
+    if width == 0 {
+        return 0;
+    }
+    let line = cursor / width;
+    if line == 0 {
+        return 0;
+    }
+    let start = ((line - 1) * width).min(text.len());
+    text[start..]
+        .iter()
+        .position(|c| graphemes::is_whitespace(c))
+        .map(|p| (start + p + 1).min(cursor))
+        .unwrap_or(start)
 }
 
 pub fn draw_end_screen(f: &mut Frame, app: &App) {
     let area = f.size();
-    // We don't render a central RESULTS section to avoid duplication.
-    // We only render anchored stats and bottom buttons.
+    // Figures go two rows deep on a narrow terminal; on a short one the chart
+    // gives way to the passage
+    let figures = result_figures(app);
+    let per_row = if area.width < 60 { figures.len().div_ceil(2) } else { figures.len() };
+    let figure_rows = figures.len().div_ceil(per_row.max(1));
+    let inner = Rect {
+        x: area.x + 1,
+        y: area.y + 1,
+        width: area.width.saturating_sub(2),
+        height: area.height.saturating_sub(2),
+    };
+    let (passage, passage_lines) = passage_lines(app, inner.width);
+    let rest = inner.height.saturating_sub(1 + 2 * figure_rows as u16 + 1 + 3);
+    let passage_height = if rest >= 12 { passage_lines.min(rest / 2) } else { rest };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1),
+            Constraint::Length(2 * figure_rows as u16 + 1),
+            Constraint::Min(0),
+            Constraint::Length(passage_height),
+            Constraint::Length(3),
+        ])
+        .split(inner);
+
+    let header = pb_line(app).unwrap_or_else(|| {
+        Line::from(Span::styled("RESULTS", Style::default().fg(app.theme.success).add_modifier(Modifier::BOLD)))
+    });
+    f.render_widget(Paragraph::new(header).alignment(Alignment::Center), chunks[0]);
+
+    for (row, figures) in figures.chunks(per_row.max(1)).enumerate() {
+        let row_rect = Rect { y: chunks[1].y + 2 * row as u16, height: 2, ..chunks[1] };
+        let columns = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(vec![Constraint::Ratio(1, figures.len() as u32); figures.len()])
+            .split(row_rect);
+        for ((label, value), rect) in figures.iter().zip(columns.iter()) {
+            let figure = Paragraph::new(vec![
+                Line::from(Span::styled(*label, Style::default().fg(Color::Gray))),
+                Line::from(Span::styled(
+                    value.clone(),
+                    Style::default().fg(app.theme.success).add_modifier(Modifier::BOLD),
+                )),
+            ])
+            .alignment(Alignment::Center);
+            f.render_widget(figure, *rect);
+        }
+    }
+
+    let timeline = app.engine.timeline();
+    if !timeline.is_empty() && chunks[2].height >= 6 {
+        draw_timeline_chart(f, app, chunks[2], &timeline);
+    }
+
+    // Trimming would take the indentation off code
+    let trim = app.mode != GameMode::Programming;
+    f.render_widget(Paragraph::new(passage).wrap(Wrap { trim }), chunks[3]);
+
+    // A zen run has no text to race
+    let actions = if app.mode == GameMode::Zen { "S: Stats   R: Replay" } else { "S: Stats   R: Replay   G: Race It" };
+    let buttons = Paragraph::new(vec![
+        Line::from(Span::styled("Enter: Play Again", Style::default())),
+        Line::from(Span::styled(actions, Style::default())),
+        Line::from(Span::styled("Esc: Main Menu", Style::default())),
+    ])
+    .alignment(Alignment::Center);
+    f.render_widget(buttons, chunks[4]);
+}
+
+// Label and value of every figure on the results screen
+fn result_figures(app: &App) -> Vec<(&'static str, String)> {
+    if app.mode == GameMode::Zen {
+        // Free typing has speed and length but nothing to get right
+        return vec![
+            ("WPM", format!("{:.0}", app.engine.wpm())),
+            ("CONSISTENCY", format!("{:.0}%", app.engine.consistency())),
+            ("TIME", format!("{:.1}s", app.engine.elapsed().as_secs_f64())),
+            ("WORDS", app.engine.word_count().to_string()),
+            ("CHARS", graphemes::count(app.engine.input()).to_string()),
+        ];
+    }
+    let counts = app.engine.char_counts();
+    let mut figures = vec![
+        ("WPM", format!("{:.0}", app.engine.wpm())),
+        ("RAW", format!("{:.0}", app.engine.raw_wpm())),
+        ("ACC", format!("{:.0}%", app.engine.accuracy())),
+        ("CONSISTENCY", format!("{:.0}%", app.engine.consistency())),
+        ("TIME", format!("{:.1}s", app.engine.elapsed().as_secs_f64())),
+        ("CHARS", format!("{}/{}/{}/{}", counts.correct, counts.incorrect, counts.extra, counts.missed)),
+        ("STREAK", app.engine.stats().get_best_streak().to_string()),
+    ];
+    if let GameMode::Words(_) = app.mode {
+        let results = app.word_results();
+        let correct = results.iter().filter(|r| r.correct).count();
+        figures.push(("WORDS", format!("{}/{}", correct, results.len())));
+    }
+    figures
+}
+
+// The passage as typed, with every character that was ever mistyped marked: a
+// mistake fixed since is underlined, one left in is shown reversed. Below it
+// the word-count misses and the attribution. Also returns roughly how many
+// lines that takes at `width`.
+fn passage_lines(app: &App, width: u16) -> (Vec<Line<'static>>, u16) {
+    let width = width.max(1) as usize;
+    if app.mode == GameMode::Zen {
+        let input = app.engine.input().to_string();
+        let height = graphemes::count(&input).div_ceil(width) as u16 + 1;
+        return (vec![Line::from(Span::styled(input, Style::default().fg(app.theme.foreground)))], height);
+    }
+    let text = graphemes::graphemes(app.engine.text());
+    let input = graphemes::graphemes(app.engine.input());
+    let mistakes = app.engine.mistake_positions();
+    // A timed run's text never ends, so only what was reached is shown
+    let shown = if app.time_limit().is_some() { input.len().min(text.len()) } else { text.len() };
+    let mut spans: Vec<Span> = Vec::new();
+    for (i, &c) in text.iter().enumerate().take(shown) {
+        let style = if i >= input.len() {
+            Style::default().fg(Color::Gray)
+        } else if input[i] != c {
+            Style::default().fg(app.theme.error).add_modifier(Modifier::REVERSED)
+        } else if mistakes.binary_search(&i).is_ok() {
+            Style::default().fg(app.theme.error).add_modifier(Modifier::UNDERLINED)
+        } else {
+            Style::default().fg(app.theme.success)
+        };
+        spans.push(Span::styled(c.to_string(), style));
+    }
+    let (mut lines, mut height) = if app.mode == GameMode::Programming {
+        let lines = code_lines(spans);
+        let height = lines.len() as u16;
+        (lines, height)
+    } else {
+        (vec![Line::from(spans)], shown.div_ceil(width) as u16 + 1)
+    };
+
+    // Word-count runs also spell out the misses
+    if let GameMode::Words(_) = app.mode {
+        let mut missed: Vec<Span> = Vec::new();
+        for r in app.word_results().iter().filter(|r| !r.correct) {
+            missed.push(Span::styled(r.typed.clone(), Style::default().fg(app.theme.error)));
+            missed.push(Span::styled(format!(" \u{2192} {}   ", r.expected), Style::default().fg(Color::Gray)));
+        }
+        if !missed.is_empty() {
+            lines.push(Line::from(missed));
+            height += 2;
+        }
+    }
+    if let Some(source) = app.attribution() {
+        lines.push(Line::from(Span::styled(format!("— {}", source), Style::default().fg(Color::Gray))));
+        height += 1;
+    }
+    (lines, height)
+}
+
+pub fn draw_stats_screen(f: &mut Frame, app: &App) {
+    let area = f.size();
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([
+            Constraint::Length(2),
+            Constraint::Length(6),
+            Constraint::Min(8),
+            Constraint::Length(4),
+            Constraint::Length(1),
+        ])
+        .split(area);
 
-    // Anchored stats at the edges
-    let wpm_rect = ratatui::layout::Rect { x: area.x + 1, y: area.y + area.height.saturating_sub(6), width: 20, height: 3 };
-    let acc_rect = ratatui::layout::Rect { x: area.x + area.width.saturating_sub(21), y: area.y + area.height.saturating_sub(6), width: 20, height: 3 };
-    let time_rect = ratatui::layout::Rect { x: area.x + area.width / 2 - 10, y: area.y + area.height.saturating_sub(5), width: 20, height: 2 };
-    let buttons_rect = ratatui::layout::Rect { x: area.x + area.width / 2 - 20, y: area.y + area.height.saturating_sub(2), width: 40, height: 2 };
+    f.render_widget(
+        Paragraph::new(Line::from(Span::styled(
+            "STATISTICS",
+            Style::default().fg(app.theme.success).add_modifier(Modifier::BOLD),
+        )))
+        .alignment(Alignment::Center),
+        chunks[0],
+    );
+    f.render_widget(
+        Paragraph::new(Line::from(Span::styled(
+            "Tab: Trends / Keys   Esc: Back",
+            Style::default().fg(Color::Gray),
+        )))
+        .alignment(Alignment::Center),
+        chunks[4],
+    );
 
-    let wpm_widget = Paragraph::new(vec![
-        Line::from(Span::styled("WPM", Style::default().fg(Color::Gray))),
-        Line::from(Span::styled(
-            format!("{:.0}", app.stats.wpm()),
-            Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+    let Some(history) = app.history.as_ref().filter(|h| !h.runs().is_empty()) else {
+        f.render_widget(
+            Paragraph::new("No runs saved yet. Finish a test to start your history.")
+                .alignment(Alignment::Center),
+            chunks[1],
+        );
+        return;
+    };
+
+    if app.stats_view == StatsView::Keys {
+        let body = Rect {
+            x: chunks[1].x,
+            y: chunks[1].y,
+            width: chunks[1].width,
+            height: chunks[4].y.saturating_sub(chunks[1].y),
+        };
+        draw_key_heatmap(f, app, body, &history.key_stats(&RunQuery::new()));
+        return;
+    }
+
+    // Averages on the left, one line per category on the right
+    let top = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(chunks[1]);
+    let all = history.summary(&RunQuery::new());
+    let last_10 = history.summary(&RunQuery::new().limit(10));
+    let last_100 = history.summary(&RunQuery::new().limit(100));
+    let mut overview = Vec::new();
+    if let Some(all) = &all {
+        let secs = all.total_time.as_secs();
+        overview.push(Line::from(format!(
+            "Runs {}   Best {:.0} wpm   Practiced {}h {:02}m",
+            all.runs,
+            all.best_wpm,
+            secs / 3600,
+            secs / 60 % 60
+        )));
+    }
+    for (label, summary) in [("Last 10", &last_10), ("Last 100", &last_100), ("All time", &all)] {
+        if let Some(summary) = summary {
+            overview.push(summary_line(label, summary));
+        }
+    }
+    f.render_widget(
+        Paragraph::new(overview).block(Block::default().borders(Borders::ALL).title("Averages")),
+        top[0],
+    );
+    let categories: Vec<Line> = history
+        .category_summaries()
+        .iter()
+        .map(|(category, summary)| summary_line(category, summary))
+        .collect();
+    f.render_widget(
+        Paragraph::new(categories).block(Block::default().borders(Borders::ALL).title("By category")),
+        top[1],
+    );
+
+    // Trends over the most recent runs, oldest on the left
+    let mut recent = history.query(&RunQuery::new().limit(TREND_RUNS));
+    recent.reverse();
+    let wpm_points: Vec<(f64, f64)> = recent.iter().enumerate().map(|(i, r)| (i as f64 + 1.0, r.wpm)).collect();
+    draw_trend_chart(f, app, chunks[2], &wpm_points);
+
+    let accuracy: Vec<u64> = recent.iter().map(|r| r.accuracy.round() as u64).collect();
+    let last_accuracy = recent.last().map(|r| r.accuracy).unwrap_or(0.0);
+    f.render_widget(
+        Sparkline::default()
+            .block(Block::default().borders(Borders::ALL).title(format!("Accuracy (last {:.0}%)", last_accuracy)))
+            .data(&accuracy)
+            .max(100)
+            .style(Style::default().fg(app.theme.success)),
+        chunks[3],
+    );
+}
+
+// WPM over the run as sampled each second, against the raw WPM of each second,
+// with a dot on the WPM line for every second that had a mistake in it
+fn draw_timeline_chart(f: &mut Frame, app: &App, area: Rect, timeline: &[Second]) {
+    let point = |i: usize, wpm: f64| (i as f64 + 1.0, wpm);
+    // Runs too short for a whole-second sample fall back to the per-second net
+    let net: Vec<(f64, f64)> = if app.wpm_history.is_empty() {
+        timeline.iter().enumerate().map(|(i, s)| point(i, s.net_wpm)).collect()
+    } else {
+        app.wpm_history.iter().enumerate().map(|(i, &wpm)| point(i, wpm as f64)).collect()
+    };
+    let raw: Vec<(f64, f64)> = timeline.iter().enumerate().map(|(i, s)| point(i, s.raw_wpm)).collect();
+    let errors: Vec<(f64, f64)> = timeline
+        .iter()
+        .enumerate()
+        .filter(|(_, s)| s.errors > 0)
+        .map(|(i, s)| net.get(i).copied().unwrap_or_else(|| point(i, s.net_wpm)))
+        .collect();
+    let max_wpm = raw.iter().chain(&net).map(|&(_, wpm)| wpm).fold(0.0, f64::max);
+    let y_max = ((max_wpm / 20.0).floor() + 1.0) * 20.0;
+    let seconds = timeline.len().max(net.len());
+    let x_max = (seconds as f64).max(2.0);
+    let datasets = vec![
+        Dataset::default()
+            .name("raw")
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(Color::Gray))
+            .data(&raw),
+        Dataset::default()
+            .name("wpm")
+            .marker(symbols::Marker::Braille)
+            .graph_type(GraphType::Line)
+            .style(Style::default().fg(app.theme.accent))
+            .data(&net),
+        Dataset::default()
+            .name("errors")
+            .marker(symbols::Marker::Dot)
+            .graph_type(GraphType::Scatter)
+            .style(Style::default().fg(app.theme.error))
+            .data(&errors),
+    ];
+    let title = format!(
+        "WPM over time   consistency {:.0}%   burst {:.0}",
+        app.engine.consistency(),
+        app.engine.burst()
+    );
+    let chart = Chart::new(datasets)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .x_axis(
+            Axis::default()
+                .style(Style::default().fg(Color::Gray))
+                .bounds([1.0, x_max])
+                .labels(vec![Span::raw("1s"), Span::raw(format!("{}s", seconds))]),
+        )
+        .y_axis(
+            Axis::default()
+                .style(Style::default().fg(Color::Gray))
+                .bounds([0.0, y_max])
+                .labels(vec![Span::raw("0"), Span::raw(format!("{:.0}", y_max))]),
+        );
+    f.render_widget(chart, area);
+}
+
+fn summary_line(label: &str, summary: &Summary) -> Line<'static> {
+    Line::from(vec![
+        Span::styled(format!("{:<12}", label), Style::default().fg(Color::Gray)),
+        Span::raw(format!(
+            "{:>5.1} wpm  {:>5.1}%  ({} runs)",
+            summary.average_wpm, summary.average_accuracy, summary.runs
         )),
     ])
-    .alignment(Alignment::Left);
+}
 
-    let acc_widget = Paragraph::new(vec![
-        Line::from(Span::styled("ACC", Style::default().fg(Color::Gray))),
-        Line::from(Span::styled(
-            format!("{:.0}%", app.stats.accuracy()),
-            Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+fn draw_trend_chart(f: &mut Frame, app: &App, area: Rect, points: &[(f64, f64)]) {
+    let max_wpm = points.iter().map(|&(_, wpm)| wpm).fold(0.0, f64::max);
+    // Round the top of the axis up to the next 20 WPM so the line never touches it
+    let y_max = ((max_wpm / 20.0).floor() + 1.0) * 20.0;
+    let x_max = (points.len() as f64).max(2.0);
+    let datasets = vec![Dataset::default()
+        .name("wpm")
+        .marker(symbols::Marker::Braille)
+        .graph_type(GraphType::Line)
+        .style(Style::default().fg(app.theme.accent))
+        .data(points)];
+    let chart = Chart::new(datasets)
+        .block(Block::default().borders(Borders::ALL).title(format!("WPM (last {} runs)", points.len())))
+        .x_axis(
+            Axis::default()
+                .style(Style::default().fg(Color::Gray))
+                .bounds([1.0, x_max])
+                .labels(vec![Span::raw("1"), Span::raw(format!("{}", points.len()))]),
+        )
+        .y_axis(
+            Axis::default()
+                .style(Style::default().fg(Color::Gray))
+                .bounds([0.0, y_max])
+                .labels(vec![
+                    Span::raw("0"),
+                    Span::raw(format!("{:.0}", y_max / 2.0)),
+                    Span::raw(format!("{:.0}", y_max)),
+                ]),
+        );
+    f.render_widget(chart, area);
+}
+
+// Keys need this many attempts before they are ranked among the weakest
+const MIN_KEY_ATTEMPTS: u32 = 5;
+
+fn draw_key_heatmap(f: &mut Frame, app: &App, area: Rect, keys: &KeyStats) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(9), Constraint::Min(4)])
+        .split(area);
+
+    // One cell per key, shaded by how often it was hit, indented like a real keyboard
+    let caps = keys.by_key_cap();
+    let mut lines: Vec<Line> = Vec::new();
+    for (row, keys_in_row) in KEYBOARD_ROWS.iter().enumerate() {
+        let mut spans = vec![Span::raw(" ".repeat(row * 2))];
+        for cap in keys_in_row.chars() {
+            spans.push(Span::styled(format!(" {} ", cap), heat_style(caps.get(&cap))));
+            spans.push(Span::raw(" "));
+        }
+        lines.push(Line::from(spans));
+    }
+    lines.push(Line::from(Span::styled(" ".repeat(30), heat_style(caps.get(&' ')))));
+    lines.push(Line::from(""));
+    lines.push(Line::from(vec![
+        Span::styled(" 98%+ ", heat_style(Some(&KeyStat { hits: 1, ..KeyStat::default() }))),
+        Span::raw(" "),
+        Span::styled(" 94%+ ", heat_style(Some(&KeyStat { hits: 95, misses: 5, ..KeyStat::default() }))),
+        Span::raw(" "),
+        Span::styled(" 90%+ ", heat_style(Some(&KeyStat { hits: 91, misses: 9, ..KeyStat::default() }))),
+        Span::raw(" "),
+        Span::styled(" less ", heat_style(Some(&KeyStat { misses: 1, ..KeyStat::default() }))),
+        Span::raw(" "),
+        Span::styled(" no data ", heat_style(None)),
+    ]));
+    f.render_widget(
+        Paragraph::new(lines)
+            .alignment(Alignment::Center)
+            .block(Block::default().borders(Borders::ALL).title("Accuracy by key")),
+        chunks[0],
+    );
+
+    let lists = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(chunks[1]);
+    let rows = lists[0].height.saturating_sub(2) as usize;
+    let weakest_keys: Vec<Line> = keys
+        .weakest_keys(MIN_KEY_ATTEMPTS)
+        .into_iter()
+        .take(rows)
+        .map(|(key, stat)| key_line(app, &format!("{:?}", key), &stat))
+        .collect();
+    let weakest_bigrams: Vec<Line> = keys
+        .weakest_bigrams(MIN_KEY_ATTEMPTS)
+        .into_iter()
+        .take(rows)
+        .map(|(bigram, stat)| key_line(app, &format!("{:?}", bigram), &stat))
+        .collect();
+    f.render_widget(
+        Paragraph::new(weakest_keys).block(Block::default().borders(Borders::ALL).title("Weakest keys")),
+        lists[0],
+    );
+    f.render_widget(
+        Paragraph::new(weakest_bigrams).block(Block::default().borders(Borders::ALL).title("Weakest bigrams")),
+        lists[1],
+    );
+}
+
+fn heat_style(stat: Option<&KeyStat>) -> Style {
+    let bg = match stat.filter(|s| s.attempts() > 0).map(KeyStat::accuracy) {
+        None => Color::DarkGray,
+        Some(acc) if acc >= 98.0 => Color::Green,
+        Some(acc) if acc >= 94.0 => Color::Yellow,
+        Some(acc) if acc >= 90.0 => Color::LightRed,
+        Some(_) => Color::Red,
+    };
+    Style::default().fg(Color::Black).bg(bg)
+}
+
+fn key_line(app: &App, label: &str, stat: &KeyStat) -> Line<'static> {
+    let latency = stat
+        .mean_latency_ms()
+        .map(|ms| format!("{:>4.0} ms", ms))
+        .unwrap_or_else(|| "   - ms".to_string());
+    Line::from(vec![
+        Span::styled(format!("{:<6}", label), Style::default().fg(app.theme.accent)),
+        Span::raw(format!(
+            "{:>5.1}%  {}  missed {}/{}",
+            stat.accuracy(),
+            latency,
+            stat.misses,
+            stat.attempts()
         )),
     ])
-    .alignment(Alignment::Right);
+}
 
-    let time_widget = Paragraph::new(vec![
-        Line::from(Span::styled("TIME", Style::default().fg(Color::Gray))),
+pub fn draw_replay(f: &mut Frame, app: &App) {
+    let Some(player) = &app.replay else {
+        return;
+    };
+    let area = f.size();
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([Constraint::Length(2), Constraint::Min(0), Constraint::Length(3), Constraint::Length(1)])
+        .split(area);
+
+    let status = if player.is_done() {
+        "finished"
+    } else if player.paused {
+        "paused"
+    } else {
+        "playing"
+    };
+    let header = Line::from(vec![
+        Span::styled("REPLAY", Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD)),
+        Span::styled(
+            format!("  {}  {}x  {}", mode_label(player.log.mode), player.speed, status),
+            Style::default().fg(Color::Gray),
+        ),
+    ]);
+    f.render_widget(Paragraph::new(header).alignment(Alignment::Center), chunks[0]);
+
+    // The replayed input over the text, coloured like a live run
+    let input = player.input();
+    let text_chars = graphemes::graphemes(&player.log.target);
+    let input_chars = graphemes::graphemes(&input);
+    let cursor = input_chars.len();
+    let code = player.log.mode == GameMode::Programming;
+    let start = if code { 0 } else { window_start(&text_chars, cursor, chunks[1].width as usize) };
+    let mut spans: Vec<Span> = Vec::new();
+    for (i, &c) in text_chars.iter().enumerate().skip(start) {
+        let style = match input_chars.get(i) {
+            Some(&typed) if typed == c => Style::default().fg(app.theme.success),
+            Some(_) => Style::default().fg(app.theme.error),
+            None => Style::default().fg(Color::Gray),
+        };
+        let style = if i == cursor { style.add_modifier(Modifier::REVERSED) } else { style };
+        spans.push(Span::styled(c.to_string(), style));
+    }
+    // Past the end of the text is a mistake, except in zen where there is no text
+    let extra = if player.log.mode == GameMode::Zen { app.theme.foreground } else { app.theme.error };
+    for &c in input_chars.iter().skip(text_chars.len()) {
+        spans.push(Span::styled(c.to_string(), Style::default().fg(extra)));
+    }
+    if code {
+        let mut lines = vec![Line::from("")];
+        lines.extend(code_lines(spans));
+        f.render_widget(Paragraph::new(lines), chunks[1]);
+    } else {
+        f.render_widget(
+            Paragraph::new(vec![Line::from(""), Line::from(spans)])
+                .alignment(Alignment::Center)
+                .wrap(Wrap { trim: true }),
+            chunks[1],
+        );
+    }
+
+    let figures = Line::from(vec![
+        Span::styled("TIME ", Style::default().fg(Color::Gray)),
+        Span::styled(
+            format!("{:.1}/{:.1}s", player.position().as_secs_f64(), player.log.duration().as_secs_f64()),
+            Style::default().fg(app.theme.success).add_modifier(Modifier::BOLD),
+        ),
+        Span::styled("   WPM ", Style::default().fg(Color::Gray)),
+        Span::styled(
+            format!("{:.0}", replay_wpm(player)),
+            Style::default().fg(app.theme.success).add_modifier(Modifier::BOLD),
+        ),
+        Span::styled("   MISTAKES ", Style::default().fg(Color::Gray)),
+        Span::styled(
+            player.mistakes().to_string(),
+            Style::default().fg(app.theme.error).add_modifier(Modifier::BOLD),
+        ),
+    ]);
+    let idle = player.idle();
+    let hesitation = if idle >= HESITATION && !player.is_done() {
+        Line::from(Span::styled(
+            format!("hesitating {:.1}s", idle.as_secs_f64()),
+            Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD),
+        ))
+    } else {
+        Line::from("")
+    };
+    f.render_widget(Paragraph::new(vec![figures, hesitation]).alignment(Alignment::Center), chunks[2]);
+
+    let help = "Space: Pause   +/-: Speed   R: Restart   Esc: Back";
+    f.render_widget(
+        Paragraph::new(Span::styled(help, Style::default().fg(Color::Gray))).alignment(Alignment::Center),
+        chunks[3],
+    );
+}
+
+// Correct characters at the replay position, five to a word. The first second
+// counts as a whole one so the opening keystrokes don't show absurd rates.
+fn replay_wpm(player: &ReplayPlayer) -> f64 {
+    let input = player.input();
+    let correct = graphemes::graphemes(&input)
+        .into_iter()
+        .zip(graphemes::graphemes(&player.log.target))
+        .filter(|(typed, expected)| typed == expected)
+        .count();
+    metrics::net_wpm(correct, player.position().max(std::time::Duration::from_secs(1)))
+}
+
+pub fn draw_join_screen(f: &mut Frame, app: &App) {
+    let form = &app.join_form;
+    let choice = |label: &'static str, on: bool| {
+        if on {
+            Span::styled(format!("[{}]", label), Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD))
+        } else {
+            Span::styled(format!(" {} ", label), Style::default().fg(Color::Gray))
+        }
+    };
+    let mut lines = vec![
         Line::from(Span::styled(
-            format!("{:.1}", app.stats.elapsed_time().as_secs_f64()),
-            Style::default().fg(Color::Green).add_modifier(Modifier::BOLD),
+            "RACE ONLINE",
+            Style::default().fg(app.theme.success).add_modifier(Modifier::BOLD),
         )),
-    ])
-    .alignment(Alignment::Center);
+        Line::from(""),
+        Line::from(vec![choice("Host", form.host), Span::from("   "), choice("Join", !form.host)]),
+        Line::from(""),
+    ];
+    for &field in form.fields() {
+        let (label, value) = match field {
+            JoinField::Name => ("Name", &form.name),
+            JoinField::Address if form.host => ("Listen on", &form.address),
+            JoinField::Address => ("Server", &form.address),
+            JoinField::Room => ("Room code", &form.room),
+        };
+        let style = if field == form.field {
+            Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD)
+        } else {
+            Style::default()
+        };
+        let cursor = if field == form.field { "_" } else { "" };
+        lines.push(Line::from(vec![
+            Span::styled(format!("{:>10}: ", label), Style::default().fg(Color::Gray)),
+            Span::styled(format!("{:<24}", format!("{}{}", value, cursor)), style),
+        ]));
+    }
+    lines.push(Line::from(""));
+    if let Some(error) = &form.error {
+        lines.push(Line::from(Span::styled(error.clone(), Style::default().fg(app.theme.error))));
+        lines.push(Line::from(""));
+    }
+    let action = if form.host { "Enter: Host a Room" } else { "Enter: Join" };
+    lines.push(Line::from(Span::styled(action, Style::default())));
+    lines.push(Line::from(Span::styled("Host / Join: \u{2190} / \u{2192}   Next Field: Tab", Style::default())));
+    lines.push(Line::from(Span::styled("Esc: Main Menu", Style::default())));
 
-    let buttons = Paragraph::new(vec![
-        Line::from(Span::styled("Enter: Play Again", Style::default())),
-        Line::from(Span::styled("Esc: Main Menu", Style::default())),
-    ])
-    .alignment(Alignment::Center);
+    let chunks = Layout::default().direction(Direction::Vertical).margin(1).constraints([Constraint::Min(0)]).split(f.size());
+    f.render_widget(Paragraph::new(lines).alignment(Alignment::Center), chunks[0]);
+}
 
-    f.render_widget(wpm_widget, wpm_rect);
-    f.render_widget(acc_widget, acc_rect);
-    f.render_widget(time_widget, time_rect);
-    f.render_widget(buttons, buttons_rect);
+pub fn draw_lobby(f: &mut Frame, app: &App) {
+    let Some(room) = &app.room else {
+        return;
+    };
+    let mut lines = vec![
+        Line::from(vec![
+            Span::styled("ROOM ", Style::default().fg(Color::Gray)),
+            Span::styled(room.code.clone(), Style::default().fg(app.theme.success).add_modifier(Modifier::BOLD)),
+        ]),
+        Line::from(""),
+    ];
+    for racer in &room.racers {
+        let (mark, color) = if racer.info.id == room.host {
+            ("host ", app.theme.accent)
+        } else if racer.info.ready {
+            ("ready", app.theme.success)
+        } else {
+            ("  ...", Color::Gray)
+        };
+        let you = if racer.info.id == room.player_id { " (you)" } else { "" };
+        lines.push(Line::from(vec![
+            Span::styled(format!("{}  ", mark), Style::default().fg(color).add_modifier(Modifier::BOLD)),
+            Span::styled(format!("{:<24}", format!("{}{}", racer.info.name, you)), Style::default()),
+        ]));
+    }
+    lines.push(Line::from(""));
+    if let Some(notice) = &room.notice {
+        lines.push(Line::from(Span::styled(notice.clone(), Style::default().fg(app.theme.error))));
+        lines.push(Line::from(""));
+    }
+    if room.is_host() {
+        let start = if room.all_ready() { "Enter: Start the Race" } else { "Waiting for everyone to be ready" };
+        lines.push(Line::from(Span::styled(start, Style::default())));
+        lines.push(Line::from(Span::styled(
+            format!("Others join room {} with this machine's address", room.code),
+            Style::default().fg(Color::Gray),
+        )));
+    } else {
+        let ready = room.me().is_some_and(|me| me.info.ready);
+        let label = if ready { "Space: Not Ready" } else { "Space: Ready" };
+        lines.push(Line::from(Span::styled(label, Style::default())));
+    }
+    lines.push(Line::from(Span::styled("Esc: Leave Room", Style::default())));
+
+    let chunks = Layout::default().direction(Direction::Vertical).margin(1).constraints([Constraint::Min(0)]).split(f.size());
+    f.render_widget(Paragraph::new(lines).alignment(Alignment::Center), chunks[0]);
+}
+
+pub fn draw_countdown(f: &mut Frame, app: &App) {
+    let Some(room) = &app.room else {
+        return;
+    };
+    let left = app
+        .race_start
+        .map(|start| start.saturating_duration_since(std::time::Instant::now()))
+        .unwrap_or_default();
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([Constraint::Length(3), Constraint::Min(0), Constraint::Length(1)])
+        .split(f.size());
+    let count = Line::from(Span::styled(
+        format!("{}", left.as_secs_f64().ceil() as u64),
+        Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD),
+    ));
+    f.render_widget(
+        Paragraph::new(vec![Line::from(Span::styled("GET READY", Style::default().fg(Color::Gray))), count])
+            .alignment(Alignment::Center),
+        chunks[0],
+    );
+    let text = room.text.clone().unwrap_or_default();
+    f.render_widget(
+        Paragraph::new(Span::styled(text, Style::default().fg(Color::Gray)))
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true }),
+        chunks[1],
+    );
+    f.render_widget(
+        Paragraph::new(Span::styled("Esc: Leave Room", Style::default().fg(Color::Gray))).alignment(Alignment::Center),
+        chunks[2],
+    );
+}
+
+pub fn draw_race_results(f: &mut Frame, app: &App) {
+    let Some(room) = &app.room else {
+        return;
+    };
+    let mut lines = vec![
+        Line::from(Span::styled(
+            if room.standings.is_empty() { "WAITING FOR THE OTHERS" } else { "RESULTS" },
+            Style::default().fg(app.theme.success).add_modifier(Modifier::BOLD),
+        )),
+        Line::from(""),
+    ];
+    if room.standings.is_empty() {
+        lines.extend(racer_lines(app, room));
+    } else {
+        for standing in &room.standings {
+            let place = standing.place.map(|p| format!("{:>2}.", p)).unwrap_or_else(|| " --".to_string());
+            let mut result = match (standing.time, &standing.disqualified) {
+                (_, Some(reason)) => format!("DQ: {}", reason),
+                (Some(time), None) => format!("{:>4.0} wpm  {:>6.1}s", standing.wpm, time),
+                (None, None) => format!("{:>4.0}% done", standing.progress),
+            };
+            if !standing.flags.is_empty() {
+                let flags: Vec<&str> = standing.flags.iter().map(|flag| flag.label()).collect();
+                result.push_str(&format!("  ({})", flags.join(", ")));
+            }
+            let style = if standing.player_id == room.player_id {
+                Style::default().fg(app.theme.accent).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            lines.push(Line::from(Span::styled(format!("{} {:<16} {}", place, standing.name, result), style)));
+        }
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled("Enter: Back to the Room   Esc: Leave Room", Style::default())));
+
+    let chunks = Layout::default().direction(Direction::Vertical).margin(1).constraints([Constraint::Min(0)]).split(f.size());
+    f.render_widget(Paragraph::new(lines).alignment(Alignment::Center), chunks[0]);
+}
+
+// One line per racer: name, progress bar, WPM and place once finished
+fn racer_lines(app: &App, room: &RoomView) -> Vec<Line<'static>> {
+    room.racers.iter().map(|racer| racer_line(app, racer, racer.info.id == room.player_id)).collect()
+}
+
+fn racer_line(app: &App, racer: &Racer, me: bool) -> Line<'static> {
+    let filled = ((racer.progress.clamp(0.0, 100.0) / 100.0) * RACE_BAR_WIDTH as f32).round() as usize;
+    let bar_color = if me { app.theme.accent } else { app.theme.success };
+    let status = match (racer.finish, &racer.disqualified) {
+        (_, Some(_)) => format!("{:>4.0} wpm  DQ", racer.wpm),
+        // A mark for runs the server found unusual
+        (Some((place, _)), None) if !racer.flags.is_empty() => format!("{:>4.0} wpm  #{} ?", racer.wpm, place),
+        (Some((place, _)), None) => format!("{:>4.0} wpm  #{}", racer.wpm, place),
+        (None, None) => format!("{:>4.0} wpm    ", racer.wpm),
+    };
+    Line::from(vec![
+        Span::styled(format!("{:>12} ", racer.info.name), Style::default().fg(if me { app.theme.accent } else { Color::Gray })),
+        Span::styled("\u{2588}".repeat(filled), Style::default().fg(bar_color)),
+        Span::styled("\u{2591}".repeat(RACE_BAR_WIDTH - filled), Style::default().fg(Color::DarkGray)),
+        Span::styled(format!(" {}", status), Style::default()),
+    ])
 }
@@ -1,98 +1,164 @@
-use crate::game::Game;
+use crate::engine::Engine;
+use crate::game::GameMode;
+use crate::keystats::KeyStats;
+use crate::metrics::{Metrics, Second};
+use crate::normalize::Normalization;
+use crate::replay::KeystrokeLog;
 use crate::types::Theme;
+use crate::words::{WordList, WordResult};
 
 pub struct TyperPunkGame {
-    pub game: Game,
+    pub engine: Engine,
+    theme: Theme,
 }
 
 impl TyperPunkGame {
     pub fn new() -> Self {
         Self {
-            game: Game::new(),
+            engine: Engine::default(),
+            theme: Theme::default(),
         }
     }
 
     pub fn set_text(&mut self, text: String) {
-        self.game.set_text(text);
+        if self.engine.mode() == GameMode::Zen {
+            self.engine.set_mode(GameMode::Normal);
+        }
+        self.engine.set_text(&text);
+    }
+
+    pub fn set_words_mode(&mut self, count: usize) {
+        let text = WordList::english().generate(count, &mut rand::thread_rng());
+        self.engine = Engine::with_normalization(GameMode::Words(count), &text, self.engine.normalization());
+    }
+
+    pub fn set_zen_mode(&mut self) {
+        self.engine = Engine::with_normalization(GameMode::Zen, "", self.engine.normalization());
+    }
+
+    pub fn finish(&mut self) {
+        self.engine.stop();
+    }
+
+    pub fn set_normalization(&mut self, normalization: Normalization) {
+        self.engine.set_normalization(normalization);
+    }
+
+    pub fn get_word_count(&self) -> usize {
+        self.engine.word_count()
+    }
+
+    pub fn get_words_committed(&self) -> usize {
+        self.engine.words_committed()
+    }
+
+    pub fn get_word_results(&self) -> Vec<WordResult> {
+        self.engine.word_results()
+    }
+
+    pub fn get_key_stats(&self) -> KeyStats {
+        self.engine.key_stats().clone()
+    }
+
+    pub fn get_keystroke_log(&self) -> KeystrokeLog {
+        self.engine.keystroke_log().clone()
     }
 
     pub fn get_text(&self) -> String {
-        self.game.get_text()
+        self.engine.text().to_string()
     }
 
     pub fn get_input(&self) -> String {
-        self.game.get_input().to_string()
+        self.engine.input().to_string()
     }
 
     pub fn start(&mut self) {
-        self.game.start();
+        self.engine.start();
     }
 
     pub fn handle_input(&mut self, input: &str) -> Result<(), String> {
-        self.game.handle_input(input)
+        self.engine.set_input(input);
+        Ok(())
     }
 
     pub fn is_finished(&self) -> bool {
-        self.game.is_finished()
+        self.engine.is_finished()
     }
 
     pub fn get_error_positions(&self) -> Vec<usize> {
-        self.game.get_error_positions()
+        self.engine.stats().get_error_positions()
     }
 
     pub fn get_current_streak(&self) -> u32 {
-        self.game.get_current_streak()
+        self.engine.stats().get_current_streak() as u32
     }
 
     pub fn get_best_streak(&self) -> u32 {
-        self.game.get_best_streak()
+        self.engine.stats().get_best_streak() as u32
     }
 
     pub fn get_theme(&self) -> String {
-        self.game.get_theme().to_string()
+        self.theme.to_string()
     }
 
     pub fn set_theme(&mut self, theme: String) {
-        let theme = match theme.as_str() {
+        self.theme = match theme.as_str() {
             "light" => Theme::Light,
             _ => Theme::Dark,
         };
-        self.game.set_theme(theme);
     }
 
     pub fn get_wpm(&self) -> f64 {
-        self.game.get_wpm()
+        self.engine.wpm()
+    }
+
+    pub fn get_raw_wpm(&self) -> f64 {
+        self.engine.raw_wpm()
     }
 
     pub fn get_accuracy(&self) -> f64 {
-        self.game.get_accuracy()
+        self.engine.accuracy()
+    }
+
+    pub fn get_metrics(&self) -> Metrics {
+        self.engine.metrics()
+    }
+
+    pub fn get_timeline(&self) -> Vec<Second> {
+        self.engine.timeline()
     }
 
     pub fn get_time_elapsed(&self) -> f64 {
-        self.game.get_time_elapsed()
+        self.engine.elapsed().as_secs_f64()
     }
 
     pub fn can_backspace(&self) -> bool {
-        self.game.can_backspace()
+        self.engine.can_backspace()
     }
 
     pub fn can_ctrl_backspace(&self) -> bool {
-        self.game.can_ctrl_backspace()
+        self.engine.can_backspace()
     }
 
     pub fn handle_backspace(&mut self, ctrl: bool) -> Result<bool, String> {
-        self.game.handle_backspace(ctrl)
+        Ok(self.engine.handle_backspace(ctrl))
     }
 
     pub fn get_total_mistakes(&self) -> u32 {
-        self.game.get_total_mistakes()
+        self.engine.errors() as u32
     }
 
     pub fn get_stats(&self) -> Result<(f64, u32), String> {
-        self.game.get_stats()
+        Ok((self.get_accuracy(), self.get_total_mistakes()))
     }
 
     pub fn get_stats_and_input(&self) -> Result<(String, f64, u32), String> {
-        self.game.get_stats_and_input()
+        Ok((self.get_input(), self.get_accuracy(), self.get_total_mistakes()))
     }
-} 
\ No newline at end of file
+}
+
+impl Default for TyperPunkGame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
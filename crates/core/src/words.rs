@@ -0,0 +1,216 @@
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{adaptive, graphemes};
+
+// Bundled word list used for word-count runs; kept apart from the passage packs
+const ENGLISH_WORDS: &str = include_str!("../../../data/words/english.json");
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WordList {
+    pub name: String,
+    pub words: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WordResult {
+    pub expected: String,
+    pub typed: String,
+    pub correct: bool,
+}
+
+/// Characters of the input compared word by word with the text: typed right,
+/// typed wrong, typed past the end of a word, and left out of a committed word
+/// that was cut short. Spaces are not counted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CharCounts {
+    pub correct: usize,
+    pub incorrect: usize,
+    pub extra: usize,
+    pub missed: usize,
+}
+
+impl WordList {
+    pub fn english() -> Self {
+        Self::from_json(ENGLISH_WORDS).expect("bundled word list is valid JSON")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Builds a test of exactly `count` words, never repeating the same word twice in a row.
+    pub fn generate<R: Rng + ?Sized>(&self, count: usize, rng: &mut R) -> String {
+        if self.words.is_empty() {
+            return String::new();
+        }
+        let mut picked: Vec<&str> = Vec::with_capacity(count);
+        while picked.len() < count {
+            let word = self.words[rng.gen_range(0..self.words.len())].as_str();
+            if self.words.len() > 1 && picked.last() == Some(&word) {
+                continue;
+            }
+            picked.push(word);
+        }
+        picked.join(" ")
+    }
+
+    /// Like [`generate`](Self::generate), but each word is drawn in proportion to
+    /// `weight(word)`. Falls back to uniform picks if no word has a positive weight.
+    pub fn generate_weighted<R, F>(&self, count: usize, weight: F, rng: &mut R) -> String
+    where
+        R: Rng + ?Sized,
+        F: Fn(&str) -> f64,
+    {
+        let weights = adaptive::finite_weights(self.words.iter().map(|w| weight(w)));
+        let Ok(dist) = WeightedIndex::new(&weights) else {
+            return self.generate(count, rng);
+        };
+        let distinct = weights.iter().filter(|w| **w > 0.0).count();
+        let mut picked: Vec<&str> = Vec::with_capacity(count);
+        while picked.len() < count {
+            let word = self.words[dist.sample(rng)].as_str();
+            if distinct > 1 && picked.last() == Some(&word) {
+                continue;
+            }
+            picked.push(word);
+        }
+        picked.join(" ")
+    }
+}
+
+impl Default for WordList {
+    fn default() -> Self {
+        Self::english()
+    }
+}
+
+/// Number of target words the user has committed. A word is committed once it is
+/// followed by whitespace; the final word of the target also counts as soon as it
+/// is typed exactly, so the test ends without a trailing space.
+pub fn committed_words(input: &str, target: &str) -> usize {
+    let input_words: Vec<&str> = input.split_whitespace().collect();
+    let target_words: Vec<&str> = target.split_whitespace().collect();
+    if input_words.is_empty() {
+        return 0;
+    }
+
+    let mut committed = input_words.len();
+    if !input.ends_with(char::is_whitespace) {
+        let last = input_words.len() - 1;
+        let is_final_word = input_words.len() == target_words.len();
+        if !(is_final_word && input_words[last] == target_words[last]) {
+            committed -= 1;
+        }
+    }
+    committed.min(target_words.len())
+}
+
+/// Per-word outcome for every committed word, in order.
+pub fn word_results(input: &str, target: &str) -> Vec<WordResult> {
+    let committed = committed_words(input, target);
+    input
+        .split_whitespace()
+        .zip(target.split_whitespace())
+        .take(committed)
+        .map(|(typed, expected)| WordResult {
+            expected: expected.to_string(),
+            typed: typed.to_string(),
+            correct: typed == expected,
+        })
+        .collect()
+}
+
+/// How the characters of `input` went against `target`. The word being typed
+/// has no missed characters yet.
+pub fn char_counts(input: &str, target: &str) -> CharCounts {
+    let committed = committed_words(input, target);
+    let mut counts = CharCounts::default();
+    for (i, (typed, expected)) in input.split_whitespace().zip(target.split_whitespace()).enumerate() {
+        let typed = graphemes::graphemes(typed);
+        let expected = graphemes::graphemes(expected);
+        for (t, e) in typed.iter().zip(&expected) {
+            if t == e {
+                counts.correct += 1;
+            } else {
+                counts.incorrect += 1;
+            }
+        }
+        counts.extra += typed.len().saturating_sub(expected.len());
+        if i < committed {
+            counts.missed += expected.len().saturating_sub(typed.len());
+        }
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_exact_word_count() {
+        let list = WordList::english();
+        let mut rng = rand::thread_rng();
+        for count in [1, 10, 25, 100] {
+            let text = list.generate(count, &mut rng);
+            assert_eq!(text.split_whitespace().count(), count);
+        }
+        assert_eq!(list.generate(0, &mut rng), "");
+    }
+
+    #[test]
+    fn test_generate_weighted_follows_weights() {
+        let list = WordList::english();
+        let mut rng = rand::thread_rng();
+        let text = list.generate_weighted(20, |w| if w.contains('k') { 1.0 } else { 0.0 }, &mut rng);
+        assert_eq!(text.split_whitespace().count(), 20);
+        assert!(text.split_whitespace().all(|w| w.contains('k')));
+    }
+
+    #[test]
+    fn test_generate_weighted_survives_overflowing_weights() {
+        let list = WordList::english();
+        let mut rng = rand::thread_rng();
+        let text = list.generate_weighted(10, |w| if w.contains('k') { f64::MAX } else { 1.0 }, &mut rng);
+        assert_eq!(text.split_whitespace().count(), 10);
+        let text = list.generate_weighted(10, |_| f64::INFINITY, &mut rng);
+        assert_eq!(text.split_whitespace().count(), 10);
+    }
+
+    #[test]
+    fn test_committed_words() {
+        let target = "one two three";
+        assert_eq!(committed_words("", target), 0);
+        assert_eq!(committed_words("one", target), 0);
+        assert_eq!(committed_words("one ", target), 1);
+        assert_eq!(committed_words("onr two ", target), 2);
+        // The final word commits as soon as it is typed correctly...
+        assert_eq!(committed_words("one two three", target), 3);
+        // ...or when it is followed by a space, even with a typo
+        assert_eq!(committed_words("one two thre", target), 2);
+        assert_eq!(committed_words("one two thre ", target), 3);
+    }
+
+    #[test]
+    fn test_word_results() {
+        let results = word_results("one twp thr", "one two three");
+        assert_eq!(results.len(), 2);
+        assert!(results[0].correct);
+        assert_eq!(results[1], WordResult {
+            expected: "two".to_string(),
+            typed: "twp".to_string(),
+            correct: false,
+        });
+    }
+
+    #[test]
+    fn test_char_counts() {
+        let counts = char_counts("one twpo th thr", "one two three four");
+        assert_eq!(counts, CharCounts { correct: 7, incorrect: 4, extra: 1, missed: 3 });
+        // The word being typed isn't short yet
+        assert_eq!(char_counts("one tw", "one two").missed, 0);
+        assert_eq!(char_counts("caf\u{e9}s", "caf\u{e9}").extra, 1);
+    }
+}
@@ -0,0 +1,243 @@
+use std::path::PathBuf;
+
+use clap::{Args, Parser, Subcommand};
+use typerpunk_core::history::RunQuery;
+use typerpunk_core::replay::MAX_REPLAY_SPEED;
+
+/// A modern typing test for the terminal.
+///
+/// Settings are layered: defaults, then the config file, then TYPERPUNK_*
+/// environment variables, then the flags below.
+#[derive(Debug, Parser)]
+#[command(name = "typerpunk", version, about, long_about = None)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub run: RunArgs,
+
+    /// Settings file to use instead of the one in the user config directory
+    #[arg(long, global = true, value_name = "PATH", env = "TYPERPUNK_CONFIG")]
+    pub config: Option<PathBuf>,
+
+    /// Extra text pack file or directory of packs
+    #[arg(long, global = true, value_name = "PATH")]
+    pub texts: Option<PathBuf>,
+
+    /// Run history file to use instead of the one in the user data directory
+    #[arg(long, global = true, value_name = "PATH", env = "TYPERPUNK_HISTORY")]
+    pub history: Option<PathBuf>,
+}
+
+/// Flags that pick what to type. Giving any of them skips the main menu.
+#[derive(Debug, Args)]
+pub struct RunArgs {
+    /// Test mode
+    #[arg(short, long, value_parser = ["passage", "quote", "code", "time", "words", "zen"])]
+    pub mode: Option<String>,
+
+    /// Duration of a timed test in seconds (implies --mode time)
+    #[arg(short, long, value_name = "SECS")]
+    pub time: Option<u64>,
+
+    /// Number of words in a word-count test (implies --mode words)
+    #[arg(short, long, value_name = "COUNT")]
+    pub words: Option<usize>,
+
+    /// Only pick passages from this category
+    #[arg(short, long, value_name = "NAME")]
+    pub category: Option<String>,
+
+    /// Type the passage in this file instead of a pack, or `-` to read it from stdin
+    #[arg(short, long, value_name = "PATH")]
+    pub file: Option<PathBuf>,
+
+    /// Colour theme
+    #[arg(long, value_name = "NAME")]
+    pub theme: Option<String>,
+
+    /// Seed for passage and word selection, to repeat the same drill
+    #[arg(long, value_name = "N")]
+    pub seed: Option<u64>,
+
+    /// Mark your personal-best pace in the text while typing
+    #[arg(long)]
+    pub ghost: bool,
+
+    /// Race a ghost that retypes this keystroke log (from `replay --export` or the
+    /// replays directory), on the same text and mode
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["mode", "time", "words", "file"])]
+    pub race: Option<PathBuf>,
+
+    /// Favour passages and words that drill your weakest keys
+    #[arg(long)]
+    pub adaptive: bool,
+
+    /// How strongly --adaptive leans on weak keys (0 is uniform, up to 10, default 2)
+    #[arg(long, value_name = "N")]
+    pub adaptive_strength: Option<f64>,
+
+    /// Accept ' " - and ... for curly quotes, dashes and ellipses
+    #[arg(long)]
+    pub ascii_punctuation: bool,
+
+    /// Type passages without their accents, e.g. "cafe" for "café"
+    #[arg(long)]
+    pub strip_diacritics: bool,
+
+    /// In code mode, type each line's indentation yourself after Enter
+    #[arg(long)]
+    pub no_auto_indent: bool,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Inspect the text packs the TUI would load
+    Packs {
+        #[command(subcommand)]
+        command: PacksCommand,
+    },
+    /// Show a summary of saved runs
+    Stats {
+        #[command(flatten)]
+        filter: RunFilter,
+    },
+    /// List saved runs, newest first
+    History {
+        #[command(flatten)]
+        filter: RunFilter,
+
+        /// Number of runs to show
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+
+        /// Print the matching runs as JSON lines
+        #[arg(long)]
+        json: bool,
+    },
+    /// Play back the keystrokes of a saved run
+    Replay {
+        /// Which run, counting from 1 for the newest as numbered by `history`
+        #[arg(default_value_t = 1, conflicts_with = "file")]
+        run: usize,
+
+        #[command(flatten)]
+        filter: RunFilter,
+
+        /// Keystroke log file to play instead of a saved run
+        #[arg(long, value_name = "PATH")]
+        file: Option<PathBuf>,
+
+        /// Playback speed, e.g. 2 for twice as fast (up to 16)
+        #[arg(long, default_value_t = 1.0, value_name = "X", value_parser = parse_speed)]
+        speed: f64,
+
+        /// Write the run's keystroke log to this file instead of playing it, to
+        /// race it elsewhere with `--race`
+        #[arg(long, value_name = "PATH")]
+        export: Option<PathBuf>,
+    },
+    /// Run a race server that players join with a room code
+    #[cfg(feature = "multiplayer")]
+    Server {
+        /// Address to listen on
+        #[arg(long, default_value = "0.0.0.0:7878", value_name = "HOST:PORT")]
+        addr: String,
+    },
+}
+
+/// Which saved runs `stats`, `history` and `replay` look at.
+#[derive(Debug, Args)]
+pub struct RunFilter {
+    /// Only runs in this mode
+    #[arg(short, long, value_parser = ["passage", "code", "time", "words", "zen"])]
+    pub mode: Option<String>,
+
+    /// Only runs on passages from this category
+    #[arg(short, long, value_name = "NAME")]
+    pub category: Option<String>,
+}
+
+impl RunFilter {
+    pub fn query(&self) -> RunQuery {
+        RunQuery {
+            mode: self.mode.clone(),
+            category: self.category.clone(),
+            ..RunQuery::default()
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PacksCommand {
+    /// List every pack with its text count and categories
+    List,
+}
+
+impl Cli {
+    /// Config keys set on the command line, as `(flag, key, value)` for `Config::set`.
+    pub fn config_overrides(&self) -> Vec<(&'static str, &'static str, String)> {
+        let mut overrides = Vec::new();
+        let run = &self.run;
+        if let Some(theme) = &run.theme {
+            overrides.push(("--theme", "theme", theme.clone()));
+        }
+        if let Some(mode) = &run.mode {
+            overrides.push(("--mode", "mode", mode.clone()));
+        }
+        if let Some(time) = run.time {
+            overrides.push(("--time", "time", time.to_string()));
+            if run.mode.is_none() {
+                overrides.push(("--time", "mode", "time".to_string()));
+            }
+        }
+        if let Some(words) = run.words {
+            overrides.push(("--words", "words", words.to_string()));
+            if run.mode.is_none() && run.time.is_none() {
+                overrides.push(("--words", "mode", "words".to_string()));
+            }
+        }
+        if run.ghost {
+            overrides.push(("--ghost", "ghost", "true".to_string()));
+        }
+        if run.adaptive {
+            overrides.push(("--adaptive", "adaptive", "true".to_string()));
+        }
+        if let Some(strength) = run.adaptive_strength {
+            overrides.push(("--adaptive-strength", "adaptive_strength", strength.to_string()));
+        }
+        if run.ascii_punctuation {
+            overrides.push(("--ascii-punctuation", "normalize.ascii_punctuation", "true".to_string()));
+        }
+        if run.strip_diacritics {
+            overrides.push(("--strip-diacritics", "normalize.strip_diacritics", "true".to_string()));
+        }
+        if run.no_auto_indent {
+            overrides.push(("--no-auto-indent", "auto_indent", "false".to_string()));
+        }
+        if let Some(texts) = &self.texts {
+            overrides.push(("--texts", "texts_path", texts.display().to_string()));
+        }
+        overrides
+    }
+
+    /// Whether the flags describe a specific drill, so the menu can be skipped.
+    pub fn starts_run(&self) -> bool {
+        let run = &self.run;
+        run.mode.is_some()
+            || run.time.is_some()
+            || run.words.is_some()
+            || run.category.is_some()
+            || run.file.is_some()
+    }
+}
+
+// Replay speeds above zero and no faster than the player allows
+fn parse_speed(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(speed) if speed > 0.0 && speed <= MAX_REPLAY_SPEED => Ok(speed),
+        Ok(_) => Err(format!("must be above 0 and at most {}", MAX_REPLAY_SPEED)),
+        Err(e) => Err(e.to_string()),
+    }
+}
@@ -7,14 +7,130 @@ use ratatui::{
     backend::CrosstermBackend,
     Terminal,
 };
-use std::{io, error::Error as StdError};
+use clap::Parser;
+use std::{
+    fs,
+    io::{self, Read},
+    error::Error as StdError,
+    path::Path,
+};
 use typerpunk_core::{
     app::App,
+    config::Config,
+    game::GameMode,
+    history::History,
     input::InputHandler,
+    packs::TextLibrary,
+    replay::KeystrokeLog,
+    text::{Text, CODE_CATEGORY},
     ui::draw,
 };
 
-fn main() -> Result<(), Box<dyn StdError>> {
+mod cli;
+mod report;
+
+use cli::{Cli, Command, PacksCommand};
+
+fn main() {
+    // Report startup errors (bad flags, invalid settings) in readable form
+    if let Err(e) = run() {
+        eprintln!("typerpunk: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn StdError>> {
+    let cli = Cli::parse();
+
+    // Settings are layered: defaults, then the config file, then TYPERPUNK_*
+    // environment variables, then command-line flags. Only the file layer is saved.
+    let config_path = cli.config.clone().or_else(Config::default_path);
+    let mut file_config = match &config_path {
+        Some(path) => Config::load_or_default(path)?,
+        None => Config::new(),
+    };
+    let mut config = file_config.clone();
+    config.apply_env(std::env::vars())?;
+    for (flag, key, value) in cli.config_overrides() {
+        config.set(flag, key, &value)?;
+    }
+    let start_mode = config.game_mode();
+    let start_adaptive = config.adaptive;
+
+    // Load text packs before touching the terminal so a bad path fails cleanly
+    let library = match &cli.run.file {
+        Some(path) => custom_library(path, start_mode == GameMode::Programming)?,
+        None => TextLibrary::load(config.texts_path.as_deref()),
+    };
+
+    // Commands that never look at the history run before it is opened, so an
+    // unreadable history file doesn't stop them
+    match &cli.command {
+        Some(Command::Packs { command: PacksCommand::List }) => {
+            report::list_packs(&library);
+            return Ok(());
+        }
+        #[cfg(feature = "multiplayer")]
+        Some(Command::Server { addr }) => return serve(addr),
+        _ => {}
+    }
+
+    let history_path = cli.history.clone().or_else(History::default_path);
+    let history = match &history_path {
+        Some(path) => History::open(path)?,
+        None => History::new(),
+    };
+    if history.skipped > 0 {
+        eprintln!("warning: skipped {} unreadable runs in the history file", history.skipped);
+    }
+
+    let mut replay = None;
+    match &cli.command {
+        Some(Command::Stats { filter }) => {
+            report::print_stats(&history, &filter.query());
+            return Ok(());
+        }
+        Some(Command::History { filter, limit, json }) => {
+            report::print_history(&history, &filter.query().limit(*limit), *json)?;
+            return Ok(());
+        }
+        Some(Command::Replay { run, filter, file, speed, export }) => {
+            let log = match file {
+                Some(path) => KeystrokeLog::load(path)?,
+                None => {
+                    let runs = history.query(&filter.query());
+                    let record = run
+                        .checked_sub(1)
+                        .and_then(|i| runs.get(i))
+                        .ok_or_else(|| format!("no saved run number {} (see `typerpunk history`)", run))?;
+                    history.load_replay(record)?
+                }
+            };
+            if let Some(path) = export {
+                log.save(path)?;
+                return Ok(());
+            }
+            replay = Some((log, *speed));
+        }
+        _ => {}
+    }
+
+    let mut app = App::with_library(config, library).map_err(|e| e as Box<dyn StdError>)?;
+    app.history = Some(history);
+    if let Some(seed) = cli.run.seed {
+        app.seed(seed);
+    }
+    if let Some(category) = &cli.run.category {
+        app.select_category(category)?;
+    }
+    if let Some((log, speed)) = replay {
+        app.open_replay(log, speed);
+    } else if let Some(path) = &cli.run.race {
+        app.race(KeystrokeLog::load(path)?);
+    } else if cli.starts_run() {
+        app.start_run();
+    }
+
     // Setup terminal
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -22,15 +138,6 @@ fn main() -> Result<(), Box<dyn StdError>> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
-    // Create app and run it
-    let app = match App::new() {
-        Ok(app) => app,
-        Err(e) => {
-            cleanup_terminal(&mut terminal)?;
-            return Err(e);
-        }
-    };
-
     let mut input_handler = InputHandler::new(app);
     let res = run_app(&mut terminal, &mut input_handler);
 
@@ -41,9 +148,80 @@ fn main() -> Result<(), Box<dyn StdError>> {
         println!("Error: {:?}", err);
     }
 
+    // Skipped pack entries are listed once the alternate screen is gone
+    for warning in &input_handler.app.text_warnings {
+        eprintln!("warning: {}", warning);
+    }
+    for error in &input_handler.app.history_errors {
+        eprintln!("warning: could not save run: {}", error);
+    }
+
+    // Remember a mode or adaptive toggle picked in the menu, and write a starter
+    // file on first run. A race's mode comes from the raced run, not a choice.
+    if let Some(path) = &config_path {
+        let mode = if cli.run.race.is_some() { start_mode } else { input_handler.app.mode };
+        let adaptive = input_handler.app.config.adaptive;
+        if mode != start_mode || adaptive != start_adaptive || !path.exists() {
+            if mode != start_mode {
+                file_config.set_game_mode(mode);
+            }
+            if adaptive != start_adaptive {
+                file_config.adaptive = adaptive;
+            }
+            if let Err(e) = file_config.save(path) {
+                eprintln!("warning: could not save settings: {}", e);
+            }
+        }
+    }
+
     Ok(())
 }
 
+// Runs the race server in the foreground until it fails or is interrupted
+#[cfg(feature = "multiplayer")]
+fn serve(addr: &str) -> Result<(), Box<dyn StdError>> {
+    use typerpunk_core::multiplayer::RaceServer;
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime
+        .block_on(async {
+            let server = RaceServer::bind(addr).await?;
+            println!("race server listening on ws://{}", server.local_addr()?);
+            server.run().await
+        })
+        .map_err(|e| e as Box<dyn StdError>)
+}
+
+// A library of just the text in `path`. Code keeps its lines and indentation,
+// with the file extension as its language; prose is typed as one paragraph.
+fn custom_library(path: &Path, code: bool) -> Result<TextLibrary, Box<dyn StdError>> {
+    let (raw, source) = if path == Path::new("-") {
+        let mut raw = String::new();
+        io::stdin().read_to_string(&mut raw)?;
+        (raw, "stdin".to_string())
+    } else {
+        let raw = fs::read_to_string(path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        (raw, path.display().to_string())
+    };
+    let content = if code {
+        raw.lines().map(str::trim_end).collect::<Vec<_>>().join("\n").trim_matches('\n').to_string()
+    } else {
+        raw.split_whitespace().collect::<Vec<_>>().join(" ")
+    };
+    if content.trim().is_empty() {
+        return Err(format!("{}: no text to type", source).into());
+    }
+    let text = if code {
+        let language = path.extension().and_then(|e| e.to_str()).unwrap_or("code");
+        Text::from_all(&content, &source, language, CODE_CATEGORY)
+    } else {
+        Text::from_all(&content, &source, "en", "custom")
+    };
+    let mut library = TextLibrary::new();
+    library.add_text(text);
+    Ok(library)
+}
+
 fn cleanup_terminal<B: ratatui::backend::Backend + std::io::Write>(terminal: &mut Terminal<B>) -> io::Result<()> {
     disable_raw_mode()?;
     execute!(
@@ -0,0 +1,121 @@
+use chrono::Local;
+use typerpunk_core::{
+    adaptive::AdaptiveWeights,
+    game::GameMode,
+    history::{History, PbKey, RunQuery, RunRecord, Summary},
+    packs::TextLibrary,
+};
+
+// Plain-text output for the subcommands that run without the TUI
+
+pub fn list_packs(library: &TextLibrary) {
+    for pack in &library.packs {
+        let categories = pack.categories.iter().cloned().collect::<Vec<_>>().join(", ");
+        let mut line = format!("{}  {} texts", pack.origin, pack.texts);
+        if pack.duplicates > 0 {
+            line.push_str(&format!(", {} duplicates", pack.duplicates));
+        }
+        if pack.skipped > 0 {
+            line.push_str(&format!(", {} skipped", pack.skipped));
+        }
+        if !categories.is_empty() {
+            line.push_str(&format!("  [{}]", categories));
+        }
+        println!("{}", line);
+    }
+    println!("{} texts in total", library.texts.len());
+    for warning in &library.warnings {
+        eprintln!("warning: {}", warning);
+    }
+}
+
+pub fn print_history(history: &History, query: &RunQuery, json: bool) -> serde_json::Result<()> {
+    let runs = history.query(query);
+    if json {
+        for run in runs {
+            println!("{}", serde_json::to_string(run)?);
+        }
+        return Ok(());
+    }
+    if runs.is_empty() {
+        println!("No runs saved yet.");
+        return Ok(());
+    }
+    for (i, run) in runs.iter().enumerate() {
+        println!("{:>3}  {}", i + 1, history_line(run));
+    }
+    Ok(())
+}
+
+pub fn print_stats(history: &History, query: &RunQuery) {
+    let Some(all) = history.summary(query) else {
+        println!("No runs saved yet.");
+        return;
+    };
+    print_summary("All runs", &all);
+    if all.runs > 10 {
+        if let Some(recent) = history.summary(&RunQuery { limit: Some(10), ..query.clone() }) {
+            println!();
+            print_summary("Last 10 runs", &recent);
+        }
+    }
+
+    let bests: Vec<(PbKey, &RunRecord)> = history
+        .personal_bests()
+        .into_iter()
+        .filter(|(_, run)| query.matches(run))
+        .collect();
+    println!();
+    println!("Personal bests");
+    for (key, run) in bests {
+        let when = run.timestamp.with_timezone(&Local).format("%Y-%m-%d");
+        println!(
+            "  {:<12} {:<14} {:>6.1} wpm  {:>5.1}%  {}",
+            key.mode,
+            key.category.as_deref().unwrap_or("-"),
+            run.wpm,
+            run.accuracy,
+            when
+        );
+    }
+
+    let weights = AdaptiveWeights::from_history(history);
+    let targets = weights.targets(10);
+    if !targets.is_empty() {
+        println!();
+        println!("Adaptive practice targets (weight above your average)");
+        for (key, weight) in targets {
+            println!("  {:<4} {:.2}", format!("{:?}", key), weight);
+        }
+    }
+}
+
+fn print_summary(title: &str, summary: &Summary) {
+    let secs = summary.total_time.as_secs();
+    println!("{} ({})", title, summary.runs);
+    println!("  best     {:.1} wpm", summary.best_wpm);
+    println!("  average  {:.1} wpm, raw {:.1}", summary.average_wpm, summary.average_raw_wpm);
+    println!("  accuracy {:.1}%", summary.average_accuracy);
+    println!("  typing   {}h {:02}m {:02}s", secs / 3600, secs / 60 % 60, secs % 60);
+}
+
+fn history_line(run: &RunRecord) -> String {
+    let when = run.timestamp.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+    let mode = match run.mode {
+        GameMode::Time(secs) => format!("time {}s", secs),
+        GameMode::Words(count) => format!("words {}", count),
+        _ => run.mode_name().to_string(),
+    };
+    let mut line = format!(
+        "{}  {:<9} {:>6.1} wpm  raw {:>6.1}  {:>5.1}%  {:>3} errors  {:>6.1}s",
+        when, mode, run.wpm, run.raw_wpm, run.accuracy, run.errors, run.duration_secs
+    );
+    if let Some(category) = &run.category {
+        line.push_str("  ");
+        line.push_str(category);
+    }
+    if run.replay.is_some() {
+        line.push_str("  [replay]");
+    }
+    line
+}
@@ -1,12 +1,19 @@
+use serde::Serialize;
 use wasm_bindgen::prelude::*;
-use typerpunk_core::game::Game;
+use typerpunk_core::engine::Engine;
+use typerpunk_core::game::GameMode;
+use typerpunk_core::normalize::Normalization;
+use typerpunk_core::words::WordList;
+
+mod race;
 
 // Re-export TyperPunkGame as TyperPunk
 pub use typerpunk_core::wasm::TyperPunkGame as TyperPunk;
+pub use race::WebRaceClient;
 
 #[wasm_bindgen]
 pub struct TyperPunkGame {
-    game: Option<Game>,
+    game: Option<Engine>,
 }
 
 #[wasm_bindgen]
@@ -14,7 +21,7 @@ impl TyperPunkGame {
     #[wasm_bindgen(constructor)]
     pub fn new() -> Self {
         Self {
-            game: Some(Game::new()),
+            game: Some(Engine::default()),
         }
     }
 
@@ -29,21 +36,135 @@ impl TyperPunkGame {
             Err(_) => return Err(JsValue::from_str("Invalid UTF-8 text")),
         };
 
-        game.set_text(text_str);
+        // A text to type ends free typing
+        if game.mode() == GameMode::Zen {
+            game.set_mode(GameMode::Normal);
+        }
+        game.set_text(&text_str);
+        Ok(())
+    }
+
+    /// Switches to a word-count run and fills the text with `count` generated words.
+    #[wasm_bindgen]
+    pub fn set_words_mode(&mut self, count: usize) -> Result<(), JsValue> {
+        let game = self.game.as_mut()
+            .ok_or_else(|| JsValue::from_str("Game not initialized"))?;
+
+        let text = WordList::english().generate(count, &mut rand::thread_rng());
+        *game = Engine::with_normalization(GameMode::Words(count), &text, game.normalization());
         Ok(())
     }
 
+    /// Switches to a zen run: no text, anything typed counts, and the run goes
+    /// on until `finish` is called.
+    #[wasm_bindgen]
+    pub fn set_zen_mode(&mut self) -> Result<(), JsValue> {
+        let game = self.game.as_mut()
+            .ok_or_else(|| JsValue::from_str("Game not initialized"))?;
+
+        *game = Engine::with_normalization(GameMode::Zen, "", game.normalization());
+        Ok(())
+    }
+
+    /// Ends a zen run, or any run early, stopping its clock.
+    #[wasm_bindgen]
+    pub fn finish(&mut self) {
+        if let Some(game) = &mut self.game {
+            game.stop();
+        }
+    }
+
+    /// Chooses which characters count as typed right, and restarts the run on
+    /// the same text. Set it before `set_text` to strip accents from a passage.
+    #[wasm_bindgen]
+    pub fn set_normalization(&mut self, unicode: bool, ascii_punctuation: bool, strip_diacritics: bool) -> Result<(), JsValue> {
+        let game = self.game.as_mut()
+            .ok_or_else(|| JsValue::from_str("Game not initialized"))?;
+
+        game.set_normalization(Normalization { unicode, ascii_punctuation, strip_diacritics });
+        Ok(())
+    }
+
+    /// Words typed so far, right or wrong; the word count of a zen run.
+    #[wasm_bindgen]
+    pub fn get_word_count(&self) -> usize {
+        self.game.as_ref()
+            .map(|game| game.word_count())
+            .unwrap_or(0)
+    }
+
+    #[wasm_bindgen]
+    pub fn get_words_committed(&self) -> usize {
+        self.game.as_ref()
+            .map(|game| game.words_committed())
+            .unwrap_or(0)
+    }
+
+    /// Per-word results as an array of `{ expected, typed, correct }` objects.
+    #[wasm_bindgen]
+    pub fn get_word_results(&self) -> Result<JsValue, JsValue> {
+        let game = self.game.as_ref()
+            .ok_or_else(|| JsValue::from_str("Game not initialized"))?;
+
+        serde_wasm_bindgen::to_value(&game.word_results())
+            .map_err(|e| JsValue::from_str(&e.to_string()))
+    }
+
+    /// Per-key and per-bigram results of the current run, as
+    /// `{ keys: { [char]: KeyStat }, bigrams: { [pair]: KeyStat } }` where each
+    /// `KeyStat` is `{ hits, misses, latency_ms, timed }`.
+    #[wasm_bindgen]
+    pub fn get_key_stats(&self) -> Result<JsValue, JsValue> {
+        let game = self.game.as_ref()
+            .ok_or_else(|| JsValue::from_str("Game not initialized"))?;
+
+        to_plain_value(game.key_stats())
+    }
+
+    /// Every keystroke of the current run, as `{ mode, target, events }` where each
+    /// event is `{ at_ms, kind, ... }` with `kind` one of `"insert"` (with `ch`),
+    /// `"backspace"` or `"delete_word"` (with `removed`). Loads into the TUI with
+    /// `typerpunk replay --file`.
+    #[wasm_bindgen]
+    pub fn get_keystroke_log(&self) -> Result<JsValue, JsValue> {
+        let game = self.game.as_ref()
+            .ok_or_else(|| JsValue::from_str("Game not initialized"))?;
+
+        to_plain_value(game.keystroke_log())
+    }
+
+    /// Up to `limit` keys with at least `min_attempts` attempts, least accurate
+    /// first, as `{ key, accuracy, mean_latency_ms, attempts }` objects.
+    #[wasm_bindgen]
+    pub fn get_weakest_keys(&self, min_attempts: u32, limit: usize) -> Result<JsValue, JsValue> {
+        let game = self.game.as_ref()
+            .ok_or_else(|| JsValue::from_str("Game not initialized"))?;
+
+        let weakest: Vec<WeakKey> = game.key_stats()
+            .weakest_keys(min_attempts)
+            .into_iter()
+            .take(limit)
+            .map(|(key, stat)| WeakKey {
+                key: key.to_string(),
+                accuracy: stat.accuracy(),
+                mean_latency_ms: stat.mean_latency_ms(),
+                attempts: stat.attempts(),
+            })
+            .collect();
+        to_plain_value(&weakest)
+    }
+
     #[wasm_bindgen]
     pub fn get_text(&self) -> String {
         self.game.as_ref()
-            .map(|game| game.get_text())
+            .map(|game| game.text().to_string())
             .unwrap_or_default()
     }
 
     #[wasm_bindgen]
     pub fn get_input(&self) -> String {
         self.game.as_ref()
-            .map(|game| game.get_input())
+            .map(|game| game.input().to_string())
             .unwrap_or_default()
     }
 
@@ -52,15 +173,10 @@ impl TyperPunkGame {
         let game = self.game.as_mut()
             .ok_or_else(|| JsValue::from_str("Game not initialized"))?;
 
-        // Create a new owned string and validate UTF-8
-        let input_str = match std::str::from_utf8(input.as_bytes()) {
-            Ok(s) => s.to_string(),
-            Err(_) => return Err(JsValue::from_str("Invalid UTF-8 input")),
-        };
-        
-        // Process input
-        game.handle_input(&input_str)
-            .map_err(|e| JsValue::from_str(&e.to_string()))
+        // Deletions the backspace rules refuse leave the input as it was, and
+        // accepted ASCII stand-ins are replaced; read it back with get_input
+        game.set_input(input);
+        Ok(())
     }
 
     #[wasm_bindgen]
@@ -68,18 +184,7 @@ impl TyperPunkGame {
         let game = self.game.as_mut()
             .ok_or_else(|| JsValue::from_str("Game not initialized"))?;
 
-        if !game.can_backspace() {
-            return Ok(false);
-        }
-
-        // Check if ctrl+backspace is allowed
-        if ctrl && !game.can_ctrl_backspace() {
-            return Ok(false);
-        }
-
-        // Perform backspace
-        game.handle_backspace(ctrl)
-            .map_err(|e| JsValue::from_str(&e.to_string()))
+        Ok(game.handle_backspace(ctrl))
     }
 
     #[wasm_bindgen]
@@ -87,11 +192,9 @@ impl TyperPunkGame {
         let game = self.game.as_ref()
             .ok_or_else(|| JsValue::from_str("Game not initialized"))?;
 
-        let (accuracy, mistakes) = game.get_stats()
-            .map_err(|e| JsValue::from_str(&e.to_string()))?;
         let array = js_sys::Array::new();
-        array.push(&JsValue::from_f64(accuracy));
-        array.push(&JsValue::from_f64(mistakes as f64));
+        array.push(&JsValue::from_f64(game.accuracy()));
+        array.push(&JsValue::from_f64(game.errors() as f64));
         Ok(array.into())
     }
 
@@ -100,31 +203,17 @@ impl TyperPunkGame {
         let game = self.game.as_ref()
             .ok_or_else(|| JsValue::from_str("Game not initialized"))?;
 
-        // Get input first to avoid recursive use
-        let input = game.get_input();
-        
-        // Then get stats
-        let (_, accuracy, mistakes) = game.get_stats_and_input()
-            .map_err(|e| JsValue::from_str(&e.to_string()))?;
-        
         let array = js_sys::Array::new();
-        array.push(&JsValue::from_str(&input));
-        array.push(&JsValue::from_f64(accuracy));
-        array.push(&JsValue::from_f64(mistakes as f64));
+        array.push(&JsValue::from_str(game.input()));
+        array.push(&JsValue::from_f64(game.accuracy()));
+        array.push(&JsValue::from_f64(game.errors() as f64));
         Ok(array.into())
     }
 
     #[wasm_bindgen]
     pub fn is_finished(&self) -> bool {
         self.game.as_ref()
-            .map(|game| {
-                let input = game.get_input();
-                let text = game.get_text();
-                // Use the same logic as the core game
-                !input.is_empty() && 
-                input.len() >= text.len() && 
-                input.trim() == text.trim()
-            })
+            .map(|game| game.is_finished())
             .unwrap_or(false)
     }
 
@@ -135,17 +224,51 @@ impl TyperPunkGame {
         }
     }
 
+    /// Net WPM, as defined in `typerpunk_core::metrics`.
     #[wasm_bindgen]
     pub fn get_wpm(&self) -> f64 {
         self.game.as_ref()
-            .map(|game| game.get_wpm())
+            .map(|game| game.wpm())
+            .unwrap_or(0.0)
+    }
+
+    #[wasm_bindgen]
+    pub fn get_raw_wpm(&self) -> f64 {
+        self.game.as_ref()
+            .map(|game| game.raw_wpm())
             .unwrap_or(0.0)
     }
 
+    #[wasm_bindgen]
+    pub fn get_accuracy(&self) -> f64 {
+        self.game.as_ref()
+            .map(|game| game.accuracy())
+            .unwrap_or(0.0)
+    }
+
+    /// Every figure of the run so far, as `{ net_wpm, raw_wpm, cpm, accuracy,
+    /// consistency, errors, elapsed }` with `elapsed` in seconds.
+    #[wasm_bindgen]
+    pub fn get_metrics(&self) -> Result<JsValue, JsValue> {
+        let game = self.game.as_ref()
+            .ok_or_else(|| JsValue::from_str("Game not initialized"))?;
+
+        to_plain_value(&game.metrics())
+    }
+
+    /// Each second of the run so far, as `{ raw_wpm, net_wpm, errors }` objects.
+    #[wasm_bindgen]
+    pub fn get_timeline(&self) -> Result<JsValue, JsValue> {
+        let game = self.game.as_ref()
+            .ok_or_else(|| JsValue::from_str("Game not initialized"))?;
+
+        to_plain_value(&game.timeline())
+    }
+
     #[wasm_bindgen]
     pub fn get_time_elapsed(&self) -> f64 {
         self.game.as_ref()
-            .map(|game| game.get_time_elapsed())
+            .map(|game| game.elapsed().as_secs_f64())
             .unwrap_or(0.0)
     }
 
@@ -154,59 +277,45 @@ impl TyperPunkGame {
         self.game = None;
     }
 
+    /// Whether backspace could delete back to grapheme cluster `position`:
+    /// anywhere in the current word, and into earlier words only past a mistake.
     #[wasm_bindgen]
     pub fn can_backspace_to_position(&self, position: usize) -> bool {
         self.game.as_ref()
-            .map(|game| {
-                // This is a simplified check - the actual logic is in the core game
-                // For now, we'll allow backspace if there are any errors before this position
-                let input = game.get_input();
-                let text = game.get_text();
-                
-                if position >= input.len() {
-                    return false;
-                }
-                
-                // Check if there are any errors before this position
-                let check_len = position.min(text.len()).min(input.len());
-                for i in 0..check_len {
-                    let input_char = input.chars().nth(i);
-                    let text_char = text.chars().nth(i);
-                    if input_char != text_char {
-                        return true; // Found an error, allow backspace
-                    }
-                }
-                
-                false
-            })
+            .map(|game| game.can_backspace_to(position))
             .unwrap_or(false)
     }
 
     #[wasm_bindgen]
     pub fn get_current_word_start(&self) -> usize {
         self.game.as_ref()
-            .map(|game| {
-                let input = game.get_input();
-                let mut word_start = 0;
-                let mut in_word = false;
-                
-                for (i, c) in input.chars().enumerate() {
-                    if c.is_whitespace() {
-                        if in_word {
-                            word_start = i + 1;
-                        }
-                        in_word = false;
-                    } else {
-                        in_word = true;
-                    }
-                }
-                
-                word_start
-            })
+            .map(|game| game.current_word_start())
             .unwrap_or(0)
     }
 }
 
+impl Default for TyperPunkGame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Serialize)]
+struct WeakKey {
+    key: String,
+    accuracy: f64,
+    mean_latency_ms: Option<f64>,
+    attempts: u32,
+}
+
+// Maps become plain JS objects rather than `Map`s, so the data can go straight
+// into JSON or a chart library
+fn to_plain_value<T: Serialize + ?Sized>(value: &T) -> Result<JsValue, JsValue> {
+    value
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
 #[wasm_bindgen]
 pub fn init() {
     console_error_panic_hook::set_once();
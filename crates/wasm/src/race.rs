@@ -0,0 +1,286 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use js_sys::Function;
+use serde::Serialize;
+use typerpunk_core::anticheat::Flag;
+use typerpunk_core::protocol::{MultiplayerMessage, PlayerId, Standing};
+use typerpunk_core::replay::KeystrokeLog;
+use typerpunk_core::room::RoomView;
+use wasm_bindgen::prelude::*;
+use web_sys::{CloseEvent, MessageEvent, WebSocket};
+
+use crate::to_plain_value;
+
+/// Name this client gives the server in its hello.
+const CLIENT_NAME: &str = concat!("typerpunk-web/", env!("CARGO_PKG_VERSION"));
+
+/// A race client over a browser WebSocket, speaking the same protocol as the
+/// native race server so web and terminal players can share a room.
+///
+/// Calls made before the server has accepted the hello are queued and sent once
+/// it has, so a room can be joined straight after construction:
+///
+/// ```js
+/// const race = new RaceClient("ws://127.0.0.1:7878");
+/// race.on_room(room => render(room));
+/// race.on_message(message => { if (message.type === "go") game.start(); });
+/// race.join_room("ABCD", "ann");
+/// ```
+#[wasm_bindgen(js_name = RaceClient)]
+pub struct WebRaceClient {
+    shared: Rc<RefCell<Shared>>,
+    // Kept alive for as long as the socket can call them
+    _on_open: Closure<dyn FnMut()>,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_close: Closure<dyn FnMut(CloseEvent)>,
+}
+
+struct Shared {
+    socket: WebSocket,
+    welcomed: bool,
+    // Messages waiting for the welcome
+    pending: Vec<MultiplayerMessage>,
+    room: Option<RoomView>,
+    on_message: Option<Function>,
+    on_room: Option<Function>,
+    on_close: Option<Function>,
+}
+
+impl Shared {
+    fn send(&mut self, message: MultiplayerMessage) -> Result<(), JsValue> {
+        if !self.welcomed {
+            self.pending.push(message);
+            return Ok(());
+        }
+        self.socket.send_with_str(&message.to_json())
+    }
+}
+
+#[wasm_bindgen(js_class = RaceClient)]
+impl WebRaceClient {
+    /// Connects to the race server at `url`, e.g. `ws://127.0.0.1:7878`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(url: &str) -> Result<WebRaceClient, JsValue> {
+        let socket = WebSocket::new(url)?;
+        let shared = Rc::new(RefCell::new(Shared {
+            socket: socket.clone(),
+            welcomed: false,
+            pending: Vec::new(),
+            room: None,
+            on_message: None,
+            on_room: None,
+            on_close: None,
+        }));
+
+        let on_open = {
+            let socket = socket.clone();
+            Closure::<dyn FnMut()>::new(move || {
+                let _ = socket.send_with_str(&MultiplayerMessage::hello(CLIENT_NAME).to_json());
+            })
+        };
+        let on_message = {
+            let shared = Rc::clone(&shared);
+            Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+                if let Some(text) = event.data().as_string() {
+                    receive(&shared, &text);
+                }
+            })
+        };
+        let on_close = {
+            let shared = Rc::clone(&shared);
+            Closure::<dyn FnMut(CloseEvent)>::new(move |event: CloseEvent| {
+                let callback = {
+                    let mut shared = shared.borrow_mut();
+                    shared.room = None;
+                    shared.on_close.clone()
+                };
+                if let Some(callback) = callback {
+                    let _ = callback.call1(&JsValue::NULL, &JsValue::from_str(&event.reason()));
+                }
+            })
+        };
+        socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+        socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+
+        Ok(Self { shared, _on_open: on_open, _on_message: on_message, _on_close: on_close })
+    }
+
+    /// Calls `callback` with every message from the server, as the plain object
+    /// sent on the wire, e.g. `{ type: "countdown", start_in_ms: 3000 }`.
+    pub fn on_message(&self, callback: Function) {
+        self.shared.borrow_mut().on_message = Some(callback);
+    }
+
+    /// Calls `callback` with the room, as `{ code, you, host, players, text,
+    /// standings, notice }`, whenever a message changes it. Each player is
+    /// `{ id, name, ready, progress, wpm, place, time, flags, disqualified }`, with
+    /// `place` and `time` set once they finish and `disqualified` the reason their
+    /// run was rejected.
+    pub fn on_room(&self, callback: Function) {
+        self.shared.borrow_mut().on_room = Some(callback);
+    }
+
+    /// Calls `callback` with the reason once the connection is closed.
+    pub fn on_close(&self, callback: Function) {
+        self.shared.borrow_mut().on_close = Some(callback);
+    }
+
+    /// The room as passed to `on_room`, or `null` outside a room.
+    pub fn room(&self) -> Result<JsValue, JsValue> {
+        match &self.shared.borrow().room {
+            Some(room) => to_plain_value(&RoomSnapshot::from(room)),
+            None => Ok(JsValue::NULL),
+        }
+    }
+
+    pub fn create_room(&self, name: &str) -> Result<(), JsValue> {
+        self.send(MultiplayerMessage::CreateRoom { name: name.to_string() })
+    }
+
+    pub fn join_room(&self, room: &str, name: &str) -> Result<(), JsValue> {
+        self.send(MultiplayerMessage::JoinRoom { room: room.to_string(), name: name.to_string() })
+    }
+
+    pub fn leave_room(&self) -> Result<(), JsValue> {
+        self.send(MultiplayerMessage::LeaveRoom)?;
+        self.shared.borrow_mut().room = None;
+        Ok(())
+    }
+
+    pub fn set_ready(&self, ready: bool) -> Result<(), JsValue> {
+        self.send(MultiplayerMessage::Ready { ready })
+    }
+
+    /// Starts a race on `text`; only the host may, once everyone else is ready.
+    pub fn start_race(&self, text: &str) -> Result<(), JsValue> {
+        self.send(MultiplayerMessage::StartRace { text: text.to_string() })
+    }
+
+    /// Reports your progress through the text, as a percentage, and current WPM.
+    pub fn send_progress(&self, progress: f32, wpm: f32) -> Result<(), JsValue> {
+        if let Some(room) = &mut self.shared.borrow_mut().room {
+            room.update_me(progress, wpm);
+        }
+        self.send(MultiplayerMessage::Progress { progress, wpm })
+    }
+
+    /// Reports reaching the end of the text with the run's keystrokes, as
+    /// returned by `get_keystroke_log()`; the server works out the result from them.
+    pub fn send_finish(&self, log: JsValue) -> Result<(), JsValue> {
+        let log: KeystrokeLog = serde_wasm_bindgen::from_value(log)?;
+        self.send(MultiplayerMessage::Finish { events: log.events })
+    }
+
+    pub fn close(&self) -> Result<(), JsValue> {
+        self.shared.borrow().socket.close()
+    }
+
+    fn send(&self, message: MultiplayerMessage) -> Result<(), JsValue> {
+        self.shared.borrow_mut().send(message)
+    }
+}
+
+impl Drop for WebRaceClient {
+    fn drop(&mut self) {
+        let shared = self.shared.borrow();
+        shared.socket.set_onopen(None);
+        shared.socket.set_onmessage(None);
+        shared.socket.set_onclose(None);
+        let _ = shared.socket.close();
+    }
+}
+
+// Handles one frame from the server. Callbacks run after the state is released,
+// since they may well call back into the client.
+fn receive(shared: &Rc<RefCell<Shared>>, text: &str) {
+    let message = MultiplayerMessage::from_json(text).unwrap_or_else(|refusal| refusal);
+    let (on_message, on_room, room) = {
+        let mut shared = shared.borrow_mut();
+        match &message {
+            MultiplayerMessage::Welcome { .. } => {
+                shared.welcomed = true;
+                for pending in std::mem::take(&mut shared.pending) {
+                    let _ = shared.send(pending);
+                }
+            }
+            MultiplayerMessage::Ping { nonce } => {
+                let _ = shared.send(MultiplayerMessage::Pong { nonce: *nonce });
+            }
+            _ => {}
+        }
+        let changed = match &mut shared.room {
+            Some(room) => {
+                room.apply(&message);
+                !matches!(message, MultiplayerMessage::Ping { .. } | MultiplayerMessage::Pong { .. })
+            }
+            None => {
+                shared.room = RoomView::joined(&message);
+                shared.room.is_some()
+            }
+        };
+        let room = if changed { shared.room.as_ref().map(RoomSnapshot::from) } else { None };
+        (shared.on_message.clone(), shared.on_room.clone(), room)
+    };
+    if let (Some(callback), Ok(value)) = (on_message, to_plain_value(&message)) {
+        let _ = callback.call1(&JsValue::NULL, &value);
+    }
+    if let (Some(callback), Some(room)) = (on_room, room) {
+        if let Ok(value) = to_plain_value(&room) {
+            let _ = callback.call1(&JsValue::NULL, &value);
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RoomSnapshot {
+    code: String,
+    you: PlayerId,
+    host: PlayerId,
+    players: Vec<PlayerSnapshot>,
+    text: Option<String>,
+    standings: Vec<Standing>,
+    notice: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PlayerSnapshot {
+    id: PlayerId,
+    name: String,
+    ready: bool,
+    progress: f32,
+    wpm: f32,
+    place: Option<usize>,
+    time: Option<f32>,
+    flags: Vec<Flag>,
+    disqualified: Option<String>,
+}
+
+impl From<&RoomView> for RoomSnapshot {
+    fn from(room: &RoomView) -> Self {
+        Self {
+            code: room.code.clone(),
+            you: room.player_id,
+            host: room.host,
+            players: room
+                .racers
+                .iter()
+                .map(|racer| PlayerSnapshot {
+                    id: racer.info.id,
+                    name: racer.info.name.clone(),
+                    ready: racer.info.ready,
+                    progress: racer.progress,
+                    wpm: racer.wpm,
+                    place: racer.finish.map(|(place, _)| place),
+                    time: racer.finish.map(|(_, time)| time),
+                    flags: racer.flags.clone(),
+                    disqualified: racer.disqualified.clone(),
+                })
+                .collect(),
+            text: room.text.clone(),
+            standings: room.standings.clone(),
+            notice: room.notice.clone(),
+        }
+    }
+}
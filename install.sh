@@ -36,7 +36,8 @@ if ! command -v cargo &> /dev/null; then
     exit 1
 fi
 
-# Try to generate texts.json from packs if npm is available
+# Try to generate texts.json (used by the web client) from packs if npm is available.
+# The TUI reads data/packs and the user data directory at runtime.
 if command -v npm &> /dev/null; then
     print_status "Installing Node.js dependencies (for dataset scripts)..."
     npm install || print_warning "npm install failed; continuing without merging packs"
@@ -46,7 +47,7 @@ if command -v npm &> /dev/null; then
         print_warning "merge-packs script failed or missing; using existing texts.json"
     fi
 else
-    print_warning "npm not found; skipping dataset pack merge. Ensure texts.json exists at repo root."
+    print_warning "npm not found; skipping dataset pack merge (only needed for the web client)."
 fi
 
 # Build the TUI version
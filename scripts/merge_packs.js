@@ -1,7 +1,8 @@
 #!/usr/bin/env node
 /*
   Merge all JSON packs from data/packs/*.json into texts.json at repo root.
-  Each pack item: { category: string, content: string, attribution: string }
+  Each pack item: { category: string, content: string, attribution: string },
+  plus a language for code snippets.
 */
 const fs = require('fs');
 const path = require('path');
@@ -28,6 +29,7 @@ const OUTPUT = path.join(ROOT, 'texts.json');
           category: String(item.category || 'general'),
           content: String(item.content),
           attribution: String(item.attribution || path.relative(ROOT, file)),
+          ...(item.language ? { language: String(item.language) } : {}),
         });
       }
     }
@@ -293,6 +293,13 @@ function App() {
                 // Preserve WASM accuracy and mistakes instead of recalculating
                 stats.accuracy = accuracy;
                 stats.incorrectChars = mistakes;
+                // Final speeds come from the core so they match the terminal client
+                if (typeof game.get_metrics === 'function') {
+                    const metrics = game.get_metrics();
+                    stats.wpm = metrics.net_wpm;
+                    stats.rawWpm = metrics.raw_wpm;
+                    stats.time = metrics.elapsed;
+                }
                 newState.stats = stats;
             }
 
@@ -1,7 +1,44 @@
 declare module 'typerpunk' {
+    export interface KeyStat {
+        hits: number;
+        misses: number;
+        latency_ms: number;
+        timed: number;
+    }
+
+    export type Keystroke =
+        | { at_ms: number; kind: 'insert' | 'indent'; ch: string }
+        | { at_ms: number; kind: 'backspace' | 'delete_word'; removed: number };
+
+    export interface KeystrokeLog {
+        mode: string | Record<string, number>;
+        target: string;
+        events: Keystroke[];
+    }
+
+    export interface Metrics {
+        net_wpm: number;
+        raw_wpm: number;
+        cpm: number;
+        accuracy: number;
+        consistency: number;
+        burst: number;
+        errors: number;
+        elapsed: number;
+    }
+
     export class TyperPunkGame {
         free(): void;
         set_text(text: string): void;
+        set_words_mode(count: number): void;
+        set_zen_mode(): void;
+        finish(): void;
+        get_word_count(): number;
+        get_words_committed(): number;
+        get_word_results(): { expected: string; typed: string; correct: boolean }[];
+        get_key_stats(): { keys: Record<string, KeyStat>; bigrams: Record<string, KeyStat> };
+        get_weakest_keys(min_attempts: number, limit: number): { key: string; accuracy: number; mean_latency_ms: number | null; attempts: number }[];
+        get_keystroke_log(): KeystrokeLog;
         get_text(): string;
         get_input(): string;
         start(): void;
@@ -17,11 +54,69 @@ declare module 'typerpunk' {
         get_accuracy(): number;
         get_time_elapsed(): number;
         get_raw_wpm(): number;
+        get_metrics(): Metrics;
+        get_timeline(): { raw_wpm: number; net_wpm: number; errors: number }[];
         can_backspace(): boolean;
         can_ctrl_backspace(): boolean;
         handle_backspace(ctrl: boolean): boolean;
         get_total_mistakes(): number;
     }
 
+    export interface RacePlayer {
+        id: number;
+        name: string;
+        ready: boolean;
+        progress: number;
+        wpm: number;
+        place: number | null;
+        time: number | null;
+        flags: RaceFlag[];
+        disqualified: string | null;
+    }
+
+    /** Something the server found unusual about a finished run. */
+    export type RaceFlag = "near_speed_limit" | "uniform_rhythm" | "flawless_at_speed";
+
+    export interface RaceStanding {
+        player_id: number;
+        name: string;
+        place: number | null;
+        progress: number;
+        wpm: number;
+        time: number | null;
+        flags?: RaceFlag[];
+        disqualified?: string;
+    }
+
+    export interface RaceRoom {
+        code: string;
+        you: number;
+        host: number;
+        players: RacePlayer[];
+        text: string | null;
+        standings: RaceStanding[];
+        notice: string | null;
+    }
+
+    /** A protocol message as sent on the wire, tagged by `type`. */
+    export type RaceMessage = { type: string } & Record<string, unknown>;
+
+    export class RaceClient {
+        constructor(url: string);
+        free(): void;
+        on_message(callback: (message: RaceMessage) => void): void;
+        on_room(callback: (room: RaceRoom) => void): void;
+        on_close(callback: (reason: string) => void): void;
+        room(): RaceRoom | null;
+        create_room(name: string): void;
+        join_room(room: string, name: string): void;
+        leave_room(): void;
+        set_ready(ready: boolean): void;
+        start_race(text: string): void;
+        send_progress(progress: number, wpm: number): void;
+        send_finish(log: KeystrokeLog): void;
+        close(): void;
+    }
+
     export default function init(): Promise<void>;
 } 
\ No newline at end of file